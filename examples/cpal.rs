@@ -12,9 +12,6 @@
 
 // This example shows how to play a wav file using the cpal crate.
 
-extern crate hound;
-extern crate cpal;
-
 use std::env;
 use std::thread;
 
@@ -36,7 +33,7 @@ fn main() {
     // A voice in cpal is used for playback.
     let mut voice = cpal::Voice::new(&endpoint, &format).unwrap();
 
-    let mut samples_left = reader.len() as usize;
+    let mut samples_left = reader.num_samples() as usize;
 
     let mut append_data = |voice: &mut cpal::Voice| {
         match voice.append_data(samples_left) {
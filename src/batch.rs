@@ -0,0 +1,219 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion of many files in parallel.
+//!
+//! `convert()` converts a list of files to one sample format, on the rayon
+//! thread pool. Every file is converted through `WavReader::into_pcm_read()`
+//! and `WavWriter::into_pcm_write()`, so the samples are converted as
+//! described there. A file that fails does not stop the others, the result
+//! of every file is reported separately.
+//!
+//! This module is only available with the `batch` feature.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use crate::pcm::PcmFormat;
+use crate::read::WavReader;
+use crate::write::WavWriter;
+use super::{Error, Result, SampleFormat, WavSpec};
+
+/// The outcome of converting one file.
+#[derive(Debug)]
+pub struct Conversion {
+    /// The file that was converted.
+    pub input: PathBuf,
+    /// The file that was written, in the output directory.
+    pub output: PathBuf,
+    /// Whether the conversion succeeded.
+    ///
+    /// If it failed, the output file has been removed.
+    pub result: Result<()>,
+}
+
+/// Converts the files to the sample format of `target_spec`, in parallel.
+///
+/// Every file is written to `output_dir` under the name of the input file,
+/// replacing a file that exists there. The output has the bits per sample
+/// and sample format of `target_spec`. Conversion does not resample or remix,
+/// so a file whose sample rate or number of channels differs from
+/// `target_spec` fails with `Error::Unsupported`.
+///
+/// Before any file is converted, the outputs are checked. A file whose output
+/// would be one of the inputs, or whose file name is shared with another
+/// input, is not converted, and fails with an error of kind `InvalidInput`.
+///
+/// The results are returned in the order of `inputs`.
+pub fn convert<P>(inputs: &[P], output_dir: &Path, target_spec: WavSpec) -> Vec<Conversion>
+    where P: AsRef<Path> + Sync
+{
+    let outputs = plan_outputs(inputs, output_dir);
+    inputs.par_iter().zip(outputs).map(|(input, (output, planned))| {
+        let input = input.as_ref();
+        let result = planned.and_then(|()| convert_file(input, &output, target_spec));
+        Conversion {
+            input: input.to_path_buf(),
+            output,
+            result,
+        }
+    }).collect()
+}
+
+/// Returns the output path of every input, and whether it may be written.
+///
+/// Outputs must not overwrite an input, which could still be being read,
+/// and no two inputs may write to the same output.
+fn plan_outputs<P: AsRef<Path>>(inputs: &[P], output_dir: &Path) -> Vec<(PathBuf, Result<()>)> {
+    let invalid = |message| Err(Error::IoError(io::Error::new(io::ErrorKind::InvalidInput, message)));
+    // Inputs that do not exist fail when they are opened, they are not
+    // needed here.
+    let canonical_inputs: Vec<PathBuf> = inputs.iter()
+        .filter_map(|input| fs::canonicalize(input).ok())
+        .collect();
+    let outputs: Vec<Option<PathBuf>> = inputs.iter()
+        .map(|input| input.as_ref().file_name().map(|name| output_dir.join(name)))
+        .collect();
+    outputs.iter().map(|output| {
+        let output = match *output {
+            Some(ref output) => output,
+            None => return (output_dir.to_path_buf(), invalid("path has no file name")),
+        };
+        // The output file does not exist yet in the common case, then the
+        // directory is resolved instead.
+        let canonical_output = fs::canonicalize(output).ok().or_else(|| {
+            let dir = fs::canonicalize(output_dir).ok()?;
+            Some(dir.join(output.file_name()?))
+        });
+        let result = if outputs.iter().filter(|o| o.as_ref() == Some(output)).count() > 1 {
+            invalid("several inputs have the same file name")
+        } else if canonical_output.map_or(false, |o| canonical_inputs.contains(&o)) {
+            invalid("output would overwrite an input")
+        } else {
+            Ok(())
+        };
+        (output.clone(), result)
+    }).collect()
+}
+
+/// Returns the format to pass samples in from reader to writer.
+///
+/// When the target format is one of the PCM formats, samples are converted
+/// to it directly, so floats are rounded only once.
+fn intermediate_format(spec: WavSpec) -> PcmFormat {
+    match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Float, _) => PcmFormat::F32Le,
+        (SampleFormat::Int, 8) => PcmFormat::U8,
+        (SampleFormat::Int, 16) => PcmFormat::S16Le,
+        (SampleFormat::Int, 24) => PcmFormat::S24Le,
+        (SampleFormat::Int, _) => PcmFormat::S32Le,
+    }
+}
+
+fn convert_file(input: &Path, output: &Path, target_spec: WavSpec) -> Result<()> {
+    let reader = WavReader::open(input)?;
+    let spec = reader.spec();
+    if spec.channels != target_spec.channels || spec.sample_rate != target_spec.sample_rate {
+        return Err(Error::Unsupported);
+    }
+    let format = intermediate_format(target_spec);
+    let result = WavWriter::create(output, target_spec).and_then(|writer| {
+        let mut pcm_write = writer.into_pcm_write(format);
+        io::copy(&mut reader.into_pcm_read(format), &mut pcm_write)?;
+        pcm_write.finalize()
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+#[test]
+fn convert_converts_every_file_and_reports_failures() {
+    use std::env;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("hound-batch-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let inputs = [
+        "testsamples/pcmwaveformat-16bit-44100Hz-mono.wav",
+        "testsamples/waveformatex-16bit-44100Hz-stereo.wav",
+        "testsamples/nonexistent.wav",
+    ];
+    let target_spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let conversions = convert(&inputs, &dir, target_spec);
+
+    assert_eq!(conversions.len(), 3);
+    assert_eq!(conversions[0].output, dir.join("pcmwaveformat-16bit-44100Hz-mono.wav"));
+    conversions[0].result.as_ref().unwrap();
+    let mut original = WavReader::open(inputs[0]).unwrap();
+    let mut converted = WavReader::open(&conversions[0].output).unwrap();
+    assert_eq!(converted.spec(), target_spec);
+    let expected: Vec<i32> = original.samples::<i16>().map(|s| (s.unwrap() as i32) << 8).collect();
+    let actual: Vec<i32> = converted.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(actual, expected);
+
+    match conversions[1].result {
+        Err(Error::Unsupported) => {}
+        ref other => panic!("expected Unsupported, got {:?}", other),
+    }
+    assert!(!conversions[1].output.exists());
+    match conversions[2].result {
+        Err(Error::IoError(ref err)) if err.kind() == io::ErrorKind::NotFound => {}
+        ref other => panic!("expected NotFound, got {:?}", other),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn convert_rejects_outputs_that_overwrite_inputs_or_each_other() {
+    use std::env;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("hound-batch-clash-{}", process::id()));
+    let other = dir.join("other");
+    fs::create_dir_all(&other).unwrap();
+    let source = fs::read("testsamples/pcmwaveformat-16bit-44100Hz-mono.wav").unwrap();
+    let inputs = [dir.join("a.wav"), dir.join("b.wav"), other.join("b.wav"), other.join("c.wav")];
+    for input in &inputs {
+        fs::write(input, &source).unwrap();
+    }
+    let target_spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let conversions = convert(&inputs, &dir, target_spec);
+
+    let is_invalid_input = |result: &Result<()>| match *result {
+        Err(Error::IoError(ref err)) => err.kind() == io::ErrorKind::InvalidInput,
+        _ => false,
+    };
+    // The output of `a.wav` is the input itself, and the two `b.wav` would
+    // write to the same output.
+    assert!(is_invalid_input(&conversions[0].result));
+    assert!(is_invalid_input(&conversions[1].result));
+    assert!(is_invalid_input(&conversions[2].result));
+    conversions[3].result.as_ref().unwrap();
+    for input in &inputs {
+        assert_eq!(fs::read(input).unwrap(), source);
+    }
+    assert_eq!(WavReader::open(dir.join("c.wav")).unwrap().spec(), target_spec);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,490 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The building blocks of a RIFF file: chunks, and the layout of the fmt chunk.
+//!
+//! `RiffReader` and `RiffWriter` handle the chunk structure of RIFF files of
+//! any form type, without interpreting the chunks.
+
+use std::cmp;
+use std::io;
+use std::io::Write;
+use crate::codec::swap_u32;
+use crate::read::ReadExt;
+use crate::write::WriteExt;
+use super::{Endianness, Error, Result};
+
+/// The different chunks that a WAVE file can contain.
+pub(crate) enum ChunkKind {
+    Fmt,
+    Fact,
+    Data,
+    Unknown,
+}
+
+/// Describes the structure of a chunk in the WAVE file.
+pub(crate) struct ChunkHeader {
+    pub id: [u8; 4],
+    pub kind: ChunkKind,
+    pub len: u32,
+}
+
+/// Describes the location of a chunk in a RIFF file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// The four-character chunk identifier, such as `b"fmt "` or `b"data"`.
+    pub id: [u8; 4],
+
+    /// The offset of the chunk header from the start of the RIFF file.
+    ///
+    /// The contents of the chunk start 8 bytes after this offset.
+    pub offset: u64,
+
+    /// The length of the contents of the chunk in bytes, as stated in its header.
+    ///
+    /// This excludes the chunk header, and the padding byte that follows
+    /// chunks of odd length.
+    pub len: u32,
+}
+
+// The WAVEFORMATEXTENSIBLE struct can contain several subformats.
+// These are identified by a GUID. The various GUIDS can be found in the file
+// mmreg.h that is part of the Windows SDK. The following GUIDS are defined:
+// - PCM:        00000001-0000-0010-8000-00aa00389b71
+// - IEEE_FLOAT: 00000003-0000-0010-8000-00aa00389b71
+// When written to a wav file, the byte order of a GUID is native for the first
+// three sections, which is assumed to be little endian, and big endian for the
+// last 8-byte section (which does contain a hyphen, for reasons unknown to me).
+
+/// Subformat type for PCM audio with integer samples.
+pub(crate) const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80,
+                                            0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+/// Subformat type for IEEE_FLOAT audio with float samples.
+pub(crate) const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: [u8; 16] = [0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+                                                   0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+// The ambisonic B-Format (.amb) subformats are not part of the Windows SDK,
+// they are defined at http://www.ambisonia.com/Members/mleese/file-format-for-b-format.
+// - PCM:        00000001-0721-11d3-8644-c8c1ca000000
+// - IEEE_FLOAT: 00000003-0721-11d3-8644-c8c1ca000000
+
+/// Subformat type for ambisonic B-Format audio with integer samples.
+pub(crate) const SUBTYPE_AMBISONIC_B_FORMAT_PCM: [u8; 16] = [0x01, 0x00, 0x00, 0x00, 0x21, 0x07, 0xd3, 0x11,
+                                                  0x86, 0x44, 0xc8, 0xc1, 0xca, 0x00, 0x00, 0x00];
+
+/// Subformat type for ambisonic B-Format audio with float samples.
+pub(crate) const SUBTYPE_AMBISONIC_B_FORMAT_IEEE_FLOAT: [u8; 16] = [0x03, 0x00, 0x00, 0x00, 0x21, 0x07, 0xd3,
+                                                         0x11, 0x86, 0x44, 0xc8, 0xc1, 0xca, 0x00,
+                                                         0x00, 0x00];
+
+/// The offsets and sizes of the multi-byte fields of a `WAVEFORMATEX` struct.
+const FMT_FIELDS: [(usize, usize); 7] = [
+    (0, 2), (2, 2), (4, 4), (8, 4), (12, 2), (14, 2), (16, 2),
+];
+
+/// The offsets and sizes of the fields that `WAVEFORMATEXTENSIBLE` adds.
+///
+/// The first three sections of the `SubFormat` GUID are integers too.
+const FMT_EXTENSIBLE_FIELDS: [(usize, usize); 5] = [
+    (18, 2), (20, 4), (24, 4), (28, 2), (30, 2),
+];
+
+/// Reverses the byte order of the fields of a fmt chunk.
+///
+/// In a RIFX file, the fields of the fmt chunk are big-endian. Hound parses
+/// and writes fmt chunks in the little-endian layout, and converts them with
+/// this function, in either direction. Fields beyond the end of the chunk
+/// are left out, and extra data of other formats is left untouched.
+pub(crate) fn swap_fmt_fields(fmt: &mut [u8]) {
+    // WAVE_FORMAT_EXTENSIBLE is 0xfffe, in either byte order.
+    let extensible = fmt.len() >= 2 && (fmt[..2] == [0xfe, 0xff] || fmt[..2] == [0xff, 0xfe]);
+    let extra: &[(usize, usize)] = if extensible { &FMT_EXTENSIBLE_FIELDS } else { &[] };
+    for &(offset, size) in FMT_FIELDS.iter().chain(extra) {
+        if offset + size <= fmt.len() {
+            fmt[offset..offset + size].reverse();
+        }
+    }
+}
+
+/// A writer for RIFF files of any form type, that fills in the chunk lengths.
+///
+/// `WavWriter` writes the WAVE form. `RiffWriter` writes the RIFF structure
+/// only, so it can be used to author related formats, such as custom sound
+/// bank formats, or WAVE files with a layout that `WavWriter` does not offer.
+/// Chunks are opened with `begin_chunk()` or `begin_list()`, their contents
+/// are written through the `io::Write` implementation, and `end_chunk()`
+/// fills in the length of the chunk and adds the padding byte if the length
+/// is odd. Chunks can be nested in `LIST` chunks.
+///
+/// ```
+/// use std::io::{Cursor, Write};
+/// use hound::RiffWriter;
+///
+/// let mut riff = RiffWriter::new(Cursor::new(Vec::new()), b"TEST").unwrap();
+/// riff.begin_list(b"INFO").unwrap();
+/// riff.write_chunk(b"INAM", b"Take 1\0").unwrap();
+/// riff.end_chunk().unwrap();
+/// let bytes = riff.finish().unwrap().into_inner();
+/// assert_eq!(&bytes[..12], b"RIFF\x20\x00\x00\x00TEST");
+/// ```
+///
+/// The lengths are filled in by seeking back, the writer is left at the end
+/// of the form. `finish()` must be called to end the form, the length of the
+/// RIFF header is not filled in otherwise.
+pub struct RiffWriter<W: io::Write + io::Seek> {
+    writer: W,
+    /// The position of the writer.
+    position: u64,
+    /// The offsets of the headers of the open chunks, starting with the RIFF header.
+    open_chunks: Vec<u64>,
+}
+
+impl<W: io::Write + io::Seek> RiffWriter<W> {
+    /// Starts a RIFF form of the given type, such as `b"WAVE"`, at the current position of the writer.
+    pub fn new(mut writer: W, form_type: &[u8; 4]) -> Result<RiffWriter<W>> {
+        let start = writer.stream_position()?;
+        let mut riff_writer = RiffWriter {
+            writer,
+            position: start,
+            open_chunks: Vec::new(),
+        };
+        riff_writer.begin_chunk(b"RIFF")?;
+        riff_writer.write_all(form_type)?;
+        Ok(riff_writer)
+    }
+
+    /// Starts a chunk, subsequent writes go into it until `end_chunk()`.
+    pub fn begin_chunk(&mut self, id: &[u8; 4]) -> Result<()> {
+        self.open_chunks.push(self.position);
+        self.write_all(id)?;
+        // The length is filled in when the chunk ends.
+        self.write_le_u32(0)?;
+        Ok(())
+    }
+
+    /// Starts a `LIST` chunk of the given list type, such as `b"INFO"`.
+    ///
+    /// Chunks that are written until the matching `end_chunk()` are nested
+    /// in the list.
+    pub fn begin_list(&mut self, list_type: &[u8; 4]) -> Result<()> {
+        self.begin_chunk(b"LIST")?;
+        self.write_all(list_type)?;
+        Ok(())
+    }
+
+    /// Ends the innermost chunk, fills in its length, and pads it to an even length.
+    ///
+    /// Returns `Error::TooLarge` if the contents exceed 4 GiB. This function
+    /// panics if no chunk was started, the RIFF form is ended by `finish()`.
+    pub fn end_chunk(&mut self) -> Result<()> {
+        assert!(self.open_chunks.len() > 1, "end_chunk called without an open chunk.");
+        self.end_innermost()
+    }
+
+    /// Writes a complete chunk with the given contents.
+    pub fn write_chunk(&mut self, id: &[u8; 4], data: &[u8]) -> Result<()> {
+        self.begin_chunk(id)?;
+        self.write_all(data)?;
+        self.end_chunk()
+    }
+
+    /// Returns the offset of the writer from the start of the RIFF header.
+    pub fn position(&self) -> u64 {
+        self.position - self.open_chunks[0]
+    }
+
+    /// Ends the innermost open chunk, which may be the RIFF form itself.
+    fn end_innermost(&mut self) -> Result<()> {
+        let start = self.open_chunks.pop().expect("the RIFF form is open");
+        let len = self.position - start - 8;
+        if len > 0xffff_ffff {
+            return Err(Error::TooLarge);
+        }
+        self.writer.seek(io::SeekFrom::Start(start + 4))?;
+        self.writer.write_le_u32(len as u32)?;
+        self.writer.seek(io::SeekFrom::Start(self.position))?;
+        if len % 2 == 1 {
+            self.write_u8(0)?;
+        }
+        Ok(())
+    }
+
+    /// Ends the chunks that are still open and the RIFF form, returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        while !self.open_chunks.is_empty() {
+            self.end_innermost()?;
+        }
+        Ok(self.writer)
+    }
+}
+
+impl<W: io::Write + io::Seek> io::Write for RiffWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A chunk found by `RiffReader::next_chunk()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RiffChunk {
+    /// The location of the chunk, the offset is relative to the RIFF header.
+    pub info: ChunkInfo,
+
+    /// The list type, such as `b"INFO"`, if the chunk is a `LIST` chunk.
+    ///
+    /// The chunks in the list are returned after the list itself.
+    pub list_type: Option<[u8; 4]>,
+
+    /// The number of `LIST` chunks that contain the chunk, 0 at the top level of the form.
+    pub depth: usize,
+}
+
+/// A reader that iterates over the chunks of a RIFF file of any form type.
+///
+/// `WavReader` interprets the WAVE form. `RiffReader` only knows the RIFF
+/// structure, so it can inspect the chunks of any RIFF or RIFX file, such
+/// as all metadata of a WAVE file, or a format that Hound does not know.
+/// `next_chunk()` returns the chunks in file order, and descends into `LIST`
+/// chunks. The contents of the current chunk can be read through the
+/// `io::Read` implementation. Contents that are not read are skipped.
+///
+/// ```
+/// use hound::RiffReader;
+///
+/// let file = std::fs::File::open("testsamples/pop.wav").unwrap();
+/// let mut riff = RiffReader::new(std::io::BufReader::new(file)).unwrap();
+/// assert_eq!(riff.form_type(), *b"WAVE");
+/// while let Some(chunk) = riff.next_chunk().unwrap() {
+///     println!("{} bytes of {}", chunk.info.len, String::from_utf8_lossy(&chunk.info.id));
+/// }
+/// ```
+///
+/// A length of 0 or 0xffffffff in the RIFF header, as written by streaming
+/// encoders, is taken to mean that the form extends to the end of the
+/// reader. A `LIST` chunk that claims to extend beyond its parent ends with
+/// its parent.
+pub struct RiffReader<R> {
+    reader: R,
+    endianness: Endianness,
+    form_type: [u8; 4],
+    riff_len: u32,
+    /// The position of the reader, relative to the RIFF header.
+    position: u64,
+    /// The end of the contents of the current chunk.
+    chunk_end: u64,
+    /// Whether the current chunk is followed by a padding byte.
+    chunk_padded: bool,
+    /// The ends of the form and of the lists that are being iterated, innermost last.
+    list_ends: Vec<u64>,
+}
+
+impl<R: io::Read> RiffReader<R> {
+    /// Reads the RIFF or RIFX header at the current position of the reader.
+    ///
+    /// Returns `Error::FormatError` if the reader does not start with a RIFF
+    /// header.
+    pub fn new(mut reader: R) -> Result<RiffReader<R>> {
+        let mut header = [0u8; 12];
+        reader.read_into(&mut header)?;
+        let endianness = match &header[..4] {
+            b"RIFF" => Endianness::Little,
+            b"RIFX" => Endianness::Big,
+            _ => return Err(Error::FormatError("no RIFF tag found")),
+        };
+        let riff_len = swap_u32(u32::from_le_bytes([header[4], header[5], header[6], header[7]]), endianness);
+        let mut form_type = [0u8; 4];
+        form_type.copy_from_slice(&header[8..]);
+        let end = match riff_len {
+            0 | 0xffff_ffff => u64::MAX,
+            len => 8 + len as u64,
+        };
+        Ok(RiffReader {
+            reader,
+            endianness,
+            form_type,
+            riff_len,
+            position: 12,
+            chunk_end: 12,
+            chunk_padded: false,
+            list_ends: vec![end],
+        })
+    }
+
+    /// Returns the form type, such as `b"WAVE"`.
+    pub fn form_type(&self) -> [u8; 4] {
+        self.form_type
+    }
+
+    /// Returns the byte order of the lengths: big-endian for a RIFX file, little-endian otherwise.
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Returns the length of the form as stated in the RIFF header.
+    pub fn riff_len(&self) -> u32 {
+        self.riff_len
+    }
+
+    /// Skips the rest of the current chunk, and reads the header of the next one.
+    ///
+    /// Returns `None` at the end of the form, or at the end of the reader if
+    /// that comes first.
+    pub fn next_chunk(&mut self) -> Result<Option<RiffChunk>> {
+        if self.position < self.chunk_end {
+            self.reader.skip_bytes((self.chunk_end - self.position) as usize)?;
+            self.position = self.chunk_end;
+        }
+        if self.chunk_padded {
+            // Some writers omit the padding byte after the last chunk.
+            self.chunk_padded = false;
+            if self.reader.read(&mut [0u8; 1])? == 0 {
+                return Ok(None);
+            }
+            self.position += 1;
+        }
+
+        // Leave the lists that end here, but never the form itself.
+        while self.list_ends.len() > 1 && self.position + 8 > self.list_ends[self.list_ends.len() - 1] {
+            self.list_ends.pop();
+        }
+        if self.position + 8 > self.list_ends[0] {
+            return Ok(None);
+        }
+
+        let mut header = [0u8; 8];
+        let mut progress = 0;
+        while progress < 8 {
+            match self.reader.read(&mut header[progress..]) {
+                Ok(0) if progress == 0 => return Ok(None),
+                Ok(0) => return Err(Error::IoError(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                                  "chunk header ends early"))),
+                Ok(n) => progress += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let mut id = [0u8; 4];
+        id.copy_from_slice(&header[..4]);
+        let len = swap_u32(u32::from_le_bytes([header[4], header[5], header[6], header[7]]), self.endianness);
+        let offset = self.position;
+        let depth = self.list_ends.len() - 1;
+        self.position += 8;
+        self.chunk_end = self.position + len as u64;
+        self.chunk_padded = len % 2 == 1;
+
+        let mut list_type = None;
+        if &id == b"LIST" && len >= 4 {
+            let mut ty = [0u8; 4];
+            self.reader.read_into(&mut ty)?;
+            self.position += 4;
+            list_type = Some(ty);
+            // The chunks in the list are iterated next.
+            let parent_end = self.list_ends[self.list_ends.len() - 1];
+            self.list_ends.push(cmp::min(self.chunk_end, parent_end));
+            self.chunk_end = self.position;
+        }
+
+        Ok(Some(RiffChunk {
+            info: ChunkInfo {
+                id,
+                offset,
+                len,
+            },
+            list_type,
+            depth,
+        }))
+    }
+
+    /// Destroys the reader, returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: io::Read> io::Read for RiffReader<R> {
+    /// Reads the contents of the current chunk.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.chunk_end - self.position;
+        let n = cmp::min(left, buf.len() as u64) as usize;
+        let n = self.reader.read(&mut buf[..n])?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+#[test]
+fn riff_writer_writes_a_wave_file_that_can_be_read() {
+    use crate::read::WavReader;
+
+    // The form need not start at the start of the writer.
+    let mut cursor = io::Cursor::new(b"junk".to_vec());
+    cursor.set_position(4);
+    let mut riff = RiffWriter::new(cursor, b"WAVE").unwrap();
+    riff.write_chunk(b"fmt ", &[1, 0, 1, 0, 0x40, 0x1f, 0, 0, 0x40, 0x1f, 0, 0, 1, 0, 8, 0]).unwrap();
+    riff.begin_list(b"INFO").unwrap();
+    riff.write_chunk(b"ICMT", b"odd").unwrap();
+    assert_eq!(riff.position(), 12 + 24 + 12 + 12);
+    riff.end_chunk().unwrap();
+    riff.begin_chunk(b"data").unwrap();
+    riff.write_all(&[0x81, 0x7f, 0x80]).unwrap();
+    let bytes = riff.finish().unwrap().into_inner();
+
+    assert_eq!(bytes.len(), 4 + 12 + 24 + 24 + 12);
+    assert_eq!(&bytes[4..16], b"RIFF\x40\x00\x00\x00WAVE");
+    assert_eq!(&bytes[40..64], b"LIST\x10\x00\x00\x00INFOICMT\x03\x00\x00\x00odd\x00");
+    let mut reader = WavReader::new(&bytes[4..]).unwrap();
+    assert_eq!(reader.samples::<i8>().map(|s| s.unwrap()).collect::<Vec<_>>(), vec![1, -1, 0]);
+}
+
+#[test]
+fn riff_reader_iterates_nested_lists() {
+    use std::io::Read;
+
+    let mut riff = RiffWriter::new(io::Cursor::new(Vec::new()), b"TEST").unwrap();
+    riff.write_chunk(b"abcd", b"odd").unwrap();
+    riff.begin_list(b"outr").unwrap();
+    riff.begin_list(b"innr").unwrap();
+    riff.write_chunk(b"leaf", b"leaf contents").unwrap();
+    riff.end_chunk().unwrap();
+    riff.write_chunk(b"next", b"").unwrap();
+    riff.end_chunk().unwrap();
+    riff.write_chunk(b"last", b"xy").unwrap();
+    let bytes = riff.finish().unwrap().into_inner();
+
+    let mut reader = RiffReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.form_type(), *b"TEST");
+    assert_eq!(reader.riff_len() as usize, bytes.len() - 8);
+    let mut chunks = Vec::new();
+    while let Some(chunk) = reader.next_chunk().unwrap() {
+        if &chunk.info.id == b"leaf" {
+            let mut contents = String::new();
+            reader.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "leaf contents");
+        }
+        chunks.push((chunk.info.id, chunk.info.offset, chunk.list_type, chunk.depth));
+    }
+    assert_eq!(chunks, vec![
+        (*b"abcd", 12, None, 0),
+        (*b"LIST", 24, Some(*b"outr"), 0),
+        (*b"LIST", 36, Some(*b"innr"), 1),
+        (*b"leaf", 48, None, 2),
+        (*b"next", 70, None, 1),
+        (*b"last", 78, None, 0),
+    ]);
+}
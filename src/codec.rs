@@ -0,0 +1,363 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encoding and decoding of samples in raw byte buffers.
+//!
+//! These are the routines that `WavReader` and `WavWriter` use to pack
+//! samples, exposed for code that does its own IO, such as reading the data
+//! chunk through a memory map. The byte layout is that of a WAVE data chunk:
+//! little-endian, with 8-bit samples unsigned and 24-bit samples packed in
+//! three bytes.
+//!
+//! Every function converts as many samples as fit in both the source and the
+//! destination, and returns that number. Trailing bytes of an incomplete
+//! sample are ignored. Compressed formats such as ADPCM are not supported.
+//!
+//! RIFX files store samples and header fields big-endian. The byte order
+//! functions at the end of this module convert between the byte orders; they
+//! are what `WavReader` and `WavWriter` use for RIFX files. A big-endian data
+//! chunk can be decoded by converting it to little-endian in place first,
+//! and 16-bit samples have dedicated big-endian functions.
+//!
+//! ```
+//! use hound::codec;
+//!
+//! let mut samples = [0_i16; 2];
+//! assert_eq!(codec::decode_i16_le(&[2, 0, 253, 255], &mut samples), 2);
+//! assert_eq!(samples, [2, -3]);
+//! ```
+
+use crate::convert::{signed_from_u8, u8_from_signed};
+use crate::error::Result;
+use super::{read_int_sample, write_int_sample, Endianness};
+
+/// Decodes unsigned 8-bit samples.
+pub fn decode_u8(src: &[u8], dst: &mut [i8]) -> usize {
+    for (x, &byte) in dst.iter_mut().zip(src) {
+        *x = signed_from_u8(byte);
+    }
+    src.len().min(dst.len())
+}
+
+/// Encodes samples as unsigned 8-bit samples.
+pub fn encode_u8(src: &[i8], dst: &mut [u8]) -> usize {
+    for (byte, &x) in dst.iter_mut().zip(src) {
+        *byte = u8_from_signed(x);
+    }
+    src.len().min(dst.len())
+}
+
+/// Decodes 16-bit little-endian samples.
+pub fn decode_i16_le(src: &[u8], dst: &mut [i16]) -> usize {
+    for (x, bytes) in dst.iter_mut().zip(src.chunks_exact(2)) {
+        *x = i16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    (src.len() / 2).min(dst.len())
+}
+
+/// Encodes samples as 16-bit little-endian samples.
+pub fn encode_i16_le(src: &[i16], dst: &mut [u8]) -> usize {
+    for (bytes, &x) in dst.chunks_exact_mut(2).zip(src) {
+        bytes.copy_from_slice(&x.to_le_bytes());
+    }
+    src.len().min(dst.len() / 2)
+}
+
+/// Decodes 16-bit big-endian samples.
+pub fn decode_i16_be(src: &[u8], dst: &mut [i16]) -> usize {
+    for (x, bytes) in dst.iter_mut().zip(src.chunks_exact(2)) {
+        *x = i16::from_be_bytes([bytes[0], bytes[1]]);
+    }
+    (src.len() / 2).min(dst.len())
+}
+
+/// Encodes samples as 16-bit big-endian samples.
+pub fn encode_i16_be(src: &[i16], dst: &mut [u8]) -> usize {
+    for (bytes, &x) in dst.chunks_exact_mut(2).zip(src) {
+        bytes.copy_from_slice(&x.to_be_bytes());
+    }
+    src.len().min(dst.len() / 2)
+}
+
+/// Reinterprets a buffer of 16-bit little-endian samples without copying.
+///
+/// Returns `None` if the buffer is not aligned for `i16`, if its length is
+/// odd, or if the target is big-endian. Use `decode_i16_le()` in that case.
+pub fn as_i16_le(src: &[u8]) -> Option<&[i16]> {
+    if cfg!(target_endian = "big") || src.len() % 2 != 0 {
+        return None;
+    }
+    // Every bit pattern is a valid `i16`, so the cast is sound; `align_to`
+    // puts any unaligned bytes in the prefix.
+    let (prefix, samples, suffix) = unsafe { src.align_to::<i16>() };
+    if prefix.is_empty() && suffix.is_empty() {
+        Some(samples)
+    } else {
+        None
+    }
+}
+
+/// Decodes 24-bit little-endian samples, packed in three bytes.
+pub fn decode_i24_le(src: &[u8], dst: &mut [i32]) -> usize {
+    for (x, bytes) in dst.iter_mut().zip(src.chunks_exact(3)) {
+        // Load into the high bytes, so the shift extends the sign.
+        *x = i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8;
+    }
+    (src.len() / 3).min(dst.len())
+}
+
+/// Encodes samples as 24-bit little-endian samples, packed in three bytes.
+///
+/// Only the low 24 bits of every sample are stored.
+pub fn encode_i24_le(src: &[i32], dst: &mut [u8]) -> usize {
+    for (bytes, &x) in dst.chunks_exact_mut(3).zip(src) {
+        bytes.copy_from_slice(&x.to_le_bytes()[..3]);
+    }
+    src.len().min(dst.len() / 3)
+}
+
+/// Decodes 32-bit little-endian samples.
+pub fn decode_i32_le(src: &[u8], dst: &mut [i32]) -> usize {
+    for (x, bytes) in dst.iter_mut().zip(src.chunks_exact(4)) {
+        *x = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    (src.len() / 4).min(dst.len())
+}
+
+/// Encodes samples as 32-bit little-endian samples.
+pub fn encode_i32_le(src: &[i32], dst: &mut [u8]) -> usize {
+    for (bytes, &x) in dst.chunks_exact_mut(4).zip(src) {
+        bytes.copy_from_slice(&x.to_le_bytes());
+    }
+    src.len().min(dst.len() / 4)
+}
+
+/// Decodes 32-bit little-endian IEEE float samples.
+///
+/// This function is only available with the `float` feature.
+#[cfg(feature = "float")]
+pub fn decode_f32_le(src: &[u8], dst: &mut [f32]) -> usize {
+    for (x, bytes) in dst.iter_mut().zip(src.chunks_exact(4)) {
+        *x = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    (src.len() / 4).min(dst.len())
+}
+
+/// Encodes samples as 32-bit little-endian IEEE float samples.
+///
+/// This function is only available with the `float` feature.
+#[cfg(feature = "float")]
+pub fn encode_f32_le(src: &[f32], dst: &mut [u8]) -> usize {
+    for (bytes, &x) in dst.chunks_exact_mut(4).zip(src) {
+        bytes.copy_from_slice(&x.to_le_bytes());
+    }
+    src.len().min(dst.len() / 4)
+}
+
+/// Decodes integer samples with `bits` valid bits in containers of `bytes` bytes.
+///
+/// This handles every integer layout that `WavReader` supports, such as 20
+/// bits in a 3-byte container. The padding bits are shifted out, as for
+/// `WavReader::samples()`. Returns `Error::Unsupported` for other layouts.
+pub fn decode_int(src: &[u8], dst: &mut [i32], bytes: u16, bits: u16) -> Result<usize> {
+    let mut reader = src;
+    let n = (src.len() / bytes.max(1) as usize).min(dst.len());
+    for x in &mut dst[..n] {
+        *x = read_int_sample(&mut reader, bytes, bits)?;
+    }
+    Ok(n)
+}
+
+/// Encodes integer samples with `bits` valid bits in containers of `bytes` bytes.
+///
+/// This is the inverse of `decode_int()`. Returns `Error::TooWide` if a
+/// sample does not fit in `bits` bits, in which case the samples before it
+/// have been encoded, and `Error::Unsupported` for unsupported layouts.
+pub fn encode_int(src: &[i32], dst: &mut [u8], bytes: u16, bits: u16) -> Result<usize> {
+    let n = src.len().min(dst.len() / bytes.max(1) as usize);
+    let mut writer = &mut dst[..];
+    for &x in &src[..n] {
+        write_int_sample(&mut writer, x, bits, bytes)?;
+    }
+    Ok(n)
+}
+
+/// Converts a 16-bit value between little-endian and the given byte order.
+///
+/// A field that was read with `ReadExt::read_le_u16()` from a file in the
+/// given byte order is converted to its value, and a value is converted to
+/// what `WriteExt::write_le_u16()` must write. The conversion is its own
+/// inverse.
+pub fn swap_u16(x: u16, endianness: Endianness) -> u16 {
+    match endianness {
+        Endianness::Little => x,
+        Endianness::Big => x.swap_bytes(),
+    }
+}
+
+/// Converts a 32-bit value between little-endian and the given byte order.
+///
+/// This is like `swap_u16()`, for fields such as chunk sizes.
+pub fn swap_u32(x: u32, endianness: Endianness) -> u32 {
+    match endianness {
+        Endianness::Little => x,
+        Endianness::Big => x.swap_bytes(),
+    }
+}
+
+/// Reverses the byte order of samples of `bytes_per_sample` bytes, in place.
+///
+/// This converts a big-endian data chunk to little-endian, and back.
+pub fn swap_sample_bytes(data: &mut [u8], bytes_per_sample: u16) -> usize {
+    if bytes_per_sample == 0 {
+        return 0;
+    }
+    for sample in data.chunks_exact_mut(bytes_per_sample as usize) {
+        sample.reverse();
+    }
+    data.len() / bytes_per_sample as usize
+}
+
+/// Converts samples of `bytes_per_sample` bytes from one byte order to another, in place.
+///
+/// The data is left untouched if the byte orders are the same.
+pub fn convert_byte_order(data: &mut [u8], bytes_per_sample: u16, from: Endianness, to: Endianness) -> usize {
+    if from == to {
+        data.len() / bytes_per_sample.max(1) as usize
+    } else {
+        swap_sample_bytes(data, bytes_per_sample)
+    }
+}
+
+#[test]
+fn fixed_width_codecs_roundtrip() {
+    let ints: Vec<i32> = vec![-8_388_608, -65_536, -1, 0, 1, 4660, 8_388_607];
+    let mut bytes = vec![0; 21];
+    assert_eq!(encode_i24_le(&ints, &mut bytes), 7);
+    assert_eq!(&bytes[..6], &[0x00, 0x00, 0x80, 0x00, 0x00, 0xff]);
+    let mut decoded = vec![0; 7];
+    assert_eq!(decode_i24_le(&bytes, &mut decoded), 7);
+    assert_eq!(decoded, ints);
+
+    let mut bytes = vec![0; 28];
+    encode_i32_le(&ints, &mut bytes);
+    decode_i32_le(&bytes, &mut decoded);
+    assert_eq!(decoded, ints);
+
+    let shorts: Vec<i16> = vec![-32768, -3, 0, 2, 32767];
+    let mut bytes = vec![0; 10];
+    encode_i16_le(&shorts, &mut bytes);
+    assert_eq!(&bytes[..4], &[0x00, 0x80, 253, 255]);
+    let mut decoded = vec![0; 5];
+    decode_i16_le(&bytes, &mut decoded);
+    assert_eq!(decoded, shorts);
+
+    let mut bytes = [0_u8; 3];
+    assert_eq!(encode_u8(&[-128, 0, 127], &mut bytes), 3);
+    assert_eq!(bytes, [0, 128, 255]);
+    let mut decoded = [0_i8; 3];
+    decode_u8(&bytes, &mut decoded);
+    assert_eq!(decoded, [-128, 0, 127]);
+
+    #[cfg(feature = "float")]
+    {
+        let floats = [-1.0, 0.25, 1.5];
+        let mut bytes = [0_u8; 12];
+        encode_f32_le(&floats, &mut bytes);
+        assert_eq!(&bytes[4..8], &[0, 0, 128, 62]);
+        let mut decoded = [0.0; 3];
+        decode_f32_le(&bytes, &mut decoded);
+        assert_eq!(decoded, floats);
+    }
+}
+
+#[test]
+fn codecs_stop_at_the_shorter_buffer() {
+    let mut decoded = [0_i16; 4];
+    assert_eq!(decode_i16_le(&[1, 0, 2, 0, 3], &mut decoded), 2);
+    assert_eq!(decoded, [1, 2, 0, 0]);
+    let mut decoded = [0_i32; 1];
+    assert_eq!(decode_i24_le(&[1, 0, 0, 2, 0, 0], &mut decoded), 1);
+    let mut bytes = [0_u8; 5];
+    assert_eq!(encode_i16_le(&[1, 2, 3], &mut bytes), 2);
+    assert_eq!(bytes, [1, 0, 2, 0, 0]);
+}
+
+#[test]
+fn decode_int_matches_reader() {
+    use crate::read::WavReader;
+    use crate::testutil::WavBuilder;
+
+    // 20 valid bits in a 3-byte container.
+    let data = [0x00, 0x00, 0x80, 0x10, 0x00, 0x00, 0xf0, 0xff, 0x7f];
+    let file = WavBuilder::new()
+        .fmt_extensible_pcm(1, 8000, 24, 20)
+        .chunk(b"data", &data)
+        .build();
+    let mut reader = WavReader::new(&file[..]).unwrap();
+    let expected: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+
+    let mut decoded = vec![0; 3];
+    assert_eq!(decode_int(&data, &mut decoded, 3, 20).unwrap(), 3);
+    assert_eq!(decoded, expected);
+
+    let mut bytes = vec![0; 9];
+    assert_eq!(encode_int(&decoded, &mut bytes, 3, 20).unwrap(), 3);
+    assert_eq!(&bytes[..], &data[..]);
+
+    assert!(encode_int(&[1 << 19], &mut bytes, 3, 20).is_err());
+    assert!(decode_int(&data, &mut decoded, 5, 20).is_err());
+}
+
+#[test]
+fn as_i16_le_borrows_aligned_buffers() {
+    let samples: Vec<i16> = vec![1, -2, 300];
+    let mut bytes = vec![0_u8; 6];
+    encode_i16_le(&samples, &mut bytes);
+    // Copy into an `i16` buffer to guarantee alignment.
+    let mut aligned = vec![0_i16; 3];
+    decode_i16_le(&bytes, &mut aligned);
+    let view: &[u8] = unsafe { std::slice::from_raw_parts(aligned.as_ptr() as *const u8, 6) };
+    if cfg!(target_endian = "little") {
+        assert_eq!(as_i16_le(view), Some(&samples[..]));
+        assert_eq!(as_i16_le(&view[1..5]), None);
+    }
+    assert_eq!(as_i16_le(&view[..5]), None);
+}
+
+#[test]
+fn byte_order_conversion_roundtrips() {
+    let shorts: Vec<i16> = vec![-32768, -3, 0, 2, 32767];
+    let mut big = vec![0; 10];
+    assert_eq!(encode_i16_be(&shorts, &mut big), 5);
+    assert_eq!(&big[..4], &[0x80, 0x00, 255, 253]);
+    let mut decoded = vec![0; 5];
+    assert_eq!(decode_i16_be(&big, &mut decoded), 5);
+    assert_eq!(decoded, shorts);
+
+    let mut little = big.clone();
+    assert_eq!(convert_byte_order(&mut little, 2, Endianness::Big, Endianness::Little), 5);
+    decode_i16_le(&little, &mut decoded);
+    assert_eq!(decoded, shorts);
+    assert_eq!(convert_byte_order(&mut little, 2, Endianness::Little, Endianness::Little), 5);
+    assert_eq!(convert_byte_order(&mut little, 2, Endianness::Little, Endianness::Big), 5);
+    assert_eq!(little, big);
+
+    let mut bytes = [1, 2, 3, 4, 5, 6, 7];
+    assert_eq!(swap_sample_bytes(&mut bytes, 3), 2);
+    assert_eq!(bytes, [3, 2, 1, 6, 5, 4, 7]);
+    assert_eq!(swap_sample_bytes(&mut bytes, 0), 0);
+
+    assert_eq!(swap_u16(0x1234, Endianness::Big), 0x3412);
+    assert_eq!(swap_u32(0x1234_5678, Endianness::Big), 0x7856_3412);
+    assert_eq!(swap_u32(0x1234_5678, Endianness::Little), 0x1234_5678);
+}
@@ -0,0 +1,162 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use crate::convert::int_to_f64;
+use crate::read::WavReader;
+use super::{Result, SampleFormat};
+
+/// The outcome of comparing the audio of two files, see `compare()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    /// The files have the same spec, and every sample has the same bits.
+    Identical,
+    /// The specs differ, but the samples are equal after conversion to the range of float samples.
+    ///
+    /// This is the case for a 16-bit file and a 24-bit file that was
+    /// converted from it, for instance.
+    IdenticalAfterNormalization,
+    /// The samples differ.
+    Differ {
+        /// The first frame in which a sample differs.
+        first_frame: u64,
+        /// The largest difference between two samples, in the range of float samples.
+        ///
+        /// Full scale is 1.0, so a difference of one step in a 16-bit file is
+        /// 2^-15. Samples beyond the end of the shorter file are compared to
+        /// silence. If only one of two samples is NaN, or the files have a
+        /// different number of channels or sample rate, this is infinite.
+        max_error: f64,
+    },
+}
+
+/// Reads the next sample as its bits and its value in the range of float samples.
+fn next_sample<R: io::Read>(reader: &mut WavReader<R>) -> Option<Result<(u32, f64)>> {
+    let spec = reader.spec();
+    match spec.sample_format {
+        SampleFormat::Int => {
+            let sample = reader.samples::<i32>().next();
+            sample.map(|s| s.map(|x| (x as u32, int_to_f64(x, spec.bits_per_sample))))
+        }
+        #[cfg(feature = "float")]
+        SampleFormat::Float => {
+            let sample = reader.samples::<f32>().next();
+            sample.map(|s| s.map(|x| (x.to_bits(), x as f64)))
+        }
+        #[cfg(not(feature = "float"))]
+        SampleFormat::Float => Some(Err(super::Error::Unsupported)),
+    }
+}
+
+/// Compares the audio of two files sample by sample.
+///
+/// Only the samples are compared, from the current position of both readers
+/// to the end. Differences in the container, such as the byte order, the
+/// layout of the header, or metadata chunks, do not count. This is useful to
+/// test that an audio pipeline produces the same output as before:
+///
+/// ```
+/// let mut a = hound::WavReader::open("testsamples/pop.wav").unwrap();
+/// let mut b = hound::WavReader::open("testsamples/pop.wav").unwrap();
+/// assert_eq!(hound::compare(&mut a, &mut b).unwrap(), hound::Comparison::Identical);
+/// ```
+///
+/// Decoding errors in either file are returned. Files with float samples
+/// cannot be compared without the `float` feature.
+pub fn compare<Ra, Rb>(a: &mut WavReader<Ra>, b: &mut WavReader<Rb>) -> Result<Comparison>
+    where Ra: io::Read,
+          Rb: io::Read
+{
+    let (spec_a, spec_b) = (a.spec_ex(), b.spec_ex());
+    if spec_a.spec.channels != spec_b.spec.channels ||
+       spec_a.spec.sample_rate != spec_b.spec.sample_rate {
+        return Ok(Comparison::Differ {
+            first_frame: 0,
+            max_error: f64::INFINITY,
+        });
+    }
+
+    let channels = spec_a.spec.channels as u64;
+    let mut bits_equal = spec_a == spec_b;
+    let mut first_difference: Option<u64> = None;
+    let mut max_error = 0.0_f64;
+    let mut index = 0_u64;
+    loop {
+        let (x, y) = match (next_sample(a).transpose()?, next_sample(b).transpose()?) {
+            (None, None) => break,
+            (Some(x), Some(y)) => (x, y),
+            (x, y) => {
+                bits_equal = false;
+                (x.unwrap_or((0, 0.0)), y.unwrap_or((0, 0.0)))
+            }
+        };
+        bits_equal = bits_equal && x.0 == y.0;
+        let error = if x.1 == y.1 || (x.1.is_nan() && y.1.is_nan()) {
+            0.0
+        } else if x.1.is_nan() || y.1.is_nan() {
+            f64::INFINITY
+        } else {
+            (x.1 - y.1).abs()
+        };
+        if error > 0.0 && first_difference.is_none() {
+            first_difference = Some(index / channels);
+        }
+        max_error = max_error.max(error);
+        index += 1;
+    }
+
+    match first_difference {
+        Some(frame) => Ok(Comparison::Differ {
+            first_frame: frame,
+            max_error,
+        }),
+        None if bits_equal => Ok(Comparison::Identical),
+        None => Ok(Comparison::IdenticalAfterNormalization),
+    }
+}
+
+#[test]
+fn compare_distinguishes_identical_normalized_and_different_audio() {
+    use crate::write::WavWriter;
+    use super::WavSpec;
+
+    fn wav_bytes(bits_per_sample: u16, samples: &[i32]) -> Vec<u8> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::new_in_memory(spec).unwrap();
+        writer.write_samples(samples).unwrap();
+        writer.finalize().unwrap()
+    }
+    fn compare_bytes(a: &[u8], b: &[u8]) -> Comparison {
+        compare(&mut WavReader::new(a).unwrap(), &mut WavReader::new(b).unwrap()).unwrap()
+    }
+
+    let original = wav_bytes(16, &[1, -2, 3, -4]);
+    let widened = wav_bytes(24, &[256, -512, 768, -1024]);
+    let changed = wav_bytes(16, &[1, -2, 3, -8]);
+    let shorter = wav_bytes(16, &[1, -2]);
+
+    assert_eq!(compare_bytes(&original, &original), Comparison::Identical);
+    assert_eq!(compare_bytes(&original, &widened), Comparison::IdenticalAfterNormalization);
+    assert_eq!(compare_bytes(&original, &changed), Comparison::Differ {
+        first_frame: 1,
+        max_error: 4.0 / 32768.0,
+    });
+    assert_eq!(compare_bytes(&shorter, &original), Comparison::Differ {
+        first_frame: 1,
+        max_error: 4.0 / 32768.0,
+    });
+}
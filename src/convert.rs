@@ -0,0 +1,609 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversions between sample representations.
+//!
+//! The public functions convert between integer and float samples. They are
+//! also re-exported at the crate root.
+//!
+//! # Conventions
+//!
+//! An integer sample `x` with `bits` bits per sample converts to the float
+//! `x / 2^(bits - 1)`, which is in [-1.0, 1.0). Conversion back multiplies by
+//! 2^(`bits` - 1), rounds to the nearest integer with ties away from zero,
+//! and clamps to the integer range, so 1.0 becomes the maximum and NaN
+//! becomes 0. This roundtrips every integer sample exactly.
+//!
+//! With the `conversion` feature, `ConversionPolicy` selects a different
+//! scaling, rounding mode, or clamping behavior, to match other software
+//! bit-exactly.
+
+use crate::error::{Error, Result};
+
+/// Converts an unsigned integer in the range 0-255 to a signed one in the range -128-127.
+///
+/// Presumably, the designers of the WAVE format did not like consistency. For
+/// all bit depths except 8, samples are stored as little-endian _signed_
+/// integers. However, an 8-bit sample is instead stored as an _unsigned_
+/// integer. Hound abstracts away this idiosyncrasy by providing only signed
+/// sample types.
+pub(crate) fn signed_from_u8(x: u8) -> i8 {
+    (x as i16 - 128) as i8
+}
+
+/// Converts a signed integer in the range -128-127 to an unsigned one in the range 0-255.
+pub(crate) fn u8_from_signed(x: i8) -> u8 {
+    (x as i16 + 128) as u8
+}
+
+#[test]
+fn u8_sign_conversion_is_bijective() {
+    for x in 0..255 {
+        assert_eq!(x, u8_from_signed(signed_from_u8(x)));
+    }
+    for x in -128..127 {
+        assert_eq!(x, signed_from_u8(u8_from_signed(x)));
+    }
+}
+
+/// Tries to cast the sample to an 8-bit signed integer, returning an error on overflow.
+#[inline(always)]
+pub(crate) fn narrow_to_i8(x: i32) -> Result<i8> {
+    if x < i8::MIN as i32 || x > i8::MAX as i32 {
+        Err(Error::TooWide)
+    } else {
+        Ok(x as i8)
+    }
+}
+
+#[test]
+fn verify_narrow_to_i8() {
+    assert!(narrow_to_i8(127).is_ok());
+    assert!(narrow_to_i8(128).is_err());
+    assert!(narrow_to_i8(-128).is_ok());
+    assert!(narrow_to_i8(-129).is_err());
+}
+
+/// Tries to cast the sample to a 16-bit signed integer, returning an error on overflow.
+#[inline(always)]
+pub(crate) fn narrow_to_i16(x: i32) -> Result<i16> {
+    if x < i16::MIN as i32 || x > i16::MAX as i32 {
+        Err(Error::TooWide)
+    } else {
+        Ok(x as i16)
+    }
+}
+
+#[test]
+fn verify_narrow_to_i16() {
+    assert!(narrow_to_i16(32767).is_ok());
+    assert!(narrow_to_i16(32768).is_err());
+    assert!(narrow_to_i16(-32768).is_ok());
+    assert!(narrow_to_i16(-32769).is_err());
+}
+
+/// Tries to cast the sample to a 24-bit signed integer, returning an error on overflow.
+#[inline(always)]
+pub(crate) fn narrow_to_i24(x: i32) -> Result<i32> {
+    if !(-(1 << 23)..(1 << 23)).contains(&x) {
+        Err(Error::TooWide)
+    } else {
+        Ok(x)
+    }
+}
+
+#[test]
+fn verify_narrow_to_i24() {
+    assert!(narrow_to_i24(8_388_607).is_ok());
+    assert!(narrow_to_i24(8_388_608).is_err());
+    assert!(narrow_to_i24(-8_388_608).is_ok());
+    assert!(narrow_to_i24(-8_388_609).is_err());
+}
+
+/// A xorshift64* generator, for dither noise and noise test signals.
+///
+/// `ParallelWriter` dithers every block with a generator seeded by its
+/// index, so the output does not depend on which thread converted which block.
+#[cfg(any(feature = "conversion", feature = "analysis"))]
+pub(crate) struct NoiseGenerator(u64);
+
+#[cfg(any(feature = "conversion", feature = "analysis"))]
+impl NoiseGenerator {
+    pub fn new(seed: u64) -> NoiseGenerator {
+        // The state must not be zero.
+        NoiseGenerator(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    /// Returns a number uniformly distributed in [0.0, 1.0).
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let x = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (x >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// Converts an integer sample with `bits` bits per sample to a float.
+///
+/// The sample is divided by 2^(`bits` - 1), so the result is in the range
+/// [-1.0, 1.0). For `bits` up to 32 this conversion is exact.
+///
+/// # Panics
+///
+/// Panics if `bits` is not between 1 and 32.
+pub fn int_to_f64(x: i32, bits: u16) -> f64 {
+    assert!((1..=32).contains(&bits), "bits must be between 1 and 32");
+    x as f64 / (1_u64 << (bits - 1)) as f64
+}
+
+/// Converts an integer sample with `bits` bits per sample to a float.
+///
+/// This is like `int_to_f64`, but for samples with more than 24 bits, the
+/// result is rounded to the nearest `f32`.
+///
+/// # Panics
+///
+/// Panics if `bits` is not between 1 and 32.
+pub fn int_to_f32(x: i32, bits: u16) -> f32 {
+    int_to_f64(x, bits) as f32
+}
+
+/// Converts a float sample to an integer sample with `bits` bits per sample.
+///
+/// This is the inverse of `int_to_f64`: the sample is multiplied by
+/// 2^(`bits` - 1) and rounded to the nearest integer. Values outside of the
+/// range that can be represented are clamped, NaN is converted to 0.
+///
+/// # Panics
+///
+/// Panics if `bits` is not between 1 and 32.
+pub fn f64_to_int(x: f64, bits: u16) -> i32 {
+    assert!((1..=32).contains(&bits), "bits must be between 1 and 32");
+    if x.is_nan() {
+        return 0;
+    }
+    let scale = (1_u64 << (bits - 1)) as f64;
+    let y = (x * scale).round();
+    if y < -scale {
+        -scale as i32
+    } else if y > scale - 1.0 {
+        (scale - 1.0) as i32
+    } else {
+        y as i32
+    }
+}
+
+/// Converts a float sample to an integer sample with `bits` bits per sample.
+///
+/// See `f64_to_int` for details.
+///
+/// # Panics
+///
+/// Panics if `bits` is not between 1 and 32.
+pub fn f32_to_int(x: f32, bits: u16) -> i32 {
+    f64_to_int(x as f64, bits)
+}
+
+#[test]
+fn verify_int_float_conversion() {
+    assert_eq!(int_to_f64(i32::MIN, 32), -1.0);
+    assert_eq!(int_to_f64(1 << 30, 32), 0.5);
+    assert_eq!(int_to_f32(-64, 8), -0.5);
+    assert_eq!(int_to_f32(8_388_607, 24), 8_388_607.0 / 8_388_608.0);
+
+    // Conversion through f64 is lossless for all bit depths up to 32.
+    for &x in &[i32::MIN, -2_147_483_497, -1, 0, 1, 33_587_161, i32::MAX] {
+        assert_eq!(f64_to_int(int_to_f64(x, 32), 32), x);
+    }
+    for x in -128..128 {
+        assert_eq!(f32_to_int(int_to_f32(x, 8), 8), x);
+    }
+
+    assert_eq!(f64_to_int(1.0, 32), i32::MAX);
+    assert_eq!(f64_to_int(-1.5, 32), i32::MIN);
+    assert_eq!(f32_to_int(1.0, 16), 32767);
+    assert_eq!(f32_to_int(-1.0, 16), -32768);
+    assert_eq!(f32_to_int(f32::NAN, 16), 0);
+}
+
+/// Returns whether `x` is at or beyond the extremes of a `bits`-bit signed integer.
+#[inline(always)]
+pub(crate) fn is_full_scale_int(x: i32, bits: u16) -> bool {
+    if bits == 0 || bits > 32 {
+        return false;
+    }
+    let max = (1_i64 << (bits - 1)) - 1;
+    let min = -(1_i64 << (bits - 1));
+    x as i64 >= max || x as i64 <= min
+}
+
+#[test]
+fn verify_is_full_scale_int() {
+    assert!(is_full_scale_int(127, 8));
+    assert!(is_full_scale_int(-128, 8));
+    assert!(is_full_scale_int(300, 8));
+    assert!(!is_full_scale_int(126, 8));
+    assert!(!is_full_scale_int(-127, 8));
+    assert!(is_full_scale_int(8_388_607, 24));
+    assert!(!is_full_scale_int(8_388_606, 24));
+    assert!(is_full_scale_int(-2_147_483_648, 32));
+    assert!(!is_full_scale_int(0, 16));
+}
+
+/// How integer samples are scaled to floats, and back.
+///
+/// For a sample with `bits` bits per sample, let `n` be 2^(`bits` - 1), so
+/// the integer range is [-n, n - 1]. Software disagrees on how that range
+/// maps onto [-1.0, 1.0], so converting a file with one convention and back
+/// with another changes samples by up to one least significant bit.
+#[cfg(feature = "conversion")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scaling {
+    /// Divide by `n`. The integer range maps to [-1.0, 1.0), and 1.0 is just
+    /// out of range. This is the default, and the convention of
+    /// `int_to_f64()` and `f64_to_int()`.
+    Symmetric,
+    /// Divide by `n - 1`. 1.0 maps to the maximum, and the minimum maps to
+    /// slightly less than -1.0.
+    Asymmetric,
+    /// Divide negative samples by `n` and positive ones by `n - 1`. The
+    /// integer range maps to exactly [-1.0, 1.0], at the cost of a step size
+    /// that differs by sign.
+    Split,
+}
+
+/// How scaled float samples are rounded to integers.
+///
+/// Every mode converts integer samples converted to floats back to the same
+/// integers with symmetric scaling. The other scalings divide inexactly, so
+/// the product may end up just short of an integer, and only the modes that
+/// round to nearest recover it.
+#[cfg(feature = "conversion")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round to the nearest integer, ties away from zero. This is the default.
+    Nearest,
+    /// Round to the nearest integer, ties to even.
+    NearestEven,
+    /// Round toward zero, as a plain cast does.
+    TowardZero,
+    /// Round toward negative infinity, as an arithmetic shift does.
+    Floor,
+}
+
+/// How integer samples are converted to a higher bit depth.
+#[cfg(feature = "conversion")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Widening {
+    /// Keep the value. An 8-bit sample stays in the range [-128, 127].
+    Keep,
+    /// Append zero bits, so 8-bit samples are multiplied by 256. This is the
+    /// default. It is the inverse of narrowing by discarding bits, but the
+    /// maximum falls short of full scale: 8-bit 127 becomes 32512.
+    Shift,
+    /// Repeat the bits of the offset binary sample, so 8-bit samples are
+    /// multiplied by 257. Both extremes map to full scale, so the gain is
+    /// exact, but silence gets an offset: 8-bit 0 (stored as 0x80) becomes
+    /// 128 (stored as 0x8080).
+    Replicate,
+}
+
+/// A convention for conversion between integer and float samples.
+///
+/// The default policy is the one used by `int_to_f64()` and `f64_to_int()`:
+/// symmetric scaling, rounding to the nearest integer, and clamping of
+/// values out of range. Integers are widened by shifting. Selecting another
+/// policy makes it possible to match other software bit-exactly.
+///
+/// ```
+/// use hound::convert::{ConversionPolicy, Scaling};
+///
+/// let policy = ConversionPolicy { scaling: Scaling::Asymmetric, .. Default::default() };
+/// assert_eq!(policy.f64_to_int(-1.0, 16).unwrap(), -32767);
+/// assert_eq!(policy.int_to_f64(32767, 16), 1.0);
+/// ```
+#[cfg(feature = "conversion")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConversionPolicy {
+    /// How integers are scaled to floats.
+    pub scaling: Scaling,
+    /// How scaled floats are rounded to integers.
+    pub rounding: Rounding,
+    /// Whether floats out of range are clamped. If not, converting them, or
+    /// NaN, is an error.
+    pub clamp: bool,
+    /// How integers are converted to a higher bit depth.
+    pub widening: Widening,
+}
+
+#[cfg(feature = "conversion")]
+impl Default for ConversionPolicy {
+    fn default() -> ConversionPolicy {
+        ConversionPolicy {
+            scaling: Scaling::Symmetric,
+            rounding: Rounding::Nearest,
+            clamp: true,
+            widening: Widening::Shift,
+        }
+    }
+}
+
+#[cfg(feature = "conversion")]
+impl ConversionPolicy {
+    /// Returns the divisor for a sample of the given sign.
+    fn scale(&self, bits: u16, negative: bool) -> f64 {
+        let n = (1_u64 << (bits - 1)) as f64;
+        match self.scaling {
+            Scaling::Symmetric => n,
+            Scaling::Asymmetric => n - 1.0,
+            Scaling::Split => if negative { n } else { n - 1.0 },
+        }
+    }
+
+    fn round(&self, y: f64) -> f64 {
+        match self.rounding {
+            Rounding::Nearest => y.round(),
+            Rounding::NearestEven => {
+                if (y - y.trunc()).abs() == 0.5 {
+                    2.0 * (y / 2.0).round()
+                } else {
+                    y.round()
+                }
+            }
+            Rounding::TowardZero => y.trunc(),
+            Rounding::Floor => y.floor(),
+        }
+    }
+
+    /// Converts an integer sample with `bits` bits per sample to a float.
+    ///
+    /// For `bits` up to 32 this conversion is exact with symmetric scaling,
+    /// and correctly rounded otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not between 2 and 32.
+    pub fn int_to_f64(&self, x: i32, bits: u16) -> f64 {
+        assert!((2..=32).contains(&bits), "bits must be between 2 and 32");
+        x as f64 / self.scale(bits, x < 0)
+    }
+
+    /// Converts an integer sample with `bits` bits per sample to a float.
+    ///
+    /// This is like `int_to_f64()`, with the result rounded to the nearest `f32`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not between 2 and 32.
+    pub fn int_to_f32(&self, x: i32, bits: u16) -> f32 {
+        self.int_to_f64(x, bits) as f32
+    }
+
+    /// Converts a float sample to an integer sample with `bits` bits per sample.
+    ///
+    /// This is the inverse of `int_to_f64()`: the sample is multiplied by the
+    /// scale and rounded. Values out of range are clamped and NaN is
+    /// converted to 0 if the policy clamps. Otherwise `Error::TooWide` is
+    /// returned for them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not between 2 and 32.
+    pub fn f64_to_int(&self, x: f64, bits: u16) -> Result<i32> {
+        assert!((2..=32).contains(&bits), "bits must be between 2 and 32");
+        if x.is_nan() {
+            return if self.clamp { Ok(0) } else { Err(Error::TooWide) };
+        }
+        let max = ((1_u64 << (bits - 1)) - 1) as f64;
+        let min = -max - 1.0;
+        let y = self.round(x * self.scale(bits, x < 0.0));
+        if y >= min && y <= max {
+            Ok(y as i32)
+        } else if !self.clamp {
+            Err(Error::TooWide)
+        } else if y < min {
+            Ok(min as i32)
+        } else {
+            Ok(max as i32)
+        }
+    }
+
+    /// Converts a float sample to an integer sample with `bits` bits per sample.
+    ///
+    /// See `f64_to_int()` for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not between 2 and 32.
+    pub fn f32_to_int(&self, x: f32, bits: u16) -> Result<i32> {
+        self.f64_to_int(x as f64, bits)
+    }
+
+    /// Converts an integer sample with `from_bits` bits to one with `to_bits` bits.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless 1 <= `from_bits` <= `to_bits` <= 32.
+    pub fn widen_int(&self, x: i32, from_bits: u16, to_bits: u16) -> i32 {
+        assert!(from_bits >= 1 && from_bits <= to_bits && to_bits <= 32,
+                "bits must satisfy 1 <= from_bits <= to_bits <= 32");
+        match self.widening {
+            Widening::Keep => x,
+            Widening::Shift => x << (to_bits - from_bits),
+            Widening::Replicate => {
+                // Concatenate the offset binary pattern until it fills the
+                // target width, then drop the excess low bits.
+                let mask = (1_u64 << from_bits) - 1;
+                let u = (x as i64 + (1_i64 << (from_bits - 1))) as u64 & mask;
+                let mut acc = 0_u64;
+                let mut filled = 0;
+                while filled < to_bits {
+                    acc = (acc << from_bits) | u;
+                    filled += from_bits;
+                }
+                ((acc >> (filled - to_bits)) as i64 - (1_i64 << (to_bits - 1))) as i32
+            }
+        }
+    }
+}
+
+#[cfg(feature = "conversion")]
+#[test]
+fn default_policy_matches_free_functions() {
+    let policy = ConversionPolicy::default();
+    for bits in 2..33 {
+        let n = 1_i64 << (bits - 1);
+        for &x in &[-n, -n + 1, -1, 0, 1, n / 3, n - 1] {
+            let x = x as i32;
+            assert_eq!(policy.int_to_f64(x, bits), int_to_f64(x, bits));
+            assert_eq!(policy.int_to_f32(x, bits), int_to_f32(x, bits));
+        }
+        for &x in &[-2.0, -1.0, -0.3, 0.0, 0.49 / n as f64, 0.5 / n as f64, 0.7, 1.0, 3.0] {
+            assert_eq!(policy.f64_to_int(x, bits).unwrap(), f64_to_int(x, bits));
+        }
+    }
+}
+
+#[cfg(feature = "conversion")]
+#[test]
+fn every_policy_roundtrips_every_sample() {
+    let scalings = [Scaling::Symmetric, Scaling::Asymmetric, Scaling::Split];
+    let roundings = [Rounding::Nearest, Rounding::NearestEven, Rounding::TowardZero, Rounding::Floor];
+    for &scaling in &scalings {
+        for &rounding in &roundings {
+            if scaling != Scaling::Symmetric &&
+               (rounding == Rounding::TowardZero || rounding == Rounding::Floor) {
+                continue;
+            }
+            for &clamp in &[false, true] {
+                let policy = ConversionPolicy {
+                    scaling,
+                    rounding,
+                    clamp,
+                    .. Default::default()
+                };
+                for &bits in &[8, 16] {
+                    let n = 1_i32 << (bits - 1);
+                    for x in -n..n {
+                        assert_eq!(policy.f64_to_int(policy.int_to_f64(x, bits), bits).unwrap(), x,
+                                   "{:?} at {} bits", policy, bits);
+                        assert_eq!(policy.f32_to_int(policy.int_to_f32(x, bits), bits).unwrap(), x,
+                                   "{:?} at {} bits", policy, bits);
+                    }
+                }
+                for &x in &[i32::MIN, i32::MIN + 1, -1, 0, 1, 33_587_161, i32::MAX] {
+                    assert_eq!(policy.f64_to_int(policy.int_to_f64(x, 32), 32).unwrap(), x);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "conversion")]
+#[test]
+fn verify_policy_scaling() {
+    let symmetric = ConversionPolicy::default();
+    let asymmetric = ConversionPolicy { scaling: Scaling::Asymmetric, .. symmetric };
+    let split = ConversionPolicy { scaling: Scaling::Split, .. symmetric };
+
+    assert_eq!(symmetric.int_to_f64(-32768, 16), -1.0);
+    assert_eq!(symmetric.int_to_f64(32767, 16), 32767.0 / 32768.0);
+    assert_eq!(symmetric.f64_to_int(1.0, 16).unwrap(), 32767);
+    assert_eq!(symmetric.f64_to_int(-1.0, 16).unwrap(), -32768);
+
+    assert_eq!(asymmetric.int_to_f64(-32768, 16), -32768.0 / 32767.0);
+    assert_eq!(asymmetric.int_to_f64(32767, 16), 1.0);
+    assert_eq!(asymmetric.f64_to_int(1.0, 16).unwrap(), 32767);
+    assert_eq!(asymmetric.f64_to_int(-1.0, 16).unwrap(), -32767);
+    assert_eq!(asymmetric.f64_to_int(0.5, 8).unwrap(), 64);
+
+    assert_eq!(split.int_to_f64(-128, 8), -1.0);
+    assert_eq!(split.int_to_f64(127, 8), 1.0);
+    assert_eq!(split.int_to_f64(-64, 8), -0.5);
+    assert_eq!(split.f64_to_int(1.0, 8).unwrap(), 127);
+    assert_eq!(split.f64_to_int(-1.0, 8).unwrap(), -128);
+    assert_eq!(split.f64_to_int(0.5, 8).unwrap(), 64);
+    assert_eq!(split.f64_to_int(-0.5, 8).unwrap(), -64);
+}
+
+#[cfg(feature = "conversion")]
+#[test]
+fn verify_policy_rounding_and_clamping() {
+    let policy = |rounding, clamp| {
+        ConversionPolicy { rounding, clamp, .. Default::default() }
+    };
+    // Inputs of 2.5, 3.5, -2.5, and -2.25 least significant bits at 8 bits.
+    let inputs = [2.5 / 128.0, 3.5 / 128.0, -2.5 / 128.0, -2.25 / 128.0];
+    let expected = [
+        (Rounding::Nearest, [3, 4, -3, -2]),
+        (Rounding::NearestEven, [2, 4, -2, -2]),
+        (Rounding::TowardZero, [2, 3, -2, -2]),
+        (Rounding::Floor, [2, 3, -3, -3]),
+    ];
+    for &(rounding, ref outputs) in &expected {
+        for (&x, &y) in inputs.iter().zip(outputs.iter()) {
+            assert_eq!(policy(rounding, true).f64_to_int(x, 8).unwrap(), y, "{:?}", rounding);
+        }
+    }
+
+    let clamping = policy(Rounding::Nearest, true);
+    let strict = policy(Rounding::Nearest, false);
+    assert_eq!(clamping.f64_to_int(1.0, 8).unwrap(), 127);
+    assert_eq!(clamping.f64_to_int(-1.5, 8).unwrap(), -128);
+    assert_eq!(clamping.f32_to_int(f32::NAN, 8).unwrap(), 0);
+    assert!(strict.f64_to_int(1.0, 8).is_err());
+    assert!(strict.f64_to_int(-1.5, 8).is_err());
+    assert!(strict.f32_to_int(f32::NAN, 8).is_err());
+    assert_eq!(strict.f64_to_int(-1.0, 8).unwrap(), -128);
+    assert_eq!(strict.f64_to_int(127.4 / 128.0, 8).unwrap(), 127);
+}
+
+#[cfg(feature = "conversion")]
+#[test]
+fn verify_widen_int() {
+    let policy = |widening| ConversionPolicy { widening, .. Default::default() };
+    let keep = policy(Widening::Keep);
+    let shift = policy(Widening::Shift);
+    let replicate = policy(Widening::Replicate);
+
+    // Stored 8-bit values, and the expected 16-bit samples.
+    let reference: [(u8, i32, i32, i32); 7] = [
+        (0x00, -128, -32768, -32768),
+        (0x01, -127, -32512, -32511),
+        (0x40, -64, -16384, -16320),
+        (0x7f, -1, -256, -129),
+        (0x80, 0, 0, 128),
+        (0xc0, 64, 16384, 16576),
+        (0xff, 127, 32512, 32767),
+    ];
+    for &(stored, k, s, r) in &reference {
+        let x = signed_from_u8(stored) as i32;
+        assert_eq!(keep.widen_int(x, 8, 16), k);
+        assert_eq!(shift.widen_int(x, 8, 16), s);
+        assert_eq!(replicate.widen_int(x, 8, 16), r);
+    }
+
+    // Replication is multiplication by 257 of the offset binary value.
+    for stored in 0..256_i32 {
+        let x = signed_from_u8(stored as u8) as i32;
+        assert_eq!(replicate.widen_int(x, 8, 16), stored * 257 - 32768);
+    }
+
+    // Other widths, where the pattern does not divide the target width.
+    assert_eq!(replicate.widen_int(-2048, 12, 16), -32768);
+    assert_eq!(replicate.widen_int(2047, 12, 16), 32767);
+    assert_eq!(replicate.widen_int(8_388_607, 24, 32), i32::MAX);
+    assert_eq!(replicate.widen_int(-128, 8, 32), i32::MIN);
+    assert_eq!(replicate.widen_int(5, 16, 16), 5);
+    assert_eq!(shift.widen_int(-3, 20, 24), -48);
+}
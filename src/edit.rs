@@ -0,0 +1,647 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::io;
+use std::path;
+use crate::chunks::ChunkInfo;
+use crate::read::{ReadExt, WavReader};
+use crate::write::WriteExt;
+use super::{Error, Result, Sample, WavSpec};
+
+/// Returns the number of bytes a chunk with `len` bytes of content occupies,
+/// including its header and padding byte.
+fn chunk_size(len: u32) -> u64 {
+    8 + len as u64 + (len % 2) as u64
+}
+
+/// Returns whether the chunk only reserves space, so it may be overwritten.
+fn is_slack(id: &[u8; 4]) -> bool {
+    id == b"JUNK" || id == b"junk" || id == b"PAD "
+}
+
+/// Edits the metadata chunks of an existing WAVE file in place.
+///
+/// Metadata chunks such as `bext`, `cue ` or `LIST` can be added, replaced,
+/// and removed without rewriting the audio data. To achieve this, the editor
+/// reuses space where possible:
+///
+///  * A chunk that gets smaller, or that fits in its old space together with
+///    `JUNK` chunks directly after it, is replaced in place. Leftover space is
+///    turned into a `JUNK` chunk.
+///  * Otherwise, the chunk is written into a `JUNK` chunk that is large
+///    enough, if there is one. Such chunks can be reserved when writing the
+///    file with `WavWriter::reserve_junk()`.
+///  * Otherwise, the old chunk (if any) is turned into a `JUNK` chunk, and
+///    the new chunk is appended to the end of the file. If the old chunk came
+///    after the data chunk, the chunks after it are shifted instead. Data
+///    before the chunk is never moved.
+///
+/// Removed chunks are turned into `JUNK` chunks, so the file does not shrink.
+/// The fmt and data chunks cannot be edited.
+pub struct WavEditor<F> {
+    /// The underlying file.
+    file: F,
+
+    /// All chunks in the RIFF form, in file order.
+    chunks: Vec<ChunkInfo>,
+
+    /// The offset of the end of the RIFF form.
+    end: u64,
+}
+
+impl<F> WavEditor<F>
+    where F: io::Read + io::Write + io::Seek
+{
+    /// Creates an editor for the WAVE file in `file`.
+    ///
+    /// The file must start at offset 0. All chunk headers are read
+    /// immediately, the contents of the chunks are skipped by seeking.
+    pub fn new(file: F) -> Result<WavEditor<F>> {
+        let mut editor = WavEditor {
+            file,
+            chunks: Vec::new(),
+            end: 0,
+        };
+        editor.scan()?;
+        Ok(editor)
+    }
+
+    /// Reads the RIFF header and the headers of all chunks.
+    fn scan(&mut self) -> Result<()> {
+        self.file.seek(io::SeekFrom::Start(0))?;
+        if b"RIFF" != &self.file.read_bytes(4)?[..] {
+            return Err(Error::FormatError("no RIFF tag found"));
+        }
+        let riff_len = self.file.read_le_u32()?;
+        if b"WAVE" != &self.file.read_bytes(4)?[..] {
+            return Err(Error::FormatError("no WAVE tag found"));
+        }
+
+        let end = 8 + riff_len as u64;
+        let mut chunks = Vec::new();
+        let mut offset = 12;
+        while offset + 8 <= end {
+            self.file.seek(io::SeekFrom::Start(offset))?;
+            let mut id = [0u8; 4];
+            self.file.read_into(&mut id)?;
+            let len = self.file.read_le_u32()?;
+            chunks.push(ChunkInfo {
+                id,
+                offset,
+                len,
+            });
+            offset += chunk_size(len);
+        }
+
+        // If the last chunk claims to extend beyond the RIFF form, trust the
+        // chunk, so appended chunks do not overwrite its contents.
+        self.end = if offset > end { offset } else { end };
+        self.chunks = chunks;
+        Ok(())
+    }
+
+    /// Returns all chunks in the file, in file order.
+    pub fn chunks(&self) -> &[ChunkInfo] {
+        &self.chunks[..]
+    }
+
+    /// Returns the index of the chunk that `set_chunk(id, data)` would replace.
+    fn find(&mut self, id: &[u8; 4], data: &[u8]) -> Result<Option<usize>> {
+        if id != b"LIST" || data.len() < 4 {
+            return Ok(self.chunks.iter().position(|c| c.id == *id));
+        }
+        // LIST chunks are distinguished by their list type.
+        for i in 0..self.chunks.len() {
+            let chunk = self.chunks[i];
+            if chunk.id == *id && chunk.len >= 4 {
+                self.file.seek(io::SeekFrom::Start(chunk.offset + 8))?;
+                if self.file.read_bytes(4)?[..] == data[..4] {
+                    return Ok(Some(i));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the contents of the first chunk with the given id.
+    ///
+    /// Returns `None` if there is no such chunk.
+    pub fn read_chunk(&mut self, id: &[u8; 4]) -> Result<Option<Vec<u8>>> {
+        let chunk = match self.chunks.iter().find(|c| c.id == *id) {
+            Some(chunk) => *chunk,
+            None => return Ok(None),
+        };
+        self.file.seek(io::SeekFrom::Start(chunk.offset + 8))?;
+        Ok(Some(self.file.read_bytes(chunk.len as usize)?))
+    }
+
+    /// Writes a chunk header and contents, followed by a padding byte if needed.
+    fn write_chunk(&mut self, offset: u64, id: &[u8; 4], data: &[u8]) -> Result<()> {
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        self.file.write_all(id)?;
+        self.file.write_le_u32(data.len() as u32)?;
+        self.file.write_all(data)?;
+        if data.len() % 2 == 1 {
+            self.file.write_all(&[0])?;
+        }
+        Ok(())
+    }
+
+    /// Writes a JUNK chunk header that makes the chunk occupy `size` bytes.
+    fn write_junk(&mut self, offset: u64, size: u64) -> Result<()> {
+        debug_assert!(size >= 8);
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        self.file.write_all(b"JUNK")?;
+        self.file.write_le_u32((size - 8) as u32)?;
+        Ok(())
+    }
+
+    /// Writes a chunk into a region of `space` bytes, filling the rest with JUNK.
+    fn write_into(&mut self, offset: u64, space: u64, id: &[u8; 4], data: &[u8]) -> Result<()> {
+        let size = chunk_size(data.len() as u32);
+        self.write_chunk(offset, id, data)?;
+        if space > size {
+            self.write_junk(offset + size, space - size)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether a chunk of `size` bytes fits in `space` bytes.
+    fn fits(size: u64, space: u64) -> bool {
+        // Leftover space must be able to hold a JUNK chunk header.
+        size == space || size + 8 <= space
+    }
+
+    /// Updates the RIFF length field to the current end of the form.
+    fn write_riff_len(&mut self) -> Result<()> {
+        if self.end - 8 > 0xffff_ffff {
+            return Err(Error::FormatError("file too large for RIFF"));
+        }
+        self.file.seek(io::SeekFrom::Start(4))?;
+        self.file.write_le_u32((self.end - 8) as u32)?;
+        Ok(())
+    }
+
+    /// Adds a chunk, or replaces the existing chunk with the same id.
+    ///
+    /// For `LIST` chunks, the first four bytes of `data` are the list type,
+    /// and only a `LIST` chunk with the same list type is replaced.
+    pub fn set_chunk(&mut self, id: &[u8; 4], data: &[u8]) -> Result<()> {
+        if id == b"fmt " || id == b"data" {
+            return Err(Error::Unsupported);
+        }
+        if data.len() > 0xffff_fffe {
+            return Err(Error::FormatError("chunk too large"));
+        }
+
+        let size = chunk_size(data.len() as u32);
+        let existing = self.find(id, data)?;
+
+        if let Some(i) = existing {
+            // Space of the chunk itself, and of any JUNK directly after it.
+            let offset = self.chunks[i].offset;
+            let space = self.chunks[i..]
+                            .iter()
+                            .enumerate()
+                            .take_while(|&(j, c)| j == 0 || is_slack(&c.id))
+                            .map(|(_, c)| chunk_size(c.len))
+                            .sum::<u64>();
+            if WavEditor::<F>::fits(size, space) {
+                self.write_into(offset, space, id, data)?;
+                return self.scan();
+            }
+        }
+
+        // Prefer a JUNK chunk that precedes the data chunk, so the new chunk
+        // is seen by streaming readers.
+        let data_index = self.chunks.iter().position(|c| &c.id == b"data");
+        let slack = self.chunks
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, c)| is_slack(&c.id) &&
+                                          WavEditor::<F>::fits(size, chunk_size(c.len)))
+                        .min_by_key(|&(j, _)| match data_index {
+                            Some(d) if j < d => 0,
+                            _ => 1,
+                        })
+                        .map(|(_, c)| *c);
+
+        if let Some(junk) = slack {
+            self.write_into(junk.offset, chunk_size(junk.len), id, data)?;
+            if let Some(i) = existing {
+                let old = self.chunks[i];
+                self.write_junk(old.offset, chunk_size(old.len))?;
+            }
+            return self.scan();
+        }
+
+        match existing {
+            Some(i) if data_index.map_or(true, |d| i > d) => {
+                // The chunk is after the audio data, so the chunks after it
+                // can be shifted cheaply.
+                let old = self.chunks[i];
+                let tail_start = old.offset + chunk_size(old.len);
+                let tail_len = self.end - tail_start;
+                self.file.seek(io::SeekFrom::Start(tail_start))?;
+                let tail = self.file.read_bytes(tail_len as usize)?;
+                self.write_chunk(old.offset, id, data)?;
+                self.file.write_all(&tail)?;
+                self.end = old.offset + size + tail_len;
+            }
+            _ => {
+                if let Some(i) = existing {
+                    let old = self.chunks[i];
+                    self.write_junk(old.offset, chunk_size(old.len))?;
+                }
+                let end = self.end;
+                self.write_chunk(end, id, data)?;
+                self.end += size;
+            }
+        }
+
+        self.write_riff_len()?;
+        self.scan()
+    }
+
+    /// Removes the first chunk with the given id by turning it into a JUNK chunk.
+    ///
+    /// Returns whether a chunk was removed.
+    pub fn remove_chunk(&mut self, id: &[u8; 4]) -> Result<bool> {
+        if id == b"fmt " || id == b"data" {
+            return Err(Error::Unsupported);
+        }
+        let chunk = match self.chunks.iter().find(|c| c.id == *id) {
+            Some(chunk) => *chunk,
+            None => return Ok(false),
+        };
+        self.write_junk(chunk.offset, chunk_size(chunk.len))?;
+        self.scan()?;
+        Ok(true)
+    }
+
+    /// Returns the spec of the file, and the location of the data chunk.
+    ///
+    /// Returns `Error::Unsupported` if samples are not stored in the smallest
+    /// container that holds them, because Hound cannot write such samples.
+    fn data_layout(&mut self) -> Result<(WavSpec, ChunkInfo)> {
+        let data = match self.chunks.iter().find(|c| &c.id == b"data") {
+            Some(chunk) => *chunk,
+            None => return Err(Error::FormatError("no data chunk found")),
+        };
+        self.file.seek(io::SeekFrom::Start(0))?;
+        let reader = WavReader::new(&mut self.file)?;
+        let spec = reader.spec();
+        let fmt = reader.fmt_chunk_bytes();
+        let block_align = fmt[12] as u32 | (fmt[13] as u32) << 8;
+        let bytes_per_sample = (spec.bits_per_sample as u32 + 7) / 8;
+        if block_align != bytes_per_sample * spec.channels as u32 {
+            return Err(Error::Unsupported);
+        }
+        Ok((spec, data))
+    }
+
+    /// Overwrites samples in the data chunk, starting at the given frame.
+    ///
+    /// This is intended for punch-in recording and destructive editing: the
+    /// samples replace existing audio, and the length of the file does not
+    /// change. Channel data is interleaved, so the number of samples must be
+    /// a multiple of the number of channels, otherwise
+    /// `Error::UnfinishedSample` is returned. If the samples would extend
+    /// beyond the end of the data chunk, an error of kind `InvalidInput` is
+    /// returned. In both cases, and when a sample does not fit in the number
+    /// of bits per sample of the file, nothing is written.
+    pub fn overwrite_samples<S: Sample + Copy>(&mut self,
+                                               start_frame: u64,
+                                               samples: &[S])
+                                               -> Result<()> {
+        let (spec, data) = self.data_layout()?;
+        if samples.len() % spec.channels as usize != 0 {
+            return Err(Error::UnfinishedSample);
+        }
+
+        let bytes_per_sample = ((spec.bits_per_sample as u32 + 7) / 8) as u16;
+        let block_align = bytes_per_sample as u64 * spec.channels as u64;
+        let num_frames = data.len as u64 / block_align;
+        let end_frame = start_frame + (samples.len() / spec.channels as usize) as u64;
+        if end_frame > num_frames {
+            let err = io::Error::new(io::ErrorKind::InvalidInput,
+                                     "samples extend beyond the data chunk");
+            return Err(Error::IoError(err));
+        }
+
+        // Encode everything up front, so a sample that is too wide does not
+        // leave the file partially overwritten.
+        let mut buffer = Vec::with_capacity((end_frame - start_frame) as usize *
+                                            block_align as usize);
+        for &sample in samples {
+            sample.write_padded(&mut buffer, spec.bits_per_sample, bytes_per_sample)?;
+        }
+
+        let offset = data.offset + 8 + start_frame * block_align;
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        self.file.write_all(&buffer)?;
+        Ok(())
+    }
+
+    /// Shortens the data chunk to the given number of frames.
+    ///
+    /// The chunks after the data chunk are moved back, and the RIFF length is
+    /// updated, so trimming the end of a recording does not require copying
+    /// the audio data. Cue points at or past the new end are removed from the
+    /// `cue ` chunk, and the chunk is removed if no cue points remain.
+    ///
+    /// The underlying file is not truncated, so stale bytes remain after the
+    /// RIFF form. Readers ignore these bytes, but for a file opened with
+    /// `open()`, `discard_trailing_bytes()` can be used to remove them.
+    ///
+    /// If the data chunk holds fewer than `frames` frames, an error of kind
+    /// `InvalidInput` is returned.
+    pub fn truncate_to(&mut self, frames: u64) -> Result<()> {
+        let (spec, data) = self.data_layout()?;
+        let block_align = ((spec.bits_per_sample as u64 + 7) / 8) * spec.channels as u64;
+        let new_len = frames.saturating_mul(block_align);
+        if new_len > data.len as u64 {
+            let err = io::Error::new(io::ErrorKind::InvalidInput,
+                                     "cannot truncate beyond the end of the data chunk");
+            return Err(Error::IoError(err));
+        }
+        let new_len = new_len as u32;
+
+        // Remove the cue points that would point past the end.
+        let cue = match self.read_chunk(b"cue ")? {
+            Some(cue) => {
+                if cue.len() < 4 {
+                    return Err(Error::FormatError("invalid cue chunk"));
+                }
+                let mut points: Vec<&[u8]> = Vec::new();
+                for point in cue[4..].chunks(24).filter(|p| p.len() == 24) {
+                    // The field dwSampleOffset is the last field of a cue point.
+                    let position = io::Cursor::new(&point[20..]).read_le_u32()?;
+                    if (position as u64) < frames {
+                        points.push(point);
+                    }
+                }
+                let num_points = io::Cursor::new(&cue[..4]).read_le_u32()?;
+                if points.len() as u32 == num_points {
+                    None
+                } else {
+                    let mut new_cue = Vec::with_capacity(4 + points.len() * 24);
+                    new_cue.write_le_u32(points.len() as u32)?;
+                    for point in points {
+                        new_cue.extend_from_slice(point);
+                    }
+                    Some(new_cue)
+                }
+            }
+            None => None,
+        };
+
+        let tail_start = data.offset + chunk_size(data.len);
+        let tail_len = self.end - tail_start;
+        self.file.seek(io::SeekFrom::Start(tail_start))?;
+        let tail = self.file.read_bytes(tail_len as usize)?;
+
+        self.file.seek(io::SeekFrom::Start(data.offset + 4))?;
+        self.file.write_le_u32(new_len)?;
+        self.file.seek(io::SeekFrom::Start(data.offset + 8 + new_len as u64))?;
+        if new_len % 2 == 1 {
+            self.file.write_all(&[0])?;
+        }
+        self.file.write_all(&tail)?;
+        self.end = data.offset + chunk_size(new_len) + tail_len;
+        self.write_riff_len()?;
+        self.scan()?;
+
+        match cue {
+            Some(ref new_cue) if new_cue.len() > 4 => self.set_chunk(b"cue ", new_cue),
+            Some(_) => self.remove_chunk(b"cue ").map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes the underlying file and returns it.
+    pub fn into_inner(mut self) -> Result<F> {
+        self.file.flush()?;
+        Ok(self.file)
+    }
+}
+
+impl WavEditor<fs::File> {
+    /// Opens the file at the given path for editing.
+    pub fn open<P: AsRef<path::Path>>(path: P) -> Result<WavEditor<fs::File>> {
+        let file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+        WavEditor::new(file)
+    }
+
+    /// Truncates the file to the end of the RIFF form.
+    ///
+    /// This removes stale bytes that remain after shortening the file with
+    /// `truncate_to()`.
+    pub fn discard_trailing_bytes(&mut self) -> Result<()> {
+        self.file.set_len(self.end)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn edited_file() -> io::Cursor<Vec<u8>> {
+    use crate::testutil::WavBuilder;
+
+    let bytes = WavBuilder::new()
+        .fmt_pcm(1, 8000, 16)
+        .chunk(b"bext", &[1; 10])
+        .chunk(b"JUNK", &[0; 32])
+        .chunk(b"data", &[1, 0, 2, 0])
+        .chunk(b"cue ", &[2; 4])
+        .chunk(b"LIST", b"INFOabcd")
+        .build();
+    io::Cursor::new(bytes)
+}
+
+#[cfg(test)]
+fn chunk_ids<F: io::Read + io::Write + io::Seek>(editor: &WavEditor<F>) -> Vec<[u8; 4]> {
+    editor.chunks().iter().map(|c| c.id).collect()
+}
+
+#[test]
+fn editor_replaces_chunk_in_place() {
+    let mut editor = WavEditor::new(edited_file()).unwrap();
+    let data_offset = editor.chunks()[3].offset;
+
+    // Smaller than the original, the remainder is merged with the JUNK.
+    editor.set_chunk(b"bext", &[7; 1]).unwrap();
+    assert_eq!(editor.read_chunk(b"bext").unwrap().unwrap(), vec![7]);
+    assert_eq!(&chunk_ids(&editor)[..],
+               &[*b"fmt ", *b"bext", *b"JUNK", *b"data", *b"cue ", *b"LIST"]);
+
+    // Larger than the original, but the JUNK after it provides space.
+    editor.set_chunk(b"bext", &[8; 40]).unwrap();
+    assert_eq!(editor.read_chunk(b"bext").unwrap().unwrap(), vec![8; 40]);
+    assert_eq!(editor.chunks()[3].offset, data_offset);
+    assert_eq!(&editor.chunks()[3].id, b"data");
+
+    let bytes = editor.into_inner().unwrap().into_inner();
+    let mut reader = crate::read::WavReader::new(&bytes[..]).unwrap();
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1, 2]);
+}
+
+#[test]
+fn editor_uses_junk_before_data_for_new_chunks() {
+    let mut editor = WavEditor::new(edited_file()).unwrap();
+    let len = editor.end;
+    editor.set_chunk(b"iXML", &[3; 20]).unwrap();
+    assert_eq!(editor.end, len);
+    assert_eq!(&chunk_ids(&editor)[..],
+               &[*b"fmt ", *b"bext", *b"iXML", *b"JUNK", *b"data", *b"cue ", *b"LIST"]);
+    assert_eq!(editor.read_chunk(b"iXML").unwrap().unwrap(), vec![3; 20]);
+}
+
+#[test]
+fn editor_appends_or_shifts_after_data() {
+    let mut editor = WavEditor::new(edited_file()).unwrap();
+
+    // Too large for any JUNK, and before the data, so it must be appended.
+    editor.set_chunk(b"bext", &[4; 100]).unwrap();
+    assert_eq!(&chunk_ids(&editor)[..],
+               &[*b"fmt ", *b"JUNK", *b"JUNK", *b"data", *b"cue ", *b"LIST", *b"bext"]);
+
+    // The cue chunk is after the data, so the chunks after it shift.
+    editor.set_chunk(b"cue ", &[5; 50]).unwrap();
+    assert_eq!(&chunk_ids(&editor)[..],
+               &[*b"fmt ", *b"JUNK", *b"JUNK", *b"data", *b"cue ", *b"LIST", *b"bext"]);
+    assert_eq!(editor.read_chunk(b"cue ").unwrap().unwrap(), vec![5; 50]);
+    assert_eq!(editor.read_chunk(b"bext").unwrap().unwrap(), vec![4; 100]);
+    assert_eq!(editor.read_chunk(b"LIST").unwrap().unwrap(), b"INFOabcd".to_vec());
+
+    // A LIST chunk with a different list type is added, not replaced.
+    editor.set_chunk(b"LIST", b"adtl").unwrap();
+    assert_eq!(editor.chunks().iter().filter(|c| &c.id == b"LIST").count(), 2);
+
+    let bytes = editor.into_inner().unwrap().into_inner();
+    let riff_len = (bytes[4] as usize) | (bytes[5] as usize) << 8;
+    assert_eq!(riff_len + 8, bytes.len());
+}
+
+#[test]
+fn editor_removes_chunks() {
+    let mut editor = WavEditor::new(edited_file()).unwrap();
+    assert!(editor.remove_chunk(b"cue ").unwrap());
+    assert!(!editor.remove_chunk(b"cue ").unwrap());
+    assert_eq!(editor.read_chunk(b"cue ").unwrap(), None);
+    assert!(editor.remove_chunk(b"data").is_err());
+    assert!(editor.set_chunk(b"fmt ", &[0; 16]).is_err());
+}
+
+#[test]
+fn editor_overwrites_samples_in_place() {
+    use crate::testutil::WavBuilder;
+
+    let bytes = WavBuilder::new()
+        .fmt_pcm(2, 8000, 16)
+        .chunk(b"data", &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0])
+        .chunk(b"cue ", &[0; 4])
+        .build();
+    let len = bytes.len();
+
+    let mut editor = WavEditor::new(io::Cursor::new(bytes)).unwrap();
+    editor.overwrite_samples(1, &[-3_i16, -4]).unwrap();
+    match editor.overwrite_samples(0, &[0_i16]) {
+        Err(Error::UnfinishedSample) => {}
+        _ => panic!("UnfinishedSample error should have been returned."),
+    }
+    assert!(editor.overwrite_samples(2, &[0_i16, 0, 0, 0]).is_err());
+    assert!(editor.overwrite_samples(0, &[0_i32, 1 << 20]).is_err());
+
+    let bytes = editor.into_inner().unwrap().into_inner();
+    assert_eq!(bytes.len(), len);
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1, 2, -3, -4, 5, 6]);
+}
+
+#[test]
+fn editor_overwrites_samples_with_padding_bits() {
+    use crate::write::WavWriter;
+    use crate::{SampleFormat, WavSpecEx};
+
+    let spec_ex = WavSpecEx {
+        spec: WavSpec {
+            channels: 1,
+            sample_rate: 96_000,
+            bits_per_sample: 20,
+            sample_format: SampleFormat::Int,
+        },
+        bytes_per_sample: 3,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new_with_spec_ex(&mut buffer, spec_ex).unwrap();
+        writer.write_samples(&[1_i32, 2, 3]).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    let mut editor = WavEditor::new(buffer).unwrap();
+    editor.overwrite_samples(1, &[-524_288_i32]).unwrap();
+    assert!(editor.overwrite_samples(2, &[524_288_i32]).is_err());
+
+    let bytes = editor.into_inner().unwrap().into_inner();
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.spec_ex(), spec_ex);
+    let samples: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1, -524_288, 3]);
+}
+
+#[test]
+fn editor_truncates_data_and_cue_points() {
+    use crate::testutil::WavBuilder;
+
+    // Cue points at frames 1 and 3.
+    let mut cue = vec![2, 0, 0, 0];
+    for &(name, frame) in &[(1u8, 1u8), (2, 3)] {
+        cue.extend_from_slice(&[name, 0, 0, 0, frame, 0, 0, 0]);
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, frame, 0, 0, 0]);
+    }
+
+    let bytes = WavBuilder::new()
+        .fmt_pcm(1, 8000, 8)
+        .chunk(b"data", &[129, 130, 131, 132, 133])
+        .chunk(b"cue ", &cue)
+        .chunk(b"LIST", b"INFOabcd")
+        .build();
+
+    let mut editor = WavEditor::new(io::Cursor::new(bytes)).unwrap();
+    assert!(editor.truncate_to(6).is_err());
+    editor.truncate_to(3).unwrap();
+    assert_eq!(&chunk_ids(&editor)[..],
+               &[*b"fmt ", *b"data", *b"cue ", *b"JUNK", *b"LIST"]);
+    assert_eq!(editor.read_chunk(b"LIST").unwrap().unwrap(), b"INFOabcd".to_vec());
+    assert_eq!(editor.read_chunk(b"cue ").unwrap().unwrap(), {
+        let mut expected = vec![1, 0, 0, 0];
+        expected.extend_from_slice(&cue[4..28]);
+        expected
+    });
+
+    editor.truncate_to(1).unwrap();
+    assert_eq!(&chunk_ids(&editor)[..],
+               &[*b"fmt ", *b"data", *b"JUNK", *b"JUNK", *b"LIST"]);
+
+    let end = editor.end as usize;
+    let bytes = editor.into_inner().unwrap().into_inner();
+    let riff_len = (bytes[4] as usize) | (bytes[5] as usize) << 8;
+    assert_eq!(riff_len + 8, end);
+    let mut reader = WavReader::new(&bytes[..end]).unwrap();
+    let samples: Vec<i8> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1]);
+}
@@ -0,0 +1,333 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The error type of Hound, and its categories.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// The error type for operations on `WavReader` and `WavWriter`.
+#[derive(Debug)]
+pub enum Error {
+    /// An IO error occured in the underlying reader or writer.
+    IoError(io::Error),
+    /// Ill-formed WAVE data was encountered.
+    FormatError(&'static str),
+    /// The sample has more bits than the destination type.
+    ///
+    /// When iterating using the `samples` iterator, this means that the
+    /// destination type (produced by the iterator) is not wide enough to hold
+    /// the sample. When writing, this means that the sample cannot be written,
+    /// because it requires more bits than the bits per sample specified.
+    TooWide,
+    /// The number of samples written is not a multiple of the number of channels.
+    UnfinishedSample,
+    /// The format is not supported.
+    Unsupported,
+    /// The sample format is different than the destination format.
+    ///
+    /// When iterating using the `samples` iterator, this means the destination
+    /// type (produced by the iterator) has a different sample format than the
+    /// samples in the wav file.
+    ///
+    /// For example, this will occur if the user attempts to produce `i32`
+    /// samples (which have a `SampleFormat::Int`) from a wav file that
+    /// contains floating point data (`SampleFormat::Float`).
+    InvalidSampleFormat,
+    /// A sample at full scale was written while clip detection was set to
+    /// `ClipDetection::Error`.
+    Clipped,
+    /// The data chunk would exceed the 4 GiB that a RIFF file can describe,
+    /// while the size limit was set to `SizeLimit::Error`.
+    TooLarge,
+    /// The file ended before the data chunk did.
+    ///
+    /// The header promised `expected_samples` samples, but only `got` samples
+    /// could be read. This is usually the result of a truncated file, such as
+    /// a recording that was interrupted. The samples that were read are valid,
+    /// so a caller can choose to accept the truncated audio.
+    UnexpectedEof {
+        /// The number of samples in the data chunk according to its header.
+        expected_samples: u64,
+        /// The number of samples that were read before the end of the file.
+        got: u64,
+    },
+    /// The fmt chunk specifies zero channels.
+    ZeroChannels,
+    /// The fmt chunk specifies a sample rate of zero.
+    ZeroSampleRate,
+    /// The fmt chunk specifies a block align (bytes per frame) of zero.
+    ZeroBlockAlign,
+    /// The fmt chunk specifies more than 64 bits per sample.
+    ///
+    /// No sample format has more than 64 bits, so such a file is corrupt
+    /// rather than merely unsupported.
+    BitsPerSampleTooLarge(u16),
+    /// No data chunk was found within the limits set for the header.
+    ///
+    /// See `HeaderLimits`. This usually means the stream is not a WAVE file
+    /// at all, or it has an unreasonable amount of metadata.
+    NoDataChunkFound,
+    /// The storage device is full.
+    ///
+    /// This is an IO error that is singled out, because a recorder may want
+    /// to handle it differently from other failures, for instance by
+    /// continuing on a different device. See also
+    /// `WavWriter::fits_in_free_space()`.
+    DiskFull(io::Error),
+    /// The file ended in the middle of a frame.
+    ///
+    /// Returned by `WavReader::read_exact_frames()` instead of
+    /// `UnexpectedEof` when a truncated file ends with an incomplete frame.
+    /// The buffer holds `frames` complete frames, followed by the `samples`
+    /// samples of the incomplete frame, which should not be processed as if
+    /// the frame were whole.
+    PartialFrame {
+        /// The number of complete frames that were read.
+        frames: usize,
+        /// The number of samples of the incomplete frame.
+        samples: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        match *self {
+            Error::IoError(ref err) => err.fmt(formatter),
+            Error::FormatError(reason) => {
+                formatter.write_str("Ill-formed WAVE file: ")?;
+                formatter.write_str(reason)
+            }
+            Error::TooWide => {
+                formatter.write_str("The sample has more bits than the destination type.")
+            }
+            Error::UnfinishedSample => {
+                formatter.write_str(
+                    "The number of samples written is not a multiple of the number of channels.")
+            }
+            Error::Unsupported => {
+                formatter.write_str("The wave format of the file is not supported.")
+            }
+            Error::InvalidSampleFormat => {
+                formatter.write_str("The sample format differs from the destination format.")
+            }
+            Error::Clipped => {
+                formatter.write_str("The sample is at full scale and may have clipped.")
+            }
+            Error::TooLarge => {
+                formatter.write_str("The data does not fit in a RIFF file.")
+            }
+            Error::UnexpectedEof { expected_samples, got } => {
+                write!(formatter, "The file ended after {} of {} samples.", got, expected_samples)
+            }
+            Error::ZeroChannels => {
+                formatter.write_str("The file specifies zero channels.")
+            }
+            Error::ZeroSampleRate => {
+                formatter.write_str("The file specifies a sample rate of zero.")
+            }
+            Error::ZeroBlockAlign => {
+                formatter.write_str("The file specifies a block align of zero.")
+            }
+            Error::BitsPerSampleTooLarge(bits) => {
+                write!(formatter, "The file specifies {} bits per sample, more than 64.", bits)
+            }
+            Error::NoDataChunkFound => {
+                formatter.write_str("No data chunk was found within the header limits.")
+            }
+            Error::DiskFull(ref err) => {
+                formatter.write_str("The storage device is full: ")?;
+                err.fmt(formatter)
+            }
+            Error::PartialFrame { frames, samples } => {
+                write!(formatter, "The file ended with a partial frame of {} samples after {} frames.",
+                       samples, frames)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    #[allow(deprecated)]
+    fn description(&self) -> &str {
+        match *self {
+            Error::IoError(ref err) => err.description(),
+            Error::FormatError(reason) => reason,
+            Error::TooWide => "the sample has more bits than the destination type",
+            Error::UnfinishedSample => "the number of samples written is not a multiple of the number of channels",
+            Error::Unsupported => "the wave format of the file is not supported",
+            Error::InvalidSampleFormat => "the sample format differs from the destination format",
+            Error::Clipped => "the sample is at full scale and may have clipped",
+            Error::TooLarge => "the data does not fit in a RIFF file",
+            Error::UnexpectedEof { .. } => "the file ended before all samples were read",
+            Error::ZeroChannels => "the file specifies zero channels",
+            Error::ZeroSampleRate => "the file specifies a sample rate of zero",
+            Error::ZeroBlockAlign => "the file specifies a block align of zero",
+            Error::BitsPerSampleTooLarge(_) => "the file specifies more than 64 bits per sample",
+            Error::NoDataChunkFound => "no data chunk was found within the header limits",
+            Error::DiskFull(_) => "the storage device is full",
+            Error::PartialFrame { .. } => "the file ended in the middle of a frame",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Error::IoError(ref err) => Some(err),
+            Error::FormatError(_) => None,
+            Error::TooWide => None,
+            Error::UnfinishedSample => None,
+            Error::Unsupported => None,
+            Error::InvalidSampleFormat => None,
+            Error::Clipped => None,
+            Error::TooLarge => None,
+            Error::UnexpectedEof { .. } => None,
+            Error::ZeroChannels => None,
+            Error::ZeroSampleRate => None,
+            Error::ZeroBlockAlign => None,
+            Error::BitsPerSampleTooLarge(_) => None,
+            Error::NoDataChunkFound => None,
+            Error::DiskFull(ref err) => Some(err),
+            Error::PartialFrame { .. } => None,
+        }
+    }
+}
+
+/// Converts the error into an IO error, for use in `io::Read` and `io::Write` implementations.
+///
+/// IO errors are returned unchanged. A file that ends early becomes an error
+/// of kind `UnexpectedEof`, and all other errors become an error of kind
+/// `InvalidData`, with the original error as its inner error.
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::IoError(err) | Error::DiskFull(err) => err,
+            Error::UnexpectedEof { .. } | Error::PartialFrame { .. } => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, err)
+            }
+            _ => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// A coarse category of an `Error`, returned by `Error::kind()`.
+///
+/// This allows mapping errors onto responses or retry policies without
+/// matching on every variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The data is not a well-formed WAVE file, or it is truncated.
+    Format,
+    /// The underlying reader or writer failed.
+    Io {
+        /// Whether the operation may succeed when retried.
+        retryable: bool,
+    },
+    /// The file is well-formed, but uses a format that Hound does not support.
+    Unsupported,
+    /// The sample has more bits than the destination type.
+    TooWide,
+    /// The caller passed samples or requested an operation that is invalid for the spec.
+    InvalidInput,
+}
+
+impl Error {
+    /// Returns the category of the error.
+    ///
+    /// An IO error is retryable if it is of kind `Interrupted`, `WouldBlock`,
+    /// or `TimedOut`. After such an error, reading samples can be resumed.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::IoError(ref err) => ErrorKind::Io { retryable: is_retryable(err.kind()) },
+            Error::DiskFull(_) => ErrorKind::Io { retryable: false },
+            Error::FormatError(_) |
+            Error::UnexpectedEof { .. } |
+            Error::PartialFrame { .. } |
+            Error::ZeroChannels |
+            Error::ZeroSampleRate |
+            Error::ZeroBlockAlign |
+            Error::BitsPerSampleTooLarge(_) |
+            Error::NoDataChunkFound => ErrorKind::Format,
+            Error::Unsupported => ErrorKind::Unsupported,
+            Error::TooWide => ErrorKind::TooWide,
+            Error::UnfinishedSample |
+            Error::InvalidSampleFormat |
+            Error::Clipped |
+            Error::TooLarge => ErrorKind::InvalidInput,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        if is_disk_full(&err) {
+            Error::DiskFull(err)
+        } else {
+            Error::IoError(err)
+        }
+    }
+}
+
+/// Returns whether an IO error of this kind may succeed when retried.
+pub(crate) fn is_retryable(kind: io::ErrorKind) -> bool {
+    matches!(kind, io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// Returns whether the IO error indicates that the storage device is full.
+fn is_disk_full(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        // ENOSPC is 28 on Linux, macOS, and the BSDs.
+        Some(code) if cfg!(unix) => code == 28,
+        // ERROR_HANDLE_DISK_FULL and ERROR_DISK_FULL.
+        Some(code) if cfg!(windows) => code == 39 || code == 112,
+        _ => false,
+    }
+}
+
+#[test]
+fn errors_are_categorized_and_converted() {
+    let interrupted = Error::from(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+    assert_eq!(interrupted.kind(), ErrorKind::Io { retryable: true });
+    let broken = Error::from(io::Error::new(io::ErrorKind::BrokenPipe, "broken"));
+    assert_eq!(broken.kind(), ErrorKind::Io { retryable: false });
+    assert_eq!(Error::FormatError("no RIFF tag found").kind(), ErrorKind::Format);
+    assert_eq!(Error::Unsupported.kind(), ErrorKind::Unsupported);
+    assert_eq!(Error::TooWide.kind(), ErrorKind::TooWide);
+    assert_eq!(Error::UnfinishedSample.kind(), ErrorKind::InvalidInput);
+
+    assert_eq!(io::Error::from(broken).kind(), io::ErrorKind::BrokenPipe);
+    let eof = Error::UnexpectedEof { expected_samples: 2, got: 1 };
+    assert_eq!(io::Error::from(eof).kind(), io::ErrorKind::UnexpectedEof);
+    let err = io::Error::from(Error::TooWide);
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match err.into_inner().map(|inner| inner.downcast::<Error>()) {
+        Some(Ok(inner)) => assert_eq!(inner.kind(), ErrorKind::TooWide),
+        _ => panic!("Expected the original error as the inner error."),
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn disk_full_is_singled_out() {
+    match Error::from(io::Error::from_raw_os_error(28)) {
+        Error::DiskFull(_) => {}
+        other => panic!("Expected DiskFull, got {:?}.", other),
+    }
+    match Error::from(io::Error::from_raw_os_error(5)) {
+        Error::IoError(_) => {}
+        other => panic!("Expected IoError, got {:?}.", other),
+    }
+}
+
+/// A type for results generated by Hound where the error type is hard-wired.
+pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,184 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write;
+use std::io;
+use crate::read::WavReader;
+use super::{Error, SampleFormat};
+
+/// The number of samples that are listed literally in the dump.
+const HEAD_LEN: usize = 8;
+
+/// Appends `s` as a JSON string literal.
+fn push_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                write!(out, "\\u{:04x}", c as u32).unwrap();
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Appends a chunk id as a JSON string, with bytes outside of ASCII escaped.
+fn push_json_id(out: &mut String, id: &[u8; 4]) {
+    let s: String = id.iter().map(|&b| if b < 0x80 { b as char } else { '\u{fffd}' }).collect();
+    push_json_str(out, &s);
+}
+
+/// Appends a float, as a string if it is not finite, which JSON cannot represent.
+#[cfg(feature = "float")]
+fn push_json_f32(out: &mut String, x: f32) {
+    if x.is_finite() {
+        write!(out, "{:?}", x).unwrap();
+    } else {
+        push_json_str(out, &format!("{:?}", x));
+    }
+}
+
+/// Accumulates decoded samples as a count, a hash, and the first few samples.
+struct SampleSummary {
+    count: u64,
+    hash: u64,
+    head: String,
+}
+
+impl SampleSummary {
+    fn new() -> SampleSummary {
+        SampleSummary {
+            count: 0,
+            // The FNV-1a offset basis.
+            hash: 0xcbf2_9ce4_8422_2325,
+            head: String::new(),
+        }
+    }
+
+    fn push(&mut self, bits: u32, literal: &dyn Fn(&mut String)) {
+        for &byte in &bits.to_le_bytes() {
+            self.hash = (self.hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        if (self.count as usize) < HEAD_LEN {
+            if self.count > 0 {
+                self.head.push_str(", ");
+            }
+            literal(&mut self.head);
+        }
+        self.count += 1;
+    }
+}
+
+/// Decodes a file and describes the result as JSON, for golden-file tests.
+///
+/// The description contains the spec, the chunks, and the number of samples
+/// with a hash and the first few of them, or the error that occurred. The
+/// format is not stable, it serves to detect changes in decoding behavior.
+#[doc(hidden)]
+pub fn dump_json<R: io::Read + io::Seek>(reader: R) -> String {
+    let mut out = String::new();
+    let mut reader = match WavReader::new_seekable(reader) {
+        Ok(reader) => reader,
+        Err(err) => {
+            out.push_str("{\n  \"error\": ");
+            push_json_str(&mut out, &err.to_string());
+            out.push_str("\n}\n");
+            return out;
+        }
+    };
+
+    let spec_ex = reader.spec_ex();
+    let spec = spec_ex.spec;
+    let format = match spec.sample_format {
+        SampleFormat::Int => "int",
+        SampleFormat::Float => "float",
+    };
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "  \"channels\": {},", spec.channels).unwrap();
+    writeln!(out, "  \"sample_rate\": {},", spec.sample_rate).unwrap();
+    writeln!(out, "  \"bits_per_sample\": {},", spec.bits_per_sample).unwrap();
+    writeln!(out, "  \"bytes_per_sample\": {},", spec_ex.bytes_per_sample).unwrap();
+    writeln!(out, "  \"sample_format\": \"{}\",", format).unwrap();
+    match reader.channel_mask() {
+        Some(mask) => writeln!(out, "  \"channel_mask\": {},", mask.0).unwrap(),
+        None => writeln!(out, "  \"channel_mask\": null,").unwrap(),
+    }
+    writeln!(out, "  \"riff_len\": {},", reader.riff_len()).unwrap();
+    out.push_str("  \"chunks\": [");
+    for (i, chunk) in reader.file_map().iter().enumerate() {
+        out.push_str(if i == 0 { "\n    {\"id\": " } else { ",\n    {\"id\": " });
+        push_json_id(&mut out, &chunk.id);
+        write!(out, ", \"offset\": {}, \"len\": {}}}", chunk.offset, chunk.len).unwrap();
+    }
+    out.push_str("\n  ],\n");
+    writeln!(out, "  \"len\": {},", reader.num_samples()).unwrap();
+
+    let mut summary = SampleSummary::new();
+    let mut error: Option<Error> = None;
+    match spec.sample_format {
+        SampleFormat::Int => {
+            for sample in reader.samples::<i32>() {
+                match sample {
+                    Ok(x) => summary.push(x as u32, &|out| write!(out, "{}", x).unwrap()),
+                    Err(err) => { error = Some(err); break; }
+                }
+            }
+        }
+        #[cfg(feature = "float")]
+        SampleFormat::Float => {
+            for sample in reader.samples::<f32>() {
+                match sample {
+                    Ok(x) => summary.push(x.to_bits(), &|out| push_json_f32(out, x)),
+                    Err(err) => { error = Some(err); break; }
+                }
+            }
+        }
+        #[cfg(not(feature = "float"))]
+        SampleFormat::Float => error = Some(Error::Unsupported),
+    }
+
+    writeln!(out, "  \"samples\": {},", summary.count).unwrap();
+    writeln!(out, "  \"fnv1a\": \"{:016x}\",", summary.hash).unwrap();
+    writeln!(out, "  \"head\": [{}],", summary.head).unwrap();
+    out.push_str("  \"error\": ");
+    match error {
+        Some(err) => push_json_str(&mut out, &err.to_string()),
+        None => out.push_str("null"),
+    }
+    out.push_str("\n}\n");
+    out
+}
+
+#[test]
+fn dump_json_describes_file_or_error() {
+    use crate::testutil::WavBuilder;
+
+    let bytes = WavBuilder::new()
+        .fmt_pcm(1, 8000, 16)
+        .chunk(b"data", &[2, 0, 253, 255])
+        .build();
+    let json = dump_json(io::Cursor::new(bytes));
+    assert!(json.contains("\"sample_format\": \"int\",\n"));
+    assert!(json.contains("{\"id\": \"data\", \"offset\": 36, \"len\": 4}"));
+    assert!(json.contains("\"head\": [2, -3],\n"));
+    assert!(json.ends_with("\"error\": null\n}\n"));
+
+    let json = dump_json(io::Cursor::new(b"RIFF\x04\x00\x00\x00AVI ".to_vec()));
+    assert!(json.starts_with("{\n  \"error\": \"Ill-formed WAVE file: "));
+
+    let mut escaped = String::new();
+    push_json_str(&mut escaped, "a\"b\\c\n");
+    assert_eq!(escaped, "\"a\\\"b\\\\c\\u000a\"");
+}
@@ -50,23 +50,110 @@
 //!     let sample = s.unwrap() as f64;
 //!     sqr_sum + sample * sample
 //! });
-//! println!("RMS is {}", (sqr_sum / reader.len() as f64).sqrt());
+//! println!("RMS is {}", (sqr_sum / reader.num_samples() as f64).sqrt());
 //! ```
 
 #![warn(missing_docs)]
 
-use std::error;
 use std::fmt;
 use std::io;
 use std::result;
-use read::ReadExt;
-use write::WriteExt;
+use std::str;
 
-mod read;
-mod write;
+use crate::convert::{is_full_scale_int, narrow_to_i16, narrow_to_i24, narrow_to_i8, signed_from_u8,
+                     u8_from_signed};
 
-pub use read::{WavReader, WavIntoSamples, WavSamples};
-pub use write::{SampleWriter16, WavWriter};
+// Instrumentation. With the `tracing` feature, these forward to the `tracing`
+// crate, at the given level (`TRACE`, `DEBUG`, ...). Without it, they expand
+// to nothing, so their arguments must not be the only use of a variable.
+
+/// Emits an event, as `tracing::event!`.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($level:ident, $($args:tt)*) => {
+        ::tracing::event!(::tracing::Level::$level, $($args)*)
+    }
+}
+
+/// Enters a span until the end of the enclosing block, as `tracing::span!`.
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($level:ident, $($args:tt)*) => {
+        let _span = ::tracing::span!(::tracing::Level::$level, $($args)*).entered();
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($($args:tt)*) => {}
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($args:tt)*) => {}
+}
+
+mod compare;
+mod edit;
+mod golden;
+mod md5;
+#[cfg(feature = "metadata")]
+mod metadata;
+#[cfg(feature = "conversion")]
+mod parallel;
+#[cfg(feature = "conversion")]
+mod pcm;
+mod remux;
+mod sink;
+mod soundbank;
+mod stream;
+mod typed;
+
+#[cfg(feature = "batch")]
+pub mod batch;
+pub mod chunks;
+pub mod codec;
+pub mod convert;
+pub mod error;
+#[cfg(feature = "analysis")]
+pub mod meter;
+#[cfg(feature = "analysis")]
+pub mod overview;
+pub mod prelude;
+pub mod read;
+#[cfg(feature = "analysis")]
+pub mod signal;
+pub mod write;
+
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+
+pub use crate::chunks::{ChunkInfo, RiffChunk, RiffReader, RiffWriter};
+pub use crate::compare::{compare, Comparison};
+pub use crate::convert::{f32_to_int, f64_to_int, int_to_f32, int_to_f64};
+pub use crate::edit::WavEditor;
+pub use crate::error::{Error, ErrorKind, Result};
+#[doc(hidden)]
+pub use crate::golden::dump_json;
+#[cfg(feature = "metadata")]
+pub use crate::metadata::{BroadcastExtension, InfoEntry, ListChunk, MetadataTemplate, TextEncoding};
+#[cfg(feature = "conversion")]
+pub use crate::parallel::{Dither, ParallelWriter};
+#[cfg(feature = "conversion")]
+pub use crate::pcm::{PcmFormat, PcmRead, PcmWrite};
+pub use crate::read::{duration_of, ChunkReader, Consistency, HeaderLimits, PassthroughFormat, ReadExt, WavBlocks,
+               WavChannelSamples, WavFramesFixed, WavProbe, WavReader, WavIntoSamples, WavSamples,
+               WavSamplesEnumerated, WavI16Samples, WavMonoSamples, WavUncheckedSamples, WavWindows,
+               SubReader};
+pub use crate::remux::{export_region, remux, RemuxOptions};
+pub use crate::sink::{WavSink, WavSinkDrain};
+pub use crate::soundbank::{find_wav_forms, split_wav_files};
+pub use crate::stream::{StreamEvent, WavStream};
+pub use crate::typed::WavWriter16;
+#[cfg(feature = "float")]
+pub use crate::typed::WavWriterF32;
+pub use crate::write::{ClipDetection, HeaderLayout, SampleWriter16, SizeLimit, WavAtomicWriter, WavMemoryWriter,
+                WavWriter, WriteExt};
 
 /// A type that can be used to represent audio samples.
 ///
@@ -80,194 +167,274 @@ pub trait Sample: Sized {
     /// Writes the audio sample to the WAVE data chunk.
     fn write<W: io::Write>(self, writer: &mut W, bits: u16) -> Result<()>;
 
+    /// Writes the audio sample with `bits` valid bits in `byte_width` bytes.
+    ///
+    /// The sample must fit in `bits` bits. It is shifted so the valid bits are
+    /// the most significant bits of the container, and the padding bits are
+    /// zero. The default implementation only supports containers without
+    /// padding, and returns `Error::Unsupported` otherwise.
+    fn write_padded<W: io::Write>(self, writer: &mut W, bits: u16, byte_width: u16) -> Result<()> {
+        if bits as u32 != byte_width as u32 * 8 {
+            return Err(Error::Unsupported);
+        }
+        self.write(writer, bits)
+    }
+
+    /// Like `write_padded()`, but the range of the sample is only checked in debug builds.
+    ///
+    /// In debug builds, a sample that does not fit in `bits` bits causes a
+    /// panic. In release builds, the excess high bits are discarded, so the
+    /// value wraps around. The default implementation calls `write_padded()`.
+    fn write_padded_unchecked<W: io::Write>(self,
+                                            writer: &mut W,
+                                            bits: u16,
+                                            byte_width: u16)
+                                            -> Result<()> {
+        self.write_padded(writer, bits, byte_width)
+    }
+
     /// Reads the audio sample from the WAVE data chunk.
-    fn read<R: io::Read>(reader: &mut R, SampleFormat, bytes: u16, bits: u16) -> Result<Self>;
+    fn read<R: io::Read>(reader: &mut R, _: SampleFormat, bytes: u16, bits: u16) -> Result<Self>;
 
     /// Cast the sample to a 16-bit sample.
     ///
     /// This does not change the value of the sample, it only casts it. The
     /// value is assumed to fit within the range. This is not verified,
     /// truncation may occur.
+    #[allow(clippy::wrong_self_convention)]
     fn as_i16(self) -> i16;
-}
-
-/// Converts an unsigned integer in the range 0-255 to a signed one in the range -128-127.
-///
-/// Presumably, the designers of the WAVE format did not like consistency. For
-/// all bit depths except 8, samples are stored as little-endian _signed_
-/// integers. However, an 8-bit sample is instead stored as an _unsigned_
-/// integer. Hound abstracts away this idiosyncrasy by providing only signed
-/// sample types.
-fn signed_from_u8(x: u8) -> i8 {
-    (x as i16 - 128) as i8
-}
-
-/// Converts a signed integer in the range -128-127 to an unsigned one in the range 0-255.
-fn u8_from_signed(x: i8) -> u8 {
-    (x as i16 + 128) as u8
-}
 
-#[test]
-fn u8_sign_conversion_is_bijective() {
-    for x in 0..255 {
-        assert_eq!(x, u8_from_signed(signed_from_u8(x)));
-    }
-    for x in -128..127 {
-        assert_eq!(x, signed_from_u8(u8_from_signed(x)));
+    /// Returns whether the sample is at full scale when stored with `bits` bits.
+    ///
+    /// For integer samples this is true for the minimum and maximum value
+    /// that fits in `bits` bits, and for values outside of that range. For
+    /// float samples this is true when the magnitude is 1.0 or more.
+    ///
+    /// The default implementation returns false, so clip detection never
+    /// triggers for sample types outside of Hound.
+    fn is_full_scale(&self, _bits: u16) -> bool {
+        false
     }
-}
 
-/// Tries to cast the sample to an 8-bit signed integer, returning an error on overflow.
-#[inline(always)]
-fn narrow_to_i8(x: i32) -> Result<i8> {
-    use std::i8;
-    if x < i8::MIN as i32 || x > i8::MAX as i32 {
-        Err(Error::TooWide)
-    } else {
-        Ok(x as i8)
+    /// Returns which of the sample types of Hound this type is.
+    ///
+    /// This is used to check up front whether a file can be decoded into the
+    /// type, see `WavReader::check_sample_type()`. The default implementation
+    /// returns `None`, in which case that check always passes, and errors
+    /// occur when samples are read.
+    fn sample_type() -> Option<SampleType> {
+        None
     }
 }
 
-#[test]
-fn verify_narrow_to_i8() {
-    assert!(narrow_to_i8(127).is_ok());
-    assert!(narrow_to_i8(128).is_err());
-    assert!(narrow_to_i8(-128).is_ok());
-    assert!(narrow_to_i8(-129).is_err());
-}
-
-/// Tries to cast the sample to a 16-bit signed integer, returning an error on overflow.
+/// Reads an integer sample with `bits` valid bits, stored in `bytes` bytes.
+///
+/// When the number of bits is smaller than the container, the valid bits are
+/// the most significant ones, and the padding bits are shifted out. A 12-bit
+/// sample in a 16-bit container is therefore returned in the range
+/// -2048-2047. Nothing is read if the layout is not supported.
 #[inline(always)]
-fn narrow_to_i16(x: i32) -> Result<i16> {
-    use std::i16;
-    if x < i16::MIN as i32 || x > i16::MAX as i32 {
-        Err(Error::TooWide)
-    } else {
-        Ok(x as i16)
+fn read_int_sample<R: io::Read>(reader: &mut R, bytes: u16, bits: u16) -> Result<i32> {
+    if bytes > 4 || bits == 0 || bits > bytes * 8 {
+        return Err(Error::Unsupported);
     }
+    let container = match bytes {
+        1 => reader.read_u8().map(signed_from_u8)? as i32,
+        2 => reader.read_le_i16()? as i32,
+        3 => reader.read_le_i24()?,
+        _ => reader.read_le_i32()?,
+    };
+    Ok(container >> (bytes * 8 - bits))
 }
 
-#[test]
-fn verify_narrow_to_i16() {
-    assert!(narrow_to_i16(32767).is_ok());
-    assert!(narrow_to_i16(32768).is_err());
-    assert!(narrow_to_i16(-32768).is_ok());
-    assert!(narrow_to_i16(-32769).is_err());
-}
-
-/// Tries to cast the sample to a 24-bit signed integer, returning an error on overflow.
-#[inline(always)]
-fn narrow_to_i24(x: i32) -> Result<i32> {
-    if x < -(1 << 23) || x > (1 << 23) - 1 {
-        Err(Error::TooWide)
-    } else {
-        Ok(x)
+/// Writes an integer sample like `write_int_sample`, checking its range only in debug builds.
+fn write_int_sample_unchecked<W: io::Write>(writer: &mut W, x: i32, bits: u16, bytes: u16) -> Result<()> {
+    if bytes > 4 || bits == 0 || bits > bytes * 8 {
+        return Err(Error::Unsupported);
+    }
+    debug_assert!(bits >= 32 || (x >= -(1 << (bits - 1)) && x < 1 << (bits - 1)),
+                  "sample {} does not fit in {} bits", x, bits);
+    // Shifting discards the excess high bits, so an out of range sample
+    // wraps around rather than spilling into the padding bits.
+    let container = ((x as u32) << (32 - bits) >> (32 - bytes * 8)) as i32;
+    match bytes {
+        1 => Ok(writer.write_u8(u8_from_signed(container as i8))?),
+        2 => Ok(writer.write_le_i16(container as i16)?),
+        3 => Ok(writer.write_le_i24(container)?),
+        _ => Ok(writer.write_le_i32(container)?),
     }
 }
 
-#[test]
-fn verify_narrow_to_i24() {
-    assert!(narrow_to_i24(8_388_607).is_ok());
-    assert!(narrow_to_i24(8_388_608).is_err());
-    assert!(narrow_to_i24(-8_388_608).is_ok());
-    assert!(narrow_to_i24(-8_388_609).is_err());
+/// Writes an integer sample with `bits` valid bits in a container of `bytes` bytes.
+///
+/// This is the counterpart of `read_int_sample`: the valid bits are stored in
+/// the most significant bits of the container. Nothing is written if the
+/// sample does not fit, or if the layout is not supported.
+fn write_int_sample<W: io::Write>(writer: &mut W, x: i32, bits: u16, bytes: u16) -> Result<()> {
+    if bytes > 4 || bits == 0 || bits > bytes * 8 {
+        return Err(Error::Unsupported);
+    }
+    if bits < 32 && (x < -(1 << (bits - 1)) || x >= 1 << (bits - 1)) {
+        return Err(Error::TooWide);
+    }
+    let container = x << (bytes * 8 - bits);
+    match bytes {
+        1 => Ok(writer.write_u8(u8_from_signed(container as i8))?),
+        2 => Ok(writer.write_le_i16(container as i16)?),
+        3 => Ok(writer.write_le_i24(container)?),
+        _ => Ok(writer.write_le_i32(container)?),
+    }
 }
 
 impl Sample for i8 {
     fn write<W: io::Write>(self, writer: &mut W, bits: u16) -> Result<()> {
         match bits {
-            8 => Ok(try!(writer.write_u8(u8_from_signed(self)))),
-            16 => Ok(try!(writer.write_le_i16(self as i16))),
-            24 => Ok(try!(writer.write_le_i24(self as i32))),
-            32 => Ok(try!(writer.write_le_i32(self as i32))),
+            8 => Ok(writer.write_u8(u8_from_signed(self))?),
+            16 => Ok(writer.write_le_i16(self as i16)?),
+            24 => Ok(writer.write_le_i24(self as i32)?),
+            32 => Ok(writer.write_le_i32(self as i32)?),
             _ => Err(Error::Unsupported),
         }
     }
 
+    fn write_padded<W: io::Write>(self, writer: &mut W, bits: u16, byte_width: u16) -> Result<()> {
+        write_int_sample(writer, self as i32, bits, byte_width)
+    }
+
+    fn write_padded_unchecked<W: io::Write>(self,
+                                            writer: &mut W,
+                                            bits: u16,
+                                            byte_width: u16)
+                                            -> Result<()> {
+        write_int_sample_unchecked(writer, self as i32, bits, byte_width)
+    }
+
     #[inline(always)]
     fn as_i16(self) -> i16 {
         self as i16
     }
 
+    #[inline(always)]
+    fn is_full_scale(&self, bits: u16) -> bool {
+        is_full_scale_int(*self as i32, bits)
+    }
+
+    fn sample_type() -> Option<SampleType> {
+        Some(SampleType::I8)
+    }
+
     fn read<R: io::Read>(reader: &mut R, fmt: SampleFormat, bytes: u16, bits: u16) -> Result<i8> {
         if fmt != SampleFormat::Int {
             return Err(Error::InvalidSampleFormat);
         }
-        match (bytes, bits) {
-            (1, 8) => Ok(try!(reader.read_u8().map(signed_from_u8))),
-            (n, _) if n > 1 => Err(Error::TooWide),
-            // TODO: add a genric decoder for any bit depth.
-            _ => Err(Error::Unsupported),
+        if bits > 8 {
+            return Err(Error::TooWide);
         }
+        read_int_sample(reader, bytes, bits).map(|x| x as i8)
     }
 }
 
 impl Sample for i16 {
     fn write<W: io::Write>(self, writer: &mut W, bits: u16) -> Result<()> {
         match bits {
-            8 => Ok(try!(writer.write_u8(u8_from_signed(try!(narrow_to_i8(self as i32)))))),
-            16 => Ok(try!(writer.write_le_i16(self))),
-            24 => Ok(try!(writer.write_le_i24(self as i32))),
-            32 => Ok(try!(writer.write_le_i32(self as i32))),
+            8 => Ok(writer.write_u8(u8_from_signed(narrow_to_i8(self as i32)?))?),
+            16 => Ok(writer.write_le_i16(self)?),
+            24 => Ok(writer.write_le_i24(self as i32)?),
+            32 => Ok(writer.write_le_i32(self as i32)?),
             _ => Err(Error::Unsupported),
         }
     }
 
+    fn write_padded<W: io::Write>(self, writer: &mut W, bits: u16, byte_width: u16) -> Result<()> {
+        write_int_sample(writer, self as i32, bits, byte_width)
+    }
+
+    fn write_padded_unchecked<W: io::Write>(self,
+                                            writer: &mut W,
+                                            bits: u16,
+                                            byte_width: u16)
+                                            -> Result<()> {
+        write_int_sample_unchecked(writer, self as i32, bits, byte_width)
+    }
+
     #[inline(always)]
     fn as_i16(self) -> i16 {
         self
     }
 
+    #[inline(always)]
+    fn is_full_scale(&self, bits: u16) -> bool {
+        is_full_scale_int(*self as i32, bits)
+    }
+
+    fn sample_type() -> Option<SampleType> {
+        Some(SampleType::I16)
+    }
+
     fn read<R: io::Read>(reader: &mut R, fmt: SampleFormat, bytes: u16, bits: u16) -> Result<i16> {
         if fmt != SampleFormat::Int {
             return Err(Error::InvalidSampleFormat);
         }
-        match (bytes, bits) {
-            (1, 8) => Ok(try!(reader.read_u8().map(signed_from_u8).map(|x| x as i16))),
-            (2, 16) => Ok(try!(reader.read_le_i16())),
-            (n, _) if n > 2 => Err(Error::TooWide),
-            // TODO: add a generic decoder for any bit depth.
-            _ => Err(Error::Unsupported),
+        if bits > 16 {
+            return Err(Error::TooWide);
         }
+        read_int_sample(reader, bytes, bits).map(|x| x as i16)
     }
 }
 
 impl Sample for i32 {
     fn write<W: io::Write>(self, writer: &mut W, bits: u16) -> Result<()> {
         match bits {
-            8 => Ok(try!(writer.write_u8(u8_from_signed(try!(narrow_to_i8(self)))))),
-            16 => Ok(try!(writer.write_le_i16(try!(narrow_to_i16(self))))),
-            24 => Ok(try!(writer.write_le_i24(try!(narrow_to_i24(self))))),
-            32 => Ok(try!(writer.write_le_i32(self))),
+            8 => Ok(writer.write_u8(u8_from_signed(narrow_to_i8(self)?))?),
+            16 => Ok(writer.write_le_i16(narrow_to_i16(self)?)?),
+            24 => Ok(writer.write_le_i24(narrow_to_i24(self)?)?),
+            32 => Ok(writer.write_le_i32(self)?),
             _ => Err(Error::Unsupported),
         }
     }
 
+    fn write_padded<W: io::Write>(self, writer: &mut W, bits: u16, byte_width: u16) -> Result<()> {
+        write_int_sample(writer, self, bits, byte_width)
+    }
+
+    fn write_padded_unchecked<W: io::Write>(self,
+                                            writer: &mut W,
+                                            bits: u16,
+                                            byte_width: u16)
+                                            -> Result<()> {
+        write_int_sample_unchecked(writer, self, bits, byte_width)
+    }
+
     #[inline(always)]
     fn as_i16(self) -> i16 {
         self as i16
     }
 
+    #[inline(always)]
+    fn is_full_scale(&self, bits: u16) -> bool {
+        is_full_scale_int(*self, bits)
+    }
+
+    fn sample_type() -> Option<SampleType> {
+        Some(SampleType::I32)
+    }
+
     fn read<R: io::Read>(reader: &mut R, fmt: SampleFormat, bytes: u16, bits: u16) -> Result<i32> {
         if fmt != SampleFormat::Int {
             return Err(Error::InvalidSampleFormat);
         }
-        match (bytes, bits) {
-            (1, 8) => Ok(try!(reader.read_u8().map(signed_from_u8).map(|x| x as i32))),
-            (2, 16) => Ok(try!(reader.read_le_i16().map(|x| x as i32))),
-            (3, 24) => Ok(try!(reader.read_le_i24())),
-            (4, 32) => Ok(try!(reader.read_le_i32())),
-            (n, _) if n > 4 => Err(Error::TooWide),
-            // TODO: add a generic decoder for any bit depth.
-            _ => Err(Error::Unsupported),
+        if bits > 32 || bytes > 4 {
+            return Err(Error::TooWide);
         }
+        read_int_sample(reader, bytes, bits)
     }
 }
 
+#[cfg(feature = "float")]
 impl Sample for f32 {
     fn write<W: io::Write>(self, writer: &mut W, bits: u16) -> Result<()> {
         match bits {
-            32 => Ok(try!(writer.write_le_f32(self))),
+            32 => Ok(writer.write_le_f32(self)?),
             _ => Err(Error::Unsupported),
         }
     }
@@ -276,12 +443,22 @@ impl Sample for f32 {
         panic!("Calling as_i16 with an f32 is invalid.");
     }
 
+    #[inline(always)]
+    fn is_full_scale(&self, _bits: u16) -> bool {
+        // NaN compares false, so it is not considered full scale.
+        self.abs() >= 1.0
+    }
+
+    fn sample_type() -> Option<SampleType> {
+        Some(SampleType::F32)
+    }
+
     fn read<R: io::Read>(reader: &mut R, fmt: SampleFormat, bytes: u16, bits: u16) -> Result<Self> {
         if fmt != SampleFormat::Float {
             return Err(Error::InvalidSampleFormat);
         }
         match (bytes, bits) {
-            (4, 32) => Ok(try!(reader.read_le_f32())),
+            (4, 32) => Ok(reader.read_le_f32()?),
             (n, _) if n > 4 => Err(Error::TooWide),
             _ => Err(Error::Unsupported),
         }
@@ -300,6 +477,45 @@ pub enum SampleFormat {
     Int,
 }
 
+/// One of the Rust types that samples can be read into, see `Sample`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleType {
+    /// `i8`, for integer samples of up to 8 bits.
+    I8,
+    /// `i16`, for integer samples of up to 16 bits.
+    I16,
+    /// `i32`, for integer samples of up to 32 bits.
+    I32,
+    /// `f32`, for 32-bit float samples. Requires the `float` feature.
+    F32,
+}
+
+impl SampleType {
+    /// Returns whether samples of this type can hold every sample of `spec`.
+    ///
+    /// Integer types can hold integer samples of at most their width, so an
+    /// `i32` can hold 16-bit samples, but an `i16` cannot hold 24-bit ones.
+    pub fn can_represent(self, spec: &WavSpec) -> bool {
+        matches!((spec.required_sample_type(), self),
+                 (Some(SampleType::I8), SampleType::I8) |
+                 (Some(SampleType::I8), SampleType::I16) |
+                 (Some(SampleType::I16), SampleType::I16) |
+                 (Some(SampleType::I8), SampleType::I32) |
+                 (Some(SampleType::I16), SampleType::I32) |
+                 (Some(SampleType::I32), SampleType::I32) |
+                 (Some(SampleType::F32), SampleType::F32))
+    }
+}
+
+/// Specifies the byte order in which samples are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    /// The least significant byte comes first, as in regular WAVE files.
+    Little,
+    /// The most significant byte comes first.
+    Big,
+}
+
 /// Specifies properties of the audio data.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct WavSpec {
@@ -320,110 +536,401 @@ pub struct WavSpec {
     pub sample_format: SampleFormat,
 }
 
-/// The error type for operations on `WavReader` and `WavWriter`.
-#[derive(Debug)]
-pub enum Error {
-    /// An IO error occured in the underlying reader or writer.
-    IoError(io::Error),
-    /// Ill-formed WAVE data was encountered.
-    FormatError(&'static str),
-    /// The sample has more bits than the destination type.
-    ///
-    /// When iterating using the `samples` iterator, this means that the
-    /// destination type (produced by the iterator) is not wide enough to hold
-    /// the sample. When writing, this means that the sample cannot be written,
-    /// because it requires more bits than the bits per sample specified.
-    TooWide,
-    /// The number of samples written is not a multiple of the number of channels.
-    UnfinishedSample,
-    /// The format is not supported.
-    Unsupported,
-    /// The sample format is different than the destination format.
+/// The short names of the sample formats, as used by ffmpeg.
+const SAMPLE_FORMAT_NAMES: [(&str, SampleFormat, u16); 5] = [
+    ("u8", SampleFormat::Int, 8),
+    ("s16le", SampleFormat::Int, 16),
+    ("s24le", SampleFormat::Int, 24),
+    ("s32le", SampleFormat::Int, 32),
+    ("f32le", SampleFormat::Float, 32),
+];
+
+impl WavSpec {
+    /// Formats the spec as a short string, such as `s16le@44100x2`.
     ///
-    /// When iterating using the `samples` iterator, this means the destination
-    /// type (produced by the iterator) has a different sample format than the
-    /// samples in the wav file.
+    /// The string consists of the sample format as named by ffmpeg, the
+    /// sample rate, and the number of channels. It can be parsed back into a
+    /// `WavSpec` with `parse()`. Returns `None` if ffmpeg has no name for the
+    /// sample format, as is the case for 12-bit samples, for example.
+    pub fn to_short_string(&self) -> Option<String> {
+        SAMPLE_FORMAT_NAMES.iter()
+                           .find(|&&(_, format, bits)| format == self.sample_format &&
+                                                       bits == self.bits_per_sample)
+                           .map(|&(name, _, _)| {
+                               format!("{}@{}x{}", name, self.sample_rate, self.channels)
+                           })
+    }
+
+    /// Returns the narrowest sample type that can hold the samples of this spec.
     ///
-    /// For example, this will occur if the user attempts to produce `i32`
-    /// samples (which have a `SampleFormat::Int`) from a wav file that
-    /// contains floating point data (`SampleFormat::Float`).
-    InvalidSampleFormat,
+    /// Reading samples of this type with `WavReader::samples()` is lossless.
+    /// Returns `None` if none of the sample types can hold the samples, as is
+    /// the case for 64-bit float samples, for example.
+    pub fn required_sample_type(&self) -> Option<SampleType> {
+        match (self.sample_format, self.bits_per_sample) {
+            (SampleFormat::Int, 1..=8) => Some(SampleType::I8),
+            (SampleFormat::Int, 9..=16) => Some(SampleType::I16),
+            (SampleFormat::Int, 17..=32) => Some(SampleType::I32),
+            (SampleFormat::Float, 32) => Some(SampleType::F32),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a short string such as `s16le@44100x2`, see `to_short_string()`.
+impl str::FromStr for WavSpec {
+    type Err = ParseSpecError;
+
+    fn from_str(s: &str) -> result::Result<WavSpec, ParseSpecError> {
+        let mut parts = s.splitn(2, '@');
+        let name = parts.next().unwrap_or("");
+        let rest = parts.next().ok_or(ParseSpecError("missing '@'"))?;
+        let mut parts = rest.splitn(2, 'x');
+        let rate = parts.next().unwrap_or("");
+        let channels = parts.next().ok_or(ParseSpecError("missing 'x'"))?;
+
+        let &(_, format, bits) = (SAMPLE_FORMAT_NAMES.iter()
+                                                         .find(|f| f.0 == name)
+                                                         .ok_or(ParseSpecError("unknown sample format")))?;
+        let rate = rate.parse().map_err(|_| ParseSpecError("invalid sample rate"))?;
+        let channels = channels.parse().map_err(|_| ParseSpecError("invalid number of channels"))?;
+        if rate == 0 {
+            return Err(ParseSpecError("invalid sample rate"));
+        }
+        if channels == 0 {
+            return Err(ParseSpecError("invalid number of channels"));
+        }
+
+        Ok(WavSpec {
+            channels,
+            sample_rate: rate,
+            bits_per_sample: bits,
+            sample_format: format,
+        })
+    }
 }
 
-impl fmt::Display for Error {
+/// Describes the spec for humans, for example "16-bit 44.1 kHz stereo PCM".
+impl fmt::Display for WavSpec {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
-        match *self {
-            Error::IoError(ref err) => err.fmt(formatter),
-            Error::FormatError(reason) => {
-                try!(formatter.write_str("Ill-formed WAVE file: "));
-                formatter.write_str(reason)
-            }
-            Error::TooWide => {
-                formatter.write_str("The sample has more bits than the destination type.")
-            }
-            Error::UnfinishedSample => {
-                formatter.write_str(
-                    "The number of samples written is not a multiple of the number of channels.")
-            }
-            Error::Unsupported => {
-                formatter.write_str("The wave format of the file is not supported.")
-            }
-            Error::InvalidSampleFormat => {
-                formatter.write_str("The sample format differs from the destination format.")
-            }
+        write!(formatter, "{}-bit ", self.bits_per_sample)?;
+
+        // Print the rate in kHz without trailing zeros, so 48000 becomes
+        // "48 kHz", and 22050 becomes "22.05 kHz".
+        let (khz, hz) = (self.sample_rate / 1000, self.sample_rate % 1000);
+        if khz == 0 {
+            write!(formatter, "{} Hz ", hz)?;
+        } else if hz == 0 {
+            write!(formatter, "{} kHz ", khz)?;
+        } else {
+            let fraction = format!("{:03}", hz);
+            write!(formatter, "{}.{} kHz ", khz, fraction.trim_end_matches('0'))?;
+        }
+
+        match self.channels {
+            1 => formatter.write_str("mono ")?,
+            2 => formatter.write_str("stereo ")?,
+            n => write!(formatter, "{} channels ", n)?,
+        }
+
+        match self.sample_format {
+            SampleFormat::Int => formatter.write_str("PCM"),
+            SampleFormat::Float => formatter.write_str("float"),
         }
     }
 }
 
-impl error::Error for Error {
+/// The error returned when parsing a `WavSpec` from a string fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseSpecError(&'static str);
+
+impl fmt::Display for ParseSpecError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        formatter.write_str("Invalid spec string: ")?;
+        formatter.write_str(self.0)
+    }
+}
+
+impl std::error::Error for ParseSpecError {
     fn description(&self) -> &str {
-        match *self {
-            Error::IoError(ref err) => err.description(),
-            Error::FormatError(reason) => reason,
-            Error::TooWide => "the sample has more bits than the destination type",
-            Error::UnfinishedSample => "the number of samples written is not a multiple of the number of channels",
-            Error::Unsupported => "the wave format of the file is not supported",
-            Error::InvalidSampleFormat => "the sample format differs from the destination format",
-        }
+        self.0
     }
+}
 
-    fn cause(&self) -> Option<&error::Error> {
-        match *self {
-            Error::IoError(ref err) => Some(err),
-            Error::FormatError(_) => None,
-            Error::TooWide => None,
-            Error::UnfinishedSample => None,
-            Error::Unsupported => None,
-            Error::InvalidSampleFormat => None,
-        }
+#[test]
+fn spec_short_string_roundtrips() {
+    let spec: WavSpec = "s16le@44100x2".parse().unwrap();
+    assert_eq!(spec, WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    });
+    for s in &["u8@8000x1", "s16le@44100x2", "s24le@96000x6", "s32le@48000x1", "f32le@192000x8"] {
+        let spec: WavSpec = s.parse().unwrap();
+        assert_eq!(spec.to_short_string().unwrap(), *s);
+    }
+
+    let spec = WavSpec { bits_per_sample: 12, ..spec };
+    assert_eq!(spec.to_short_string(), None);
+
+    for s in &["", "s16le", "s16le@44100", "s16be@44100x2", "s16le@x2", "s16le@0x2",
+               "s16le@44100x0", "s16le@44100x2x"] {
+        assert!(s.parse::<WavSpec>().is_err(), "{} should not parse", s);
+    }
+}
+
+#[test]
+fn required_sample_type_is_narrowest_lossless_type() {
+    let spec = |sample_format, bits_per_sample| WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample,
+        sample_format,
+    };
+    assert_eq!(spec(SampleFormat::Int, 8).required_sample_type(), Some(SampleType::I8));
+    assert_eq!(spec(SampleFormat::Int, 12).required_sample_type(), Some(SampleType::I16));
+    assert_eq!(spec(SampleFormat::Int, 24).required_sample_type(), Some(SampleType::I32));
+    assert_eq!(spec(SampleFormat::Float, 32).required_sample_type(), Some(SampleType::F32));
+    assert_eq!(spec(SampleFormat::Float, 64).required_sample_type(), None);
+
+    assert!(SampleType::I32.can_represent(&spec(SampleFormat::Int, 16)));
+    assert!(!SampleType::I16.can_represent(&spec(SampleFormat::Int, 24)));
+    assert!(!SampleType::I32.can_represent(&spec(SampleFormat::Float, 32)));
+    assert!(!SampleType::F32.can_represent(&spec(SampleFormat::Int, 32)));
+}
+
+#[test]
+fn spec_displays_for_humans() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    assert_eq!(spec.to_string(), "16-bit 44.1 kHz stereo PCM");
+    let spec = WavSpec { channels: 1, sample_rate: 48000, ..spec };
+    assert_eq!(spec.to_string(), "16-bit 48 kHz mono PCM");
+    let spec = WavSpec { channels: 6, sample_rate: 22050, bits_per_sample: 32,
+                         sample_format: SampleFormat::Float };
+    assert_eq!(spec.to_string(), "32-bit 22.05 kHz 6 channels float");
+    let spec = WavSpec { sample_rate: 500, ..spec };
+    assert_eq!(spec.to_string(), "32-bit 500 Hz 6 channels float");
+}
+
+#[test]
+fn info_types_are_copy() {
+    fn assert_copy<T: Copy>() {}
+    assert_copy::<WavSpec>();
+    assert_copy::<WavSpecEx>();
+    assert_copy::<SampleFormat>();
+    assert_copy::<SampleType>();
+    assert_copy::<ChannelMask>();
+    assert_copy::<AmbisonicFormat>();
+    assert_copy::<ChunkInfo>();
+    assert_copy::<read::Consistency>();
+    assert_copy::<read::HeaderLimits>();
+
+    // The spec can be kept while the reader is borrowed mutably.
+    let file = testutil::WavBuilder::new()
+        .fmt_pcm(2, 8000, 16)
+        .chunk(b"data", &[1, 0, 2, 0])
+        .build();
+    let mut reader = WavReader::new(&file[..]).unwrap();
+    let spec = reader.spec();
+    for sample in reader.samples::<i16>() {
+        assert!(!sample.unwrap().is_full_scale(spec.bits_per_sample));
+    }
+}
+
+/// Specifies properties of the audio data, as well as the layout of the stream.
+///
+/// A `WavSpec` describes the samples, this also describes how they are stored.
+/// Usually samples are stored in the smallest number of bytes that holds them,
+/// but that need not be the case: 20-bit samples are sometimes stored in four
+/// bytes rather than three, for instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavSpecEx {
+    /// The normal information about the audio data.
+    ///
+    /// Bits per sample here is the number of _used_ bits per sample, not the
+    /// number of bits used to _store_ a sample.
+    pub spec: WavSpec,
+
+    /// The number of bytes used to store a sample.
+    pub bytes_per_sample: u16,
+}
+
+impl WavSpecEx {
+    /// Returns the number of bytes used to store a frame, one sample per channel.
+    ///
+    /// This is the block align of the fmt chunk. Reading or writing in
+    /// multiples of the block align never splits a frame.
+    pub fn block_align(&self) -> u32 {
+        self.bytes_per_sample as u32 * self.spec.channels as u32
+    }
+}
+
+/// Describes the channel layout of an ambisonic B-Format (.amb) file.
+///
+/// B-Format files store their channels in Furse-Malham (FuMa) order. The
+/// number of channels determines the horizontal and vertical order of the
+/// sound field, as specified by the .amb file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AmbisonicFormat {
+    /// The horizontal order of the sound field, between 1 and 3.
+    pub horizontal_order: u8,
+
+    /// The vertical (periphonic) order of the sound field, between 0 and 3.
+    pub vertical_order: u8,
+}
+
+/// The channel layouts defined by the .amb format: the number of channels,
+/// the horizontal and vertical order, and the channel names in file order.
+const AMBISONIC_LAYOUTS: [(u16, u8, u8, &[&str]); 9] = [
+    (3, 1, 0, &["W", "X", "Y"]),
+    (4, 1, 1, &["W", "X", "Y", "Z"]),
+    (5, 2, 0, &["W", "X", "Y", "U", "V"]),
+    (6, 2, 1, &["W", "X", "Y", "Z", "U", "V"]),
+    (7, 3, 0, &["W", "X", "Y", "U", "V", "P", "Q"]),
+    (8, 3, 1, &["W", "X", "Y", "Z", "U", "V", "P", "Q"]),
+    (9, 2, 2, &["W", "X", "Y", "Z", "R", "S", "T", "U", "V"]),
+    (11, 3, 2, &["W", "X", "Y", "Z", "R", "S", "T", "U", "V", "P", "Q"]),
+    (16, 3, 3, &["W", "X", "Y", "Z", "R", "S", "T", "U", "V", "K", "L", "M", "N", "O", "P", "Q"]),
+];
+
+impl AmbisonicFormat {
+    /// Returns the B-Format layout for the given number of channels.
+    ///
+    /// Returns `None` if the .amb format does not define a layout with this
+    /// number of channels.
+    pub fn from_channels(channels: u16) -> Option<AmbisonicFormat> {
+        AMBISONIC_LAYOUTS.iter()
+                         .find(|layout| layout.0 == channels)
+                         .map(|&(_, h, v, _)| AmbisonicFormat {
+                             horizontal_order: h,
+                             vertical_order: v,
+                         })
+    }
+
+    /// Returns the Furse-Malham names of the channels, in file order.
+    pub fn channel_names(&self) -> &'static [&'static str] {
+        AMBISONIC_LAYOUTS.iter()
+                         .find(|layout| layout.1 == self.horizontal_order &&
+                                        layout.2 == self.vertical_order)
+                         .map(|layout| layout.3)
+                         .unwrap_or(&[])
+    }
+
+    /// Returns the number of channels in the layout.
+    pub fn channels(&self) -> u16 {
+        self.channel_names().len() as u16
     }
 }
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Error {
-        Error::IoError(err)
+/// The `dwChannelMask` field of a `WAVEFORMATEXTENSIBLE` header.
+///
+/// Every bit that is set assigns a channel to a speaker position. Channels
+/// are stored in the order of the bits, from least to most significant, so
+/// the first channel of a file with mask `0x3` is front left. A mask of zero
+/// means that the channels are not assigned to speakers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChannelMask(pub u32);
+
+/// The abbreviated speaker positions of the channel mask bits defined in ksmedia.h.
+const SPEAKER_NAMES: [&str; 18] = [
+    "FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC", "BC",
+    "SL", "SR", "TC", "TFL", "TFC", "TFR", "TBL", "TBC", "TBR",
+];
+
+impl ChannelMask {
+    /// Returns the abbreviated name of the speaker position of a mask bit.
+    ///
+    /// Bit 0 is `"FL"` (front left), bit 3 is `"LFE"` (low frequency), and
+    /// bit 17 is `"TBR"` (top back right). Returns `None` for reserved bits.
+    pub fn speaker_name(bit: u32) -> Option<&'static str> {
+        SPEAKER_NAMES.get(bit as usize).cloned()
+    }
+
+    /// Returns the names of the speaker positions of the channels, in file order.
+    ///
+    /// Reserved bits have no name, and they are left out.
+    pub fn speaker_names(&self) -> Vec<&'static str> {
+        (0..32).filter(|&bit| self.0 & (1 << bit) != 0)
+               .filter_map(ChannelMask::speaker_name)
+               .collect()
+    }
+
+    /// Returns the number of speaker positions that are assigned.
+    pub fn num_speakers(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Returns whether the mask assigns exactly one speaker position per channel.
+    ///
+    /// Files where this does not hold occur in the wild. Channels beyond the
+    /// assigned ones have no position; if the mask assigns more positions
+    /// than there are channels, the excess positions are unused.
+    pub fn matches_channels(&self, channels: u16) -> bool {
+        self.num_speakers() == channels as u32
     }
 }
 
-/// A type for results generated by Hound where the error type is hard-wired.
-pub type Result<T> = result::Result<T, Error>;
+/// Returns the ambisonic order of a full-sphere ambiX file with `channels` channels.
+///
+/// AmbiX files store their channels in ACN order with SN3D normalization, in
+/// a regular PCM or IEEE float file, so they cannot be recognized by their
+/// header. A full-sphere sound field of order `n` has `(n + 1)^2` channels.
+/// Returns `None` if the number of channels is not a perfect square.
+pub fn ambix_order(channels: u16) -> Option<u16> {
+    // For 65281 channels and up, the root is 256, whose square overflows a `u16`.
+    let root = (channels as f64).sqrt().round() as u32;
+    if root > 0 && root * root == channels as u32 {
+        Some(root as u16 - 1)
+    } else {
+        None
+    }
+}
 
-// The WAVEFORMATEXTENSIBLE struct can contain several subformats.
-// These are identified by a GUID. The various GUIDS can be found in the file
-// mmreg.h that is part of the Windows SDK. The following GUIDS are defined:
-// - PCM:        00000001-0000-0010-8000-00aa00389b71
-// - IEEE_FLOAT: 00000003-0000-0010-8000-00aa00389b71
-// When written to a wav file, the byte order of a GUID is native for the first
-// three sections, which is assumed to be little endian, and big endian for the
-// last 8-byte section (which does contain a hyphen, for reasons unknown to me).
+#[test]
+fn verify_ambisonic_format() {
+    assert_eq!(AmbisonicFormat::from_channels(2), None);
+    assert_eq!(AmbisonicFormat::from_channels(10), None);
+    let format = AmbisonicFormat::from_channels(9).unwrap();
+    assert_eq!((format.horizontal_order, format.vertical_order), (2, 2));
+    assert_eq!(format.channels(), 9);
+    for &(channels, _, _, names) in &AMBISONIC_LAYOUTS {
+        let format = AmbisonicFormat::from_channels(channels).unwrap();
+        assert_eq!(format.channel_names(), names);
+        assert_eq!(format.channels(), channels);
+    }
+}
 
-/// Subformat type for PCM audio with integer samples.
-const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] = [0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80,
-                                            0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+#[test]
+fn verify_channel_mask() {
+    let surround = ChannelMask(0x60f);
+    assert_eq!(surround.speaker_names(), ["FL", "FR", "FC", "LFE", "SL", "SR"]);
+    assert!(surround.matches_channels(6));
+    assert!(!surround.matches_channels(8));
+    assert_eq!(ChannelMask::speaker_name(17), Some("TBR"));
+    assert_eq!(ChannelMask::speaker_name(18), None);
+    let reserved = ChannelMask(0x8000_0003);
+    assert_eq!(reserved.speaker_names(), ["FL", "FR"]);
+    assert_eq!(reserved.num_speakers(), 3);
+    assert!(ChannelMask(0).speaker_names().is_empty());
+}
 
-/// Subformat type for IEEE_FLOAT audio with float samples.
-const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: [u8; 16] = [0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
-                                                   0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+#[test]
+fn verify_ambix_order() {
+    assert_eq!(ambix_order(1), Some(0));
+    assert_eq!(ambix_order(4), Some(1));
+    assert_eq!(ambix_order(16), Some(3));
+    assert_eq!(ambix_order(64), Some(7));
+    assert_eq!(ambix_order(0), None);
+    assert_eq!(ambix_order(5), None);
+    assert_eq!(ambix_order(65025), Some(254));
+    assert_eq!(ambix_order(65535), None);
+}
 
 #[test]
 fn write_read_i16_is_lossless() {
@@ -447,13 +954,79 @@ fn write_read_i16_is_lossless() {
         buffer.set_position(0);
         let mut reader = WavReader::new(&mut buffer).unwrap();
         assert_eq!(write_spec, reader.spec());
-        assert_eq!(reader.len(), 2048);
+        assert_eq!(reader.num_samples(), 2048);
         for (expected, read) in (-1024_i16..1024).zip(reader.samples()) {
             assert_eq!(expected, read.unwrap());
         }
     }
 }
 
+#[test]
+fn write_read_rifx_is_lossless() {
+    let write_spec = WavSpec {
+        channels: 3,
+        sample_rate: 44100,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, write_spec).unwrap();
+        writer.set_endianness(Endianness::Big).unwrap();
+        assert_eq!(writer.set_size_limit(SizeLimit::Rf64).unwrap_err().kind(),
+                   ErrorKind::Unsupported);
+        for s in -300_i32..300 {
+            writer.write_sample(s * 1000).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    {
+        let bytes = buffer.get_ref();
+        assert_eq!(&bytes[..4], b"RIFX");
+        assert_eq!(&bytes[4..8], &[0, 0, 0x07, 0x44]); // 36 + 24 + 1800 bytes.
+        assert_eq!(&bytes[12..20], b"fmt \0\0\0\x28");
+        assert_eq!(&bytes[20..22], &[0xff, 0xfe]); // WAVE_FORMAT_EXTENSIBLE.
+        assert_eq!(&bytes[68..71], &[0xfb, 0x6c, 0x20]); // -300000.
+    }
+
+    buffer.set_position(0);
+    let mut reader = WavReader::new(&mut buffer).unwrap();
+    assert_eq!(reader.spec(), write_spec);
+    assert_eq!(reader.sample_endianness(), Endianness::Big);
+    for (expected, read) in (-300_i32..300).zip(reader.samples()) {
+        assert_eq!(expected * 1000, read.unwrap());
+    }
+
+    // The 16-bit sample writer and float samples support RIFX too.
+    let spec = WavSpec { channels: 1, bits_per_sample: 16, ..write_spec };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+        writer.set_endianness(Endianness::Big).unwrap();
+        let mut sample_writer = writer.get_i16_writer(2);
+        sample_writer.write_sample(1_i16);
+        sample_writer.write_sample(-2_i16);
+        sample_writer.flush().unwrap();
+    }
+    assert_eq!(&buffer.get_ref()[44..], &[0x00, 0x01, 0xff, 0xfe]);
+
+    #[cfg(feature = "float")]
+    {
+        let spec = WavSpec { bits_per_sample: 32, sample_format: SampleFormat::Float, ..spec };
+        let mut buffer = io::Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+            writer.set_endianness(Endianness::Big).unwrap();
+            writer.write_sample(0.5_f32).unwrap();
+            writer.finalize().unwrap();
+        }
+        buffer.set_position(0);
+        let mut reader = WavReader::new(&mut buffer).unwrap();
+        assert_eq!(reader.samples::<f32>().next().unwrap().unwrap(), 0.5);
+    }
+}
+
 #[test]
 fn write_read_i16_via_sample_writer_is_lossless() {
     let mut buffer = io::Cursor::new(Vec::new());
@@ -490,7 +1063,7 @@ fn write_read_i16_via_sample_writer_is_lossless() {
         buffer.set_position(0);
         let mut reader = WavReader::new(&mut buffer).unwrap();
         assert_eq!(write_spec, reader.spec());
-        assert_eq!(reader.len(), 2048);
+        assert_eq!(reader.num_samples(), 2048);
         for (expected, read) in (-1024_i16..1024).zip(reader.samples()) {
             assert_eq!(expected, read.unwrap());
         }
@@ -522,7 +1095,7 @@ fn write_read_i8_is_lossless() {
         buffer.set_position(0);
         let mut reader = WavReader::new(&mut buffer).unwrap();
         assert_eq!(write_spec, reader.spec());
-        assert_eq!(reader.len(), 256);
+        assert_eq!(reader.num_samples(), 256);
         for (expected, read) in (-128_i16..127 + 1).zip(reader.samples()) {
             assert_eq!(expected, read.unwrap());
         }
@@ -554,7 +1127,7 @@ fn write_read_i24_is_lossless() {
         buffer.set_position(0);
         let mut reader = WavReader::new(&mut buffer).unwrap();
         assert_eq!(write_spec, reader.spec());
-        assert_eq!(reader.len(), 256);
+        assert_eq!(reader.num_samples(), 256);
         for (expected, read) in (-128_i32..127 + 1)
             .map(|x| x * 256 * 256)
             .zip(reader.samples()) {
@@ -563,6 +1136,7 @@ fn write_read_i24_is_lossless() {
     }
 }
 #[test]
+#[cfg(feature = "float")]
 fn write_read_f32_is_lossless() {
     let mut buffer = io::Cursor::new(Vec::new());
     let write_spec = WavSpec {
@@ -584,7 +1158,7 @@ fn write_read_f32_is_lossless() {
         buffer.set_position(0);
         let mut reader = WavReader::new(&mut buffer).unwrap();
         assert_eq!(write_spec, reader.spec());
-        assert_eq!(reader.len(), 256);
+        assert_eq!(reader.num_samples(), 256);
         for (expected, read) in (1..257)
             .map(|x| 1.0_f32 / x as f32)
             .zip(reader.samples()) {
@@ -605,3 +1179,63 @@ fn no_32_bps_for_float_sample_format_panics() {
 
     WavWriter::new(&mut buffer, write_spec).unwrap();
 }
+
+/// Writes samples with a spec derived from the arguments, and checks that they read back unchanged.
+///
+/// The arguments are arbitrary, so they are mapped onto valid specs here,
+/// and the samples are truncated to whole frames that fit the bit depth.
+/// This way quickcheck can shrink a failing case to a minimal one.
+#[cfg(test)]
+fn write_read_is_lossless(format: u8, channels: u8, sample_rate: u32, samples: Vec<i32>) -> bool {
+    let (sample_format, bits_per_sample) = match format % 5 {
+        0 => (SampleFormat::Int, 8),
+        1 => (SampleFormat::Int, 16),
+        2 => (SampleFormat::Int, 24),
+        3 => (SampleFormat::Int, 32),
+        _ => (SampleFormat::Float, 32),
+    };
+    let spec = WavSpec {
+        channels: channels as u16 % 24 + 1,
+        sample_rate: sample_rate % 384_000 + 1,
+        bits_per_sample,
+        sample_format,
+    };
+    let num_samples = samples.len() - samples.len() % spec.channels as usize;
+    let shift = 32 - bits_per_sample;
+    let samples: Vec<i32> = samples[..num_samples].iter().map(|&x| (x << shift) >> shift).collect();
+
+    let mut buffer = io::Cursor::new(Vec::new());
+    match sample_format {
+        SampleFormat::Int => {
+            {
+                let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+                writer.write_samples(&samples).unwrap();
+                writer.finalize().unwrap();
+            }
+            buffer.set_position(0);
+            let mut reader = WavReader::new(&mut buffer).unwrap();
+            let read: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+            reader.spec() == spec && read == samples
+        }
+        #[cfg(not(feature = "float"))]
+        SampleFormat::Float => true,
+        #[cfg(feature = "float")]
+        SampleFormat::Float => {
+            let samples: Vec<f32> = samples.iter().map(|&x| x as f32 / (1 << 24) as f32).collect();
+            {
+                let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+                writer.write_samples(&samples).unwrap();
+                writer.finalize().unwrap();
+            }
+            buffer.set_position(0);
+            let mut reader = WavReader::new(&mut buffer).unwrap();
+            let read: Vec<f32> = reader.samples().map(|r| r.unwrap()).collect();
+            reader.spec() == spec && read == samples
+        }
+    }
+}
+
+#[test]
+fn write_read_random_specs_is_lossless() {
+    quickcheck::quickcheck(write_read_is_lossless as fn(u8, u8, u32, Vec<i32>) -> bool);
+}
@@ -0,0 +1,616 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, Write};
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::read::ReadExt;
+use crate::write::{WavWriter, WriteExt};
+use super::{Error, Result};
+
+/// Specifies how the text in metadata chunks is decoded.
+///
+/// The RIFF specification does not define an encoding for text. Real files
+/// contain UTF-8, Windows-1252, Latin-1, Shift-JIS, and more, so Hound
+/// exposes text fields as bytes, and decodes them only on request. Decoding
+/// is lossy: it never fails, but bytes that are invalid in the encoding are
+/// replaced. Encodings that Hound cannot decode can be handled by decoding
+/// the raw bytes with a different library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// Decode as UTF-8 if the text is valid UTF-8, and as Windows-1252 otherwise.
+    ///
+    /// Text in Windows-1252 or Latin-1 is rarely valid UTF-8, so this decodes
+    /// most files correctly. This is the default.
+    Auto,
+    /// Decode as UTF-8, replacing invalid sequences with U+FFFD.
+    Utf8,
+    /// Decode as Windows-1252, a superset of the printable characters of Latin-1.
+    Windows1252,
+}
+
+impl Default for TextEncoding {
+    fn default() -> TextEncoding {
+        TextEncoding::Auto
+    }
+}
+
+/// The characters for bytes 0x80 to 0x9f in Windows-1252.
+///
+/// The other bytes map to the code point with the same value, as in Latin-1.
+/// The five bytes that Windows-1252 leaves undefined map to the C1 control
+/// characters, as in the WHATWG encoding standard.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+impl TextEncoding {
+    /// Decodes text from a metadata field.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            TextEncoding::Auto => match str::from_utf8(bytes) {
+                Ok(text) => text.to_string(),
+                Err(..) => TextEncoding::Windows1252.decode(bytes),
+            },
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            TextEncoding::Windows1252 => {
+                bytes.iter().map(|&b| match b {
+                    0x80..=0x9f => WINDOWS_1252_HIGH[b as usize - 0x80],
+                    _ => b as char,
+                }).collect()
+            }
+        }
+    }
+}
+
+/// Returns the bytes of a text field up to the first zero byte.
+///
+/// Text fields are either zero-terminated, or padded with zeros to a fixed
+/// size.
+fn text_field(bytes: &[u8]) -> Vec<u8> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    bytes[..len].to_vec()
+}
+
+/// Copies text into a fixed-size field, truncating it or padding it with zeros.
+fn put_text_field(field: &mut [u8], text: &[u8]) {
+    let len = text.len().min(field.len());
+    field[..len].copy_from_slice(&text[..len]);
+}
+
+/// The contents of a `LIST` chunk: the list type, and the chunks in the list.
+///
+/// A `LIST` chunk groups chunks, such as the entries of an `INFO` list, or
+/// the labels and notes of an `adtl` (associated data) list. Lists can be
+/// nested: a chunk in the list with the id `LIST` can be parsed as a
+/// `ListChunk` in turn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListChunk {
+    /// The list type, such as `INFO` or `adtl`.
+    pub list_type: [u8; 4],
+
+    /// The ids and contents of the chunks in the list, in file order.
+    pub chunks: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl ListChunk {
+    /// Parses the contents of a `LIST` chunk.
+    ///
+    /// This is what `WavEditor::read_chunk(b"LIST")` returns. Returns
+    /// `Error::FormatError` if the contents are too short for a list type, or
+    /// if a chunk extends beyond the end of the list.
+    pub fn parse(data: &[u8]) -> Result<ListChunk> {
+        if data.len() < 4 {
+            return Err(Error::FormatError("LIST chunk too short"));
+        }
+        let mut list_type = [0u8; 4];
+        list_type.copy_from_slice(&data[..4]);
+        let mut chunks = Vec::new();
+        let mut rest = &data[4..];
+        while rest.len() >= 8 {
+            let mut id = [0u8; 4];
+            id.copy_from_slice(&rest[..4]);
+            let len = (&rest[4..8]).read_le_u32()? as usize;
+            if len > rest.len() - 8 {
+                return Err(Error::FormatError("chunk extends beyond LIST chunk"));
+            }
+            chunks.push((id, rest[8..8 + len].to_vec()));
+            // Subchunks are aligned to 2 bytes. Some writers omit the padding
+            // byte after the last chunk.
+            let end = 8 + len + len % 2;
+            rest = &rest[end.min(rest.len())..];
+        }
+        Ok(ListChunk {
+            list_type,
+            chunks,
+        })
+    }
+
+    /// Encodes the list as the contents of a `LIST` chunk, the inverse of `parse()`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = self.list_type.to_vec();
+        for (id, contents) in &self.chunks {
+            data.extend_from_slice(id);
+            data.write_le_u32(contents.len() as u32).expect("writing to a Vec does not fail");
+            data.extend_from_slice(contents);
+            if contents.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+        data
+    }
+}
+
+/// An entry of a `LIST` chunk of type `INFO`, such as the title or artist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfoEntry {
+    /// The identifier of the entry, such as `INAM` for the title.
+    pub id: [u8; 4],
+
+    /// The text of the entry, without the terminating zero byte.
+    ///
+    /// Decode it with `TextEncoding::decode()`.
+    pub value: Vec<u8>,
+}
+
+impl InfoEntry {
+    /// Parses the contents of a `LIST` chunk of type `INFO`.
+    ///
+    /// The contents start with the list type `INFO`, followed by one
+    /// subchunk per entry. This is what `WavEditor::read_chunk(b"LIST")`
+    /// returns. Returns `Error::FormatError` if the list type is different,
+    /// or if a subchunk extends beyond the end of the list.
+    pub fn parse_list(data: &[u8]) -> Result<Vec<InfoEntry>> {
+        if data.len() < 4 || &data[..4] != b"INFO" {
+            return Err(Error::FormatError("not an INFO list"));
+        }
+        let list = ListChunk::parse(data)?;
+        Ok(list.chunks.iter().map(|&(id, ref contents)| InfoEntry {
+            id,
+            value: text_field(contents),
+        }).collect())
+    }
+
+    /// Encodes entries as the contents of a `LIST` chunk of type `INFO`.
+    ///
+    /// This is the inverse of `parse_list()`. Every value is followed by a
+    /// terminating zero byte, and padded to an even length.
+    pub fn encode_list(entries: &[InfoEntry]) -> Vec<u8> {
+        let list = ListChunk {
+            list_type: *b"INFO",
+            chunks: entries.iter().map(|entry| {
+                let mut value = entry.value.clone();
+                value.push(0);
+                (entry.id, value)
+            }).collect(),
+        };
+        list.encode()
+    }
+}
+
+/// The contents of a `bext` (Broadcast Wave Format extension) chunk.
+///
+/// The chunk is specified in EBU Tech 3285. Text fields are fixed-size
+/// character arrays in the file; here they are exposed as bytes without the
+/// zero padding. Decode them with `TextEncoding::decode()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BroadcastExtension {
+    /// A description of the sound sequence, at most 256 bytes.
+    pub description: Vec<u8>,
+    /// The name of the originator, at most 32 bytes.
+    pub originator: Vec<u8>,
+    /// A reference assigned by the originator, at most 32 bytes.
+    pub originator_reference: Vec<u8>,
+    /// The date of creation, in the format `yyyy-mm-dd`.
+    pub origination_date: Vec<u8>,
+    /// The time of creation, in the format `hh:mm:ss`.
+    pub origination_time: Vec<u8>,
+    /// The position of the first sample since midnight, in samples.
+    pub time_reference: u64,
+    /// The version of the extension.
+    pub version: u16,
+    /// The SMPTE UMID, since version 1.
+    pub umid: [u8; 64],
+    /// The integrated loudness in LUFS, multiplied by 100, since version 2.
+    pub loudness_value: i16,
+    /// The loudness range in LU, multiplied by 100, since version 2.
+    pub loudness_range: i16,
+    /// The maximum true peak level in dBTP, multiplied by 100, since version 2.
+    pub max_true_peak_level: i16,
+    /// The maximum momentary loudness in LUFS, multiplied by 100, since version 2.
+    pub max_momentary_loudness: i16,
+    /// The maximum short-term loudness in LUFS, multiplied by 100, since version 2.
+    pub max_short_term_loudness: i16,
+    /// The coding history, lines of text that describe the signal chain.
+    pub coding_history: Vec<u8>,
+}
+
+impl BroadcastExtension {
+    /// Parses the contents of a `bext` chunk.
+    ///
+    /// Returns `Error::FormatError` if the chunk is shorter than the 602
+    /// bytes of fixed fields.
+    pub fn parse(data: &[u8]) -> Result<BroadcastExtension> {
+        if data.len() < 602 {
+            return Err(Error::FormatError("bext chunk too short"));
+        }
+        let mut reader = &data[338..];
+        let time_reference_low = reader.read_le_u32()? as u64;
+        let time_reference_high = reader.read_le_u32()? as u64;
+        let version = reader.read_le_u16()?;
+        let mut umid = [0u8; 64];
+        reader.read_into(&mut umid)?;
+        Ok(BroadcastExtension {
+            description: text_field(&data[..256]),
+            originator: text_field(&data[256..288]),
+            originator_reference: text_field(&data[288..320]),
+            origination_date: text_field(&data[320..330]),
+            origination_time: text_field(&data[330..338]),
+            time_reference: time_reference_high << 32 | time_reference_low,
+            version,
+            umid,
+            loudness_value: reader.read_le_i16()?,
+            loudness_range: reader.read_le_i16()?,
+            max_true_peak_level: reader.read_le_i16()?,
+            max_momentary_loudness: reader.read_le_i16()?,
+            max_short_term_loudness: reader.read_le_i16()?,
+            // The loudness fields are followed by 180 reserved bytes.
+            coding_history: text_field(&data[602..]),
+        })
+    }
+
+    /// Encodes the extension as the contents of a `bext` chunk.
+    ///
+    /// This is the inverse of `parse()`. Text fields that are longer than
+    /// their field in the chunk are truncated.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 602];
+        put_text_field(&mut data[..256], &self.description);
+        put_text_field(&mut data[256..288], &self.originator);
+        put_text_field(&mut data[288..320], &self.originator_reference);
+        put_text_field(&mut data[320..330], &self.origination_date);
+        put_text_field(&mut data[330..338], &self.origination_time);
+        {
+            let mut writer = &mut data[338..422];
+            writer.write_le_u32(self.time_reference as u32).expect("the fields fit in the chunk");
+            writer.write_le_u32((self.time_reference >> 32) as u32).expect("the fields fit in the chunk");
+            writer.write_le_u16(self.version).expect("the fields fit in the chunk");
+            writer.write_all(&self.umid).expect("the fields fit in the chunk");
+            for &x in &[self.loudness_value,
+                        self.loudness_range,
+                        self.max_true_peak_level,
+                        self.max_momentary_loudness,
+                        self.max_short_term_loudness] {
+                writer.write_le_i16(x).expect("the fields fit in the chunk");
+            }
+        }
+        data.extend_from_slice(&self.coding_history);
+        data
+    }
+
+    /// Fills in the origination date and time, and the time reference, from the start of a recording.
+    ///
+    /// The date and time are those of `start` in a time zone that is
+    /// `utc_offset` seconds ahead of UTC; Hound has no time zone database,
+    /// so the offset of the local time zone must be supplied. The time
+    /// reference is the position of `start` since midnight in that time
+    /// zone, in samples at `sample_rate`, rounded to the nearest sample. This
+    /// is the position that tools use to place the file on a timeline.
+    pub fn set_origination(&mut self, start: SystemTime, utc_offset: i32, sample_rate: u32) {
+        const NANOS_PER_DAY: i128 = 86_400_000_000_000;
+        let since_epoch = match start.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_nanos() as i128,
+            Err(err) => -(err.duration().as_nanos() as i128),
+        };
+        let local = since_epoch + utc_offset as i128 * 1_000_000_000;
+        let days = local.div_euclid(NANOS_PER_DAY);
+        let nanos_of_day = local.rem_euclid(NANOS_PER_DAY);
+
+        let (year, month, day) = civil_from_days(days);
+        let seconds_of_day = nanos_of_day / 1_000_000_000;
+        self.origination_date = format!("{:04}-{:02}-{:02}", year, month, day).into_bytes();
+        self.origination_time = format!("{:02}:{:02}:{:02}",
+                                        seconds_of_day / 3600,
+                                        seconds_of_day / 60 % 60,
+                                        seconds_of_day % 60).into_bytes();
+        self.time_reference = ((nanos_of_day * sample_rate as i128 + 500_000_000) / 1_000_000_000) as u64;
+    }
+}
+
+/// Returns the year, month, and day of a day number, where day 0 is 1970-01-01.
+///
+/// This is the `civil_from_days` algorithm by Howard Hinnant, for the
+/// proleptic Gregorian calendar.
+fn civil_from_days(days: i128) -> (i128, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months are counted from March, so the leap day is at the end.
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl Default for BroadcastExtension {
+    /// Returns an extension of version 0 with all fields empty or zero.
+    fn default() -> BroadcastExtension {
+        BroadcastExtension {
+            description: Vec::new(),
+            originator: Vec::new(),
+            originator_reference: Vec::new(),
+            origination_date: Vec::new(),
+            origination_time: Vec::new(),
+            time_reference: 0,
+            version: 0,
+            umid: [0; 64],
+            loudness_value: 0,
+            loudness_range: 0,
+            max_true_peak_level: 0,
+            max_momentary_loudness: 0,
+            max_short_term_loudness: 0,
+            coding_history: Vec::new(),
+        }
+    }
+}
+
+/// Metadata to write to many files, such as the files of a multi-file recording.
+///
+/// A capture application can build the template once, with the originator
+/// in the broadcast extension and the software in the INFO list, for
+/// instance, and write it to every file it creates. Values that differ per
+/// file are set on a clone of the template:
+///
+/// ```
+/// use hound::{BroadcastExtension, MetadataTemplate, SampleFormat, WavSpec, WavWriter};
+///
+/// let mut template = MetadataTemplate::new();
+/// template.bext = Some(BroadcastExtension {
+///     originator: b"Field Recorder".to_vec(),
+///     ..BroadcastExtension::default()
+/// });
+/// template.set_info(b"ISFT", b"Recorder 1.0");
+///
+/// let spec = WavSpec {
+///     channels: 1,
+///     sample_rate: 48000,
+///     bits_per_sample: 16,
+///     sample_format: SampleFormat::Int,
+/// };
+/// for take in 1..3 {
+///     let mut metadata = template.clone();
+///     metadata.set_info(b"INAM", format!("Take {}", take).as_bytes());
+///     let mut writer = WavWriter::new_in_memory(spec).unwrap();
+///     metadata.write_to(&mut *writer).unwrap();
+///     writer.write_sample(0_i16).unwrap();
+///     writer.finalize().unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataTemplate {
+    /// The broadcast extension, written as a `bext` chunk.
+    pub bext: Option<BroadcastExtension>,
+    /// The entries of the INFO list, written as a `LIST` chunk if there are any.
+    pub info: Vec<InfoEntry>,
+    /// Other chunks, such as `iXML`, written verbatim after the others.
+    pub chunks: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl MetadataTemplate {
+    /// Returns a template without metadata.
+    pub fn new() -> MetadataTemplate {
+        MetadataTemplate::default()
+    }
+
+    /// Sets the INFO entry with the given id, replacing an existing entry.
+    pub fn set_info(&mut self, id: &[u8; 4], value: &[u8]) {
+        match self.info.iter_mut().find(|entry| entry.id == *id) {
+            Some(entry) => entry.value = value.to_vec(),
+            None => self.info.push(InfoEntry {
+                id: *id,
+                value: value.to_vec(),
+            }),
+        }
+    }
+
+    /// Writes the metadata chunks with `WavWriter::write_chunk()`.
+    ///
+    /// Like that method, this must be called before any samples are written.
+    pub fn write_to<W>(&self, writer: &mut WavWriter<W>) -> Result<()>
+        where W: io::Write + io::Seek
+    {
+        if let Some(ref bext) = self.bext {
+            writer.write_chunk(b"bext", &bext.encode())?;
+        }
+        if !self.info.is_empty() {
+            writer.write_chunk(b"LIST", &InfoEntry::encode_list(&self.info))?;
+        }
+        for (id, data) in &self.chunks {
+            writer.write_chunk(id, data)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn text_encoding_decodes_legacy_text() {
+    // "Café – über" in Windows-1252.
+    let latin = b"Caf\xe9 \x96 \xfcber";
+    assert_eq!(TextEncoding::Auto.decode(latin), "Caf\u{e9} \u{2013} \u{fc}ber");
+    assert_eq!(TextEncoding::Windows1252.decode(latin), "Caf\u{e9} \u{2013} \u{fc}ber");
+    assert_eq!(TextEncoding::Utf8.decode(latin), "Caf\u{fffd} \u{fffd} \u{fffd}ber");
+
+    let utf8 = "Caf\u{e9} \u{2013} \u{fc}ber".as_bytes();
+    assert_eq!(TextEncoding::Auto.decode(utf8), "Caf\u{e9} \u{2013} \u{fc}ber");
+    assert_eq!(TextEncoding::Utf8.decode(utf8), "Caf\u{e9} \u{2013} \u{fc}ber");
+}
+
+#[test]
+fn info_list_is_parsed() {
+    let data = b"INFOINAM\x06\x00\x00\x00Title\x00IART\x03\x00\x00\x00M\xfcl\x00ICMT\x00\x00\x00\x00";
+    let entries = InfoEntry::parse_list(data).unwrap();
+    assert_eq!(entries, vec![
+        InfoEntry { id: *b"INAM", value: b"Title".to_vec() },
+        InfoEntry { id: *b"IART", value: b"M\xfcl".to_vec() },
+        InfoEntry { id: *b"ICMT", value: Vec::new() },
+    ]);
+    assert_eq!(TextEncoding::Auto.decode(&entries[1].value), "M\u{fc}l");
+
+    assert!(InfoEntry::parse_list(b"adtl").is_err());
+    assert!(InfoEntry::parse_list(b"INFOINAM\x10\x00\x00\x00Title").is_err());
+}
+
+#[test]
+fn nested_list_is_parsed() {
+    let inner = ListChunk {
+        list_type: *b"adtl",
+        chunks: vec![(*b"labl", b"\x01\x00\x00\x00Intro\x00".to_vec())],
+    };
+    let outer = ListChunk {
+        list_type: *b"wrap",
+        chunks: vec![(*b"LIST", inner.encode()), (*b"note", b"odd".to_vec())],
+    };
+    let data = outer.encode();
+    assert_eq!(data.len(), 4 + 8 + 4 + 8 + 10 + 8 + 4);
+
+    let parsed = ListChunk::parse(&data).unwrap();
+    assert_eq!(parsed, outer);
+    assert_eq!(ListChunk::parse(&parsed.chunks[0].1).unwrap(), inner);
+    // The padding byte after the last chunk may be missing.
+    assert_eq!(ListChunk::parse(&data[..data.len() - 1]).unwrap(), outer);
+    assert!(ListChunk::parse(b"adt").is_err());
+    assert!(ListChunk::parse(b"adtllabl\x10\x00\x00\x00Intro").is_err());
+}
+
+#[test]
+fn bext_chunk_is_parsed() {
+    let mut data = vec![0u8; 602];
+    data[..11].copy_from_slice(b"Interview 1");
+    data[256..263].copy_from_slice(b"Recorde");
+    data[320..330].copy_from_slice(b"2024-01-31");
+    data[330..338].copy_from_slice(b"12:34:56");
+    data[338..346].copy_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00]);
+    data[346] = 2;
+    data[412..414].copy_from_slice(&[0x0c, 0xfe]);
+    data.extend_from_slice(b"A=PCM,F=48000\r\n\x00");
+
+    let bext = BroadcastExtension::parse(&data).unwrap();
+    assert_eq!(bext.description, b"Interview 1".to_vec());
+    assert_eq!(bext.originator, b"Recorde".to_vec());
+    assert!(bext.originator_reference.is_empty());
+    assert_eq!(bext.origination_date, b"2024-01-31".to_vec());
+    assert_eq!(bext.origination_time, b"12:34:56".to_vec());
+    assert_eq!(bext.time_reference, 0x2_0000_0100);
+    assert_eq!(bext.version, 2);
+    assert_eq!(bext.loudness_value, -500);
+    assert_eq!(bext.coding_history, b"A=PCM,F=48000\r\n".to_vec());
+
+    assert!(BroadcastExtension::parse(&data[..601]).is_err());
+}
+
+#[test]
+fn metadata_roundtrips_through_encoding() {
+    let entries = vec![
+        InfoEntry { id: *b"INAM", value: b"Title".to_vec() },
+        InfoEntry { id: *b"ISFT", value: b"Hound".to_vec() },
+    ];
+    let list = InfoEntry::encode_list(&entries);
+    assert_eq!(&list[..18], b"INFOINAM\x06\x00\x00\x00Title\x00");
+    assert_eq!(InfoEntry::parse_list(&list).unwrap(), entries);
+
+    let bext = BroadcastExtension {
+        description: b"Interview 1".to_vec(),
+        originator: b"Recorder".to_vec(),
+        origination_date: b"2024-01-31".to_vec(),
+        origination_time: b"12:34:56".to_vec(),
+        time_reference: 0x2_0000_0100,
+        version: 2,
+        loudness_value: -500,
+        coding_history: b"A=PCM,F=48000\r\n".to_vec(),
+        ..BroadcastExtension::default()
+    };
+    let data = bext.encode();
+    assert_eq!(data.len(), 602 + 15);
+    assert_eq!(BroadcastExtension::parse(&data).unwrap(), bext);
+}
+
+#[test]
+fn metadata_template_is_written_to_every_file() {
+    use crate::edit::WavEditor;
+    use super::{SampleFormat, WavSpec};
+
+    let mut template = MetadataTemplate::new();
+    template.bext = Some(BroadcastExtension {
+        originator: b"Recorder".to_vec(),
+        ..BroadcastExtension::default()
+    });
+    template.set_info(b"ISFT", b"Hound");
+    template.chunks.push((*b"iXML", b"<BWFXML/>".to_vec()));
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    for take in 0..2_i16 {
+        let mut metadata = template.clone();
+        metadata.set_info(b"INAM", format!("Take {}", take).as_bytes());
+        metadata.set_info(b"ISFT", b"Hound 3");
+        let mut writer = WavWriter::new_in_memory(spec).unwrap();
+        metadata.write_to(&mut *writer).unwrap();
+        writer.write_sample(take).unwrap();
+        let bytes = writer.finalize().unwrap();
+
+        let mut reader = crate::read::WavReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.samples::<i16>().next().unwrap().unwrap(), take);
+        let mut editor = WavEditor::new(io::Cursor::new(bytes)).unwrap();
+        let ids: Vec<[u8; 4]> = editor.chunks().iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![*b"fmt ", *b"bext", *b"LIST", *b"iXML", *b"data"]);
+        let bext = BroadcastExtension::parse(&editor.read_chunk(b"bext").unwrap().unwrap()).unwrap();
+        assert_eq!(bext.originator, b"Recorder".to_vec());
+        let info = InfoEntry::parse_list(&editor.read_chunk(b"LIST").unwrap().unwrap()).unwrap();
+        assert_eq!(info, vec![
+            InfoEntry { id: *b"ISFT", value: b"Hound 3".to_vec() },
+            InfoEntry { id: *b"INAM", value: format!("Take {}", take).into_bytes() },
+        ]);
+    }
+}
+
+#[test]
+fn set_origination_computes_date_time_and_time_reference() {
+    use std::time::Duration;
+
+    let mut bext = BroadcastExtension::default();
+    // 2024-01-31 23:34:56.5 UTC, recorded in a time zone one hour ahead.
+    let start = UNIX_EPOCH + Duration::from_millis(1_706_744_096_500);
+    bext.set_origination(start, 3600, 48000);
+    assert_eq!(bext.origination_date, b"2024-02-01".to_vec());
+    assert_eq!(bext.origination_time, b"00:34:56".to_vec());
+    assert_eq!(bext.time_reference, 100_632_000);
+
+    // 1969-12-31 22:00:00 UTC, recorded in a time zone one hour behind.
+    let start = UNIX_EPOCH - Duration::from_secs(7200);
+    bext.set_origination(start, -3600, 44100);
+    assert_eq!(bext.origination_date, b"1969-12-31".to_vec());
+    assert_eq!(bext.origination_time, b"21:00:00".to_vec());
+    assert_eq!(bext.time_reference, 21 * 3600 * 44100);
+
+    assert_eq!(civil_from_days(0), (1970, 1, 1));
+    assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+}
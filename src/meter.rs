@@ -0,0 +1,271 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Level meters that run alongside decoding.
+//!
+//! `metered()` wraps an iterator over samples, and measures the peak, true
+//! peak, and RMS level of every channel while the samples pass through
+//! unchanged. The levels can be retrieved at any time, so an application
+//! that plays or records a file gets meters without decoding it twice.
+//!
+//! ```
+//! use hound::meter;
+//!
+//! let mut reader = hound::WavReader::open("testsamples/pop.wav").unwrap();
+//! let spec = reader.spec();
+//! let mut samples = meter::metered(reader.samples::<i16>(), spec);
+//! for sample in samples.by_ref().take(100) {
+//!     let _ = sample.unwrap();
+//! }
+//! let level = samples.level(0);
+//! assert!(level.rms <= level.peak && level.peak <= level.true_peak);
+//! ```
+
+use std::f64::consts::PI;
+use super::{Result, SampleFormat, WavSpec};
+
+/// The number of taps of every phase of the oversampling filter.
+const TAPS: usize = 12;
+
+/// The oversampling factor for measuring the true peak.
+const OVERSAMPLING: usize = 4;
+
+/// The levels of one channel, in the range of float samples.
+///
+/// Integer samples are scaled as by `int_to_f32()`, so full scale is 1.0.
+/// All levels are zero before any samples have been measured.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Level {
+    /// The largest magnitude of a sample.
+    pub peak: f32,
+    /// The largest magnitude of the reconstructed signal, between samples too.
+    ///
+    /// This is estimated by oversampling by a factor four, as the true-peak
+    /// meter of ITU-R BS.1770 does. It is at least `peak`, and exceeds full
+    /// scale for signals that clip when converted to analog.
+    pub true_peak: f32,
+    /// The root mean square of the samples.
+    pub rms: f32,
+}
+
+/// The running state of one channel.
+#[derive(Clone)]
+struct ChannelMeter {
+    peak: f32,
+    true_peak: f32,
+    sum_squares: f64,
+    count: u64,
+    /// The most recent samples, oldest first, the input of the filter.
+    history: [f32; TAPS],
+}
+
+impl ChannelMeter {
+    fn new() -> ChannelMeter {
+        ChannelMeter {
+            peak: 0.0,
+            true_peak: 0.0,
+            sum_squares: 0.0,
+            count: 0,
+            history: [0.0; TAPS],
+        }
+    }
+
+    fn measure(&mut self, x: f32, coefficients: &[[f32; TAPS]; OVERSAMPLING]) {
+        let magnitude = x.abs();
+        self.peak = self.peak.max(magnitude);
+        self.true_peak = self.true_peak.max(magnitude);
+        self.sum_squares += x as f64 * x as f64;
+        self.count += 1;
+
+        self.history.copy_within(1.., 0);
+        self.history[TAPS - 1] = x;
+        // Phase 0 falls on a sample, which is already measured.
+        for phase in &coefficients[1..] {
+            let y: f32 = phase.iter().zip(&self.history).map(|(c, x)| c * x).sum();
+            self.true_peak = self.true_peak.max(y.abs());
+        }
+    }
+
+    fn level(&self) -> Level {
+        let rms = if self.count == 0 {
+            0.0
+        } else {
+            (self.sum_squares / self.count as f64).sqrt() as f32
+        };
+        Level {
+            peak: self.peak,
+            true_peak: self.true_peak,
+            rms,
+        }
+    }
+}
+
+/// Returns the coefficients of the oversampling filter, per phase.
+///
+/// The filter is a Hann-windowed sinc that interpolates between the two
+/// samples in the middle of the history.
+fn oversampling_coefficients() -> [[f32; TAPS]; OVERSAMPLING] {
+    let mut coefficients = [[0.0; TAPS]; OVERSAMPLING];
+    let half = (TAPS / 2) as f64;
+    for (phase, taps) in coefficients.iter_mut().enumerate() {
+        let position = half - 1.0 + phase as f64 / OVERSAMPLING as f64;
+        for (i, c) in taps.iter_mut().enumerate() {
+            let t = position - i as f64;
+            let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+            let window = 0.5 * (1.0 + (PI * t / half).cos());
+            *c = (sinc * window) as f32;
+        }
+    }
+    coefficients
+}
+
+/// An iterator adapter that measures the levels of the samples it passes through.
+///
+/// See `metered()`.
+pub struct Metered<I> {
+    samples: I,
+    /// The factor to scale a sample by to bring it in the range of float samples.
+    scale: f64,
+    /// The channel of the next sample.
+    channel: usize,
+    channels: Vec<ChannelMeter>,
+    coefficients: [[f32; TAPS]; OVERSAMPLING],
+}
+
+/// Wraps an iterator over the samples of a file with the given spec in a level meter.
+///
+/// The iterator typically comes from `WavReader::samples()`, and the first
+/// sample it yields must belong to the first channel. Samples are passed
+/// through unchanged, errors are passed through and not measured.
+///
+/// Panics if the spec has zero channels.
+pub fn metered<I, S>(samples: I, spec: WavSpec) -> Metered<I>
+    where I: Iterator<Item = Result<S>>,
+          S: Copy + Into<f64>
+{
+    assert!(spec.channels > 0, "a meter needs at least one channel");
+    let scale = match spec.sample_format {
+        SampleFormat::Float => 1.0,
+        SampleFormat::Int => 1.0 / (1_u64 << (spec.bits_per_sample.clamp(1, 32) - 1)) as f64,
+    };
+    Metered {
+        samples,
+        scale,
+        channel: 0,
+        channels: vec![ChannelMeter::new(); spec.channels as usize],
+        coefficients: oversampling_coefficients(),
+    }
+}
+
+impl<I> Metered<I> {
+    /// Returns the levels of the given channel, over the samples so far.
+    ///
+    /// The true peak lags behind by a few samples, because the filter needs
+    /// samples after a point to reconstruct the signal there.
+    ///
+    /// Panics if the channel is out of range.
+    pub fn level(&self, channel: u16) -> Level {
+        assert!((channel as usize) < self.channels.len(), "channel out of range");
+        self.channels[channel as usize].level()
+    }
+
+    /// Returns the levels of all channels.
+    pub fn levels(&self) -> Vec<Level> {
+        self.channels.iter().map(ChannelMeter::level).collect()
+    }
+
+    /// Resets the levels to zero, to start measuring a new interval.
+    ///
+    /// A meter that is redrawn periodically can call this after every redraw,
+    /// to show the levels since the previous one. The history for the true
+    /// peak is kept, so it stays accurate across the reset.
+    pub fn reset(&mut self) {
+        for meter in &mut self.channels {
+            meter.peak = 0.0;
+            meter.true_peak = 0.0;
+            meter.sum_squares = 0.0;
+            meter.count = 0;
+        }
+    }
+
+    /// Returns the wrapped iterator.
+    pub fn into_inner(self) -> I {
+        self.samples
+    }
+}
+
+impl<I, S> Iterator for Metered<I>
+    where I: Iterator<Item = Result<S>>,
+          S: Copy + Into<f64>
+{
+    type Item = Result<S>;
+
+    fn next(&mut self) -> Option<Result<S>> {
+        let item = self.samples.next();
+        if let Some(Ok(sample)) = item {
+            let x = (sample.into() * self.scale) as f32;
+            self.channels[self.channel].measure(x, &self.coefficients);
+            self.channel = (self.channel + 1) % self.channels.len();
+        }
+        item
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.samples.size_hint()
+    }
+}
+
+#[cfg(test)]
+fn meter_samples<S: Copy + Into<f64>>(samples: &[S], spec: WavSpec) -> Metered<std::vec::IntoIter<Result<S>>> {
+    let mut meter = metered(samples.iter().map(|&s| Ok(s)).collect::<Vec<_>>().into_iter(), spec);
+    assert_eq!(meter.by_ref().map(|s| s.unwrap().into()).collect::<Vec<f64>>(),
+               samples.iter().map(|&s| s.into()).collect::<Vec<f64>>());
+    meter
+}
+
+#[test]
+fn metered_measures_every_channel() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut meter = meter_samples(&[16384_i16, 0, -16384, -8192, 16384, 0, -16384, 8192], spec);
+    assert_eq!(meter.level(0).peak, 0.5);
+    assert_eq!(meter.level(0).rms, 0.5);
+    assert_eq!(meter.level(1).peak, 0.25);
+    assert_eq!(meter.level(1).rms, (0.03125_f32).sqrt());
+    assert!(meter.levels().iter().all(|l| l.true_peak >= l.peak));
+
+    meter.reset();
+    assert_eq!(meter.levels(), vec![Level::default(); 2]);
+}
+
+#[test]
+fn metered_finds_intersample_peaks() {
+    // A sine at a quarter of the sample rate, sampled 45 degrees out of phase,
+    // peaks at 1.0 between samples that are all at 0.707.
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    let samples: Vec<f32> = (0..64).map(|i| ((i as f64 + 0.5) * PI / 2.0).sin() as f32).collect();
+    let level = meter_samples(&samples, spec).level(0);
+    assert!((level.peak - FRAC_1_SQRT_2).abs() < 1e-3);
+    assert!((level.rms - FRAC_1_SQRT_2).abs() < 1e-3);
+    assert!(level.true_peak > 0.98 && level.true_peak < 1.02, "true peak {}", level.true_peak);
+}
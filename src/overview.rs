@@ -0,0 +1,341 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Waveform overviews, for drawing a file at a glance.
+//!
+//! An overview divides the audio into a number of buckets of equal duration,
+//! and summarizes every bucket of every channel by its minimum, maximum, and
+//! RMS level. This is what editors draw when a file is zoomed out. Computing
+//! it takes one pass over the file, so the result can be cached in a sidecar
+//! file next to the audio.
+//!
+//! ```
+//! use hound::overview;
+//!
+//! let mut reader = hound::WavReader::open("testsamples/pop.wav").unwrap();
+//! let overview = overview::generate(&mut reader, 100).unwrap();
+//! for (min, max) in overview.channel(0) {
+//!     assert!(min <= max);
+//! }
+//! ```
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path;
+use crate::read::{next_f32, ReadExt, WavReader};
+use crate::write::WriteExt;
+use super::{Error, Result};
+
+/// The magic bytes at the start of a sidecar file.
+///
+/// Sidecars with the older `HPKS` magic stored the frame count as u32; they
+/// are rejected and regenerated by `generate_cached()`.
+const SIDECAR_MAGIC: &[u8; 4] = b"HPK2";
+
+/// The summary of one bucket of one channel.
+///
+/// Levels are in the range of float samples, see `int_to_f32()`. An empty
+/// bucket, which occurs when there are fewer frames than buckets, has all
+/// levels zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    /// The smallest sample in the bucket.
+    pub min: f32,
+    /// The largest sample in the bucket.
+    pub max: f32,
+    /// The root mean square of the samples in the bucket.
+    pub rms: f32,
+}
+
+/// The peaks of a file, per bucket and per channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Overview {
+    channels: u16,
+    frames: u64,
+    /// The peaks, interleaved by channel like samples.
+    peaks: Vec<Peak>,
+}
+
+impl Overview {
+    /// Returns the number of channels.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Returns the number of frames that the overview summarizes.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the number of buckets.
+    pub fn num_buckets(&self) -> usize {
+        self.peaks.len() / self.channels as usize
+    }
+
+    /// Returns the peaks of the given bucket, one per channel.
+    ///
+    /// Panics if the bucket is out of range.
+    pub fn bucket(&self, bucket: usize) -> &[Peak] {
+        let channels = self.channels as usize;
+        &self.peaks[bucket * channels..(bucket + 1) * channels]
+    }
+
+    /// Returns the peaks of the given channel, one per bucket.
+    ///
+    /// Panics if the channel is out of range.
+    pub fn channel_peaks(&self, channel: u16) -> Vec<Peak> {
+        assert!(channel < self.channels, "channel out of range");
+        self.peaks.iter().skip(channel as usize).step_by(self.channels as usize).cloned().collect()
+    }
+
+    /// Returns the minimum and maximum of every bucket of the given channel.
+    ///
+    /// Panics if the channel is out of range.
+    pub fn channel(&self, channel: u16) -> Vec<(f32, f32)> {
+        self.channel_peaks(channel).iter().map(|p| (p.min, p.max)).collect()
+    }
+
+    /// Writes the overview in the sidecar format.
+    ///
+    /// The format is four magic bytes `HPK2`, followed by the number of
+    /// channels (u16), frames (u64), and buckets (u32), and the minimum,
+    /// maximum, and RMS of every peak as f32, all little-endian.
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(SIDECAR_MAGIC)?;
+        writer.write_le_u16(self.channels)?;
+        writer.write_le_u64(self.frames)?;
+        writer.write_le_u32(self.num_buckets() as u32)?;
+        for peak in &self.peaks {
+            writer.write_le_f32(peak.min)?;
+            writer.write_le_f32(peak.max)?;
+            writer.write_le_f32(peak.rms)?;
+        }
+        Ok(())
+    }
+
+    /// Reads an overview in the sidecar format, see `write_to()`.
+    pub fn read_from<R: io::Read>(reader: &mut R) -> Result<Overview> {
+        let mut magic = [0; 4];
+        reader.read_into(&mut magic)?;
+        if &magic != SIDECAR_MAGIC {
+            return Err(Error::FormatError("no peak file magic"));
+        }
+        let channels = reader.read_le_u16()?;
+        if channels == 0 {
+            return Err(Error::ZeroChannels);
+        }
+        let frames_low = reader.read_le_u32()? as u64;
+        let frames = frames_low | (reader.read_le_u32()? as u64) << 32;
+        let buckets = reader.read_le_u32()?;
+        // The length is not trusted for the allocation, a truncated file
+        // fails when reading runs out.
+        let mut peaks = Vec::new();
+        for _ in 0..buckets as u64 * channels as u64 {
+            peaks.push(Peak {
+                min: reader.read_le_f32()?,
+                max: reader.read_le_f32()?,
+                rms: reader.read_le_f32()?,
+            });
+        }
+        Ok(Overview {
+            channels,
+            frames,
+            peaks,
+        })
+    }
+}
+
+/// The running state of one bucket of one channel.
+#[derive(Clone, Copy)]
+struct Accumulator {
+    min: f32,
+    max: f32,
+    sum_squares: f64,
+    count: u64,
+}
+
+impl Accumulator {
+    fn to_peak(self) -> Peak {
+        if self.count == 0 {
+            return Peak { min: 0.0, max: 0.0, rms: 0.0 };
+        }
+        Peak {
+            min: self.min,
+            max: self.max,
+            rms: (self.sum_squares / self.count as f64).sqrt() as f32,
+        }
+    }
+}
+
+/// Computes an overview of the remaining frames of the reader, in `buckets` buckets.
+///
+/// Frames are divided over the buckets as evenly as possible. Samples are
+/// converted to float as by `int_to_f32()`. Samples of an incomplete frame
+/// at the end are not included. Afterwards, the reader is positioned after
+/// the last frame.
+///
+/// Panics if `buckets` is 0.
+pub fn generate<R: io::Read>(reader: &mut WavReader<R>, buckets: u32) -> Result<Overview> {
+    assert!(buckets > 0, "an overview needs at least one bucket");
+    let channels = reader.spec().channels;
+    let frames = (reader.num_samples() - reader.samples_read()) / channels as u64;
+    let empty = Accumulator {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+        sum_squares: 0.0,
+        count: 0,
+    };
+    let mut accumulators = vec![empty; buckets as usize * channels as usize];
+
+    for frame in 0..frames {
+        let bucket = (frame as u128 * buckets as u128 / frames as u128) as usize;
+        let accumulators = &mut accumulators[bucket * channels as usize..];
+        for acc in &mut accumulators[..channels as usize] {
+            let x = next_f32(reader).expect("the frame is within the data chunk")?;
+            acc.min = acc.min.min(x);
+            acc.max = acc.max.max(x);
+            acc.sum_squares += x as f64 * x as f64;
+            acc.count += 1;
+        }
+    }
+
+    Ok(Overview {
+        channels,
+        frames,
+        peaks: accumulators.into_iter().map(Accumulator::to_peak).collect(),
+    })
+}
+
+/// Returns the path of the sidecar file for the given file, `<path>.peaks`.
+fn sidecar_path(path: &path::Path) -> path::PathBuf {
+    let mut sidecar = OsString::from(path.as_os_str());
+    sidecar.push(".peaks");
+    sidecar.into()
+}
+
+/// Returns the overview of the file at `path`, from its sidecar file if possible.
+///
+/// The sidecar file is `<path>.peaks`. It is used if it is at least as new
+/// as the file and has the requested number of buckets. Otherwise the
+/// overview is generated, and the sidecar file is written. Failure to write
+/// it is not an error, the overview is returned regardless.
+pub fn generate_cached<P: AsRef<path::Path>>(path: P, buckets: u32) -> Result<Overview> {
+    let path = path.as_ref();
+    let sidecar = sidecar_path(path);
+
+    let source_modified = fs::metadata(path)?.modified().ok();
+    let sidecar_modified = fs::metadata(&sidecar).and_then(|m| m.modified()).ok();
+    let fresh = match (source_modified, sidecar_modified) {
+        (Some(source), Some(cached)) => cached >= source,
+        _ => false,
+    };
+    if fresh {
+        let cached = fs::File::open(&sidecar).map_err(Error::from)
+            .and_then(|f| Overview::read_from(&mut io::BufReader::new(f)));
+        if let Ok(overview) = cached {
+            if overview.num_buckets() == buckets as usize {
+                return Ok(overview);
+            }
+        }
+    }
+
+    let mut reader = WavReader::open(path)?;
+    let overview = generate(&mut reader, buckets)?;
+    let _ = fs::File::create(&sidecar)
+        .and_then(|f| overview.write_to(&mut io::BufWriter::new(f)));
+    Ok(overview)
+}
+
+#[cfg(test)]
+fn ramp_file() -> Vec<u8> {
+    use crate::write::WavWriter;
+    use super::{SampleFormat, WavSpec};
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    for i in 0..10_i16 {
+        writer.write_sample(i * 1024).unwrap();
+        writer.write_sample(-i * 2048).unwrap();
+    }
+    writer.finalize().unwrap()
+}
+
+#[test]
+fn generate_computes_peaks_per_bucket_and_channel() {
+    let bytes = ramp_file();
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    let overview = generate(&mut reader, 4).unwrap();
+    assert_eq!(overview.channels(), 2);
+    assert_eq!(overview.frames(), 10);
+    assert_eq!(overview.num_buckets(), 4);
+
+    // Frames 0-2, 3-4, 5-7, and 8-9.
+    assert_eq!(overview.channel(0), vec![(0.0, 0.0625), (0.09375, 0.125),
+                                         (0.15625, 0.21875), (0.25, 0.28125)]);
+    assert_eq!(overview.channel(1), vec![(-0.125, 0.0), (-0.25, -0.1875),
+                                         (-0.4375, -0.3125), (-0.5625, -0.5)]);
+    let last = overview.bucket(3)[1];
+    assert_eq!(last.rms, ((0.5625_f64 * 0.5625 + 0.5 * 0.5) / 2.0).sqrt() as f32);
+
+    // More buckets than frames leaves some empty.
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    let overview = generate(&mut reader, 16).unwrap();
+    let peaks = overview.channel_peaks(0);
+    assert_eq!(peaks.iter().filter(|p| p.max > 0.0).count(), 9);
+    assert_eq!(peaks[2], Peak { min: 0.0, max: 0.0, rms: 0.0 });
+}
+
+#[test]
+fn sidecar_roundtrips_and_rejects_garbage() {
+    let bytes = ramp_file();
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    let overview = generate(&mut reader, 3).unwrap();
+
+    let mut sidecar = Vec::new();
+    overview.write_to(&mut sidecar).unwrap();
+    assert_eq!(sidecar.len(), 18 + 3 * 2 * 12);
+    assert_eq!(Overview::read_from(&mut &sidecar[..]).unwrap(), overview);
+
+    assert!(Overview::read_from(&mut &sidecar[..20]).is_err());
+    sidecar[0] = b'X';
+    assert!(Overview::read_from(&mut &sidecar[..]).is_err());
+}
+
+#[test]
+fn generate_cached_writes_and_reuses_sidecar() {
+    use std::env;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("hound-overview-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("ramp.wav");
+    fs::write(&path, ramp_file()).unwrap();
+
+    let overview = generate_cached(&path, 4).unwrap();
+    let sidecar = sidecar_path(&path);
+    assert_eq!(sidecar, dir.join("ramp.wav.peaks"));
+    assert!(sidecar.exists());
+    assert_eq!(generate_cached(&path, 4).unwrap(), overview);
+
+    // A cached overview with a different number of buckets is replaced.
+    assert_eq!(generate_cached(&path, 2).unwrap().num_buckets(), 2);
+    let cached = Overview::read_from(&mut fs::File::open(&sidecar).unwrap()).unwrap();
+    assert_eq!(cached.num_buckets(), 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,323 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+use crate::codec;
+use crate::write::{WavWriter, WriteExt};
+use super::{Endianness, Error, Result, SampleFormat, WavSpecEx};
+use crate::convert::{f64_to_int, NoiseGenerator};
+use super::write_int_sample;
+
+/// Specifies whether noise is added when converting float samples to integers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Round to the nearest integer. This is the default.
+    Off,
+    /// Add triangular (TPDF) noise with an amplitude of one least significant
+    /// bit before rounding. This decorrelates the quantization error from the
+    /// signal, at the cost of a slightly higher noise floor.
+    Triangular,
+}
+
+/// A block of samples to be converted by a worker thread.
+struct Block {
+    /// The sequence number of the block, which determines the write order.
+    index: u64,
+    samples: Vec<f32>,
+    dither: Dither,
+}
+
+/// Encodes a block of float samples in the format described by `spec_ex`.
+fn convert_block(block: &Block, spec_ex: WavSpecEx, endianness: Endianness) -> Vec<u8> {
+    trace_span!(TRACE, "convert_block", index = block.index, len = block.samples.len());
+    let mut bytes = encode_block(block, spec_ex);
+    codec::convert_byte_order(&mut bytes, spec_ex.bytes_per_sample, Endianness::Little, endianness);
+    bytes
+}
+
+/// Encodes a block of float samples in little-endian order.
+fn encode_block(block: &Block, spec_ex: WavSpecEx) -> Vec<u8> {
+    let spec = spec_ex.spec;
+    let mut bytes = Vec::with_capacity(block.samples.len() * spec_ex.bytes_per_sample as usize);
+
+    if spec.sample_format == SampleFormat::Float {
+        for &x in &block.samples {
+            bytes.write_le_f32(x).expect("writing to a Vec does not fail");
+        }
+        return bytes;
+    }
+
+    let bits = spec.bits_per_sample;
+    let lsb = 1.0 / (1_u64 << (bits - 1)) as f64;
+    let mut noise = NoiseGenerator::new(block.index);
+    for &x in &block.samples {
+        let x = match block.dither {
+            Dither::Off => x as f64,
+            Dither::Triangular => x as f64 + (noise.next_f64() - noise.next_f64()) * lsb,
+        };
+        // The conversion clamps, so the sample always fits.
+        write_int_sample(&mut bytes, f64_to_int(x, bits), bits, spec_ex.bytes_per_sample)
+            .expect("converted sample fits");
+    }
+    bytes
+}
+
+/// A writer that converts float samples on worker threads.
+///
+/// This is returned by `WavWriter::with_parallel_conversion()`. Blocks of
+/// samples passed to `write_samples()` are converted to the spec of the
+/// underlying `WavWriter` by a pool of worker threads, while the thread that
+/// owns the `ParallelWriter` writes converted blocks in order. For large
+/// offline renders, where conversion and dither take as long as the IO,
+/// this keeps both busy.
+///
+/// The writer must be finalized with `finalize()`. When it is dropped
+/// instead, blocks that were not written yet are lost. If a worker thread
+/// panics, the methods return an error of kind `Other`.
+pub struct ParallelWriter<W>
+    where W: io::Write + io::Seek
+{
+    writer: WavWriter<W>,
+    dither: Dither,
+    blocks: mpsc::Sender<Block>,
+    converted: mpsc::Receiver<(u64, Vec<u8>)>,
+    workers: Vec<thread::JoinHandle<()>>,
+
+    /// Converted blocks that arrived before their predecessors.
+    pending: BTreeMap<u64, Vec<u8>>,
+
+    /// The index of the next block to be submitted.
+    next_submit: u64,
+
+    /// The index of the next block to be written.
+    next_write: u64,
+
+    /// The number of blocks that may be submitted but not yet written.
+    max_in_flight: u64,
+}
+
+impl<W> WavWriter<W>
+    where W: io::Write + io::Seek
+{
+    /// Converts the writer into one that converts float samples on `threads` worker threads.
+    ///
+    /// Samples are converted to the spec of this writer as by `f64_to_int()`,
+    /// optionally with dither, see `ParallelWriter::set_dither()`. For a
+    /// float spec, the samples are written unchanged. Returns
+    /// `Error::Unsupported` if the spec has more than 32 bits per sample,
+    /// because `f64_to_int()` cannot produce such samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is 0.
+    pub fn with_parallel_conversion(self, threads: usize) -> Result<ParallelWriter<W>> {
+        assert!(threads > 0, "parallel conversion requires at least one thread");
+        let spec_ex = self.spec_ex();
+        if spec_ex.spec.bits_per_sample > 32 {
+            return Err(Error::Unsupported);
+        }
+        let endianness = self.endianness();
+        let (block_sender, block_receiver) = mpsc::channel::<Block>();
+        let (converted_sender, converted_receiver) = mpsc::channel();
+        let block_receiver = Arc::new(Mutex::new(block_receiver));
+
+        let workers = (0..threads).map(|_| {
+            let blocks = block_receiver.clone();
+            let converted = converted_sender.clone();
+            thread::spawn(move || loop {
+                // The lock is released at the end of the statement, so other
+                // workers can receive while this one converts.
+                let block = match blocks.lock().unwrap().recv() {
+                    Ok(block) => block,
+                    Err(..) => return,
+                };
+                let bytes = convert_block(&block, spec_ex, endianness);
+                if converted.send((block.index, bytes)).is_err() {
+                    return;
+                }
+            })
+        }).collect();
+
+        Ok(ParallelWriter {
+            writer: self,
+            dither: Dither::Off,
+            blocks: block_sender,
+            converted: converted_receiver,
+            workers,
+            pending: BTreeMap::new(),
+            next_submit: 0,
+            next_write: 0,
+            max_in_flight: 2 * threads as u64,
+        })
+    }
+}
+
+impl<W> ParallelWriter<W>
+    where W: io::Write + io::Seek
+{
+    /// Sets the dither applied to blocks written after this call.
+    ///
+    /// Dither is off by default. Dither is deterministic: writing the same
+    /// blocks yields the same file, regardless of the number of threads.
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    /// Submits a block of samples for conversion.
+    ///
+    /// Channel data is interleaved, as for `WavWriter::write_sample()`, but a
+    /// block need not consist of whole frames. Blocks are written in the
+    /// order in which they are submitted. This blocks when too many blocks
+    /// are waiting to be written, so memory usage stays bounded.
+    ///
+    /// Converted blocks are written as they become available, so an error
+    /// returned by this method may concern an earlier block. The block that
+    /// failed to be written is lost.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        while self.next_submit - self.next_write >= self.max_in_flight {
+            self.write_next()?;
+        }
+
+        let block = Block {
+            index: self.next_submit,
+            samples: samples.to_vec(),
+            dither: self.dither,
+        };
+        self.blocks.send(block).map_err(|_| worker_failed())?;
+        self.next_submit += 1;
+
+        // Write the blocks that are done, without waiting for others.
+        while let Ok((index, bytes)) = self.converted.try_recv() {
+            self.pending.insert(index, bytes);
+        }
+        self.write_ready()
+    }
+
+    /// Writes converted blocks for as long as they are available in order.
+    fn write_ready(&mut self) -> Result<()> {
+        while let Some(bytes) = self.pending.remove(&self.next_write) {
+            self.next_write += 1;
+            self.writer.write_raw_data(&bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the next block to be converted, and writes it.
+    fn write_next(&mut self) -> Result<()> {
+        while !self.pending.contains_key(&self.next_write) {
+            let (index, bytes) = self.converted.recv().map_err(|_| worker_failed())?;
+            self.pending.insert(index, bytes);
+        }
+        self.write_ready()
+    }
+
+    /// Writes all remaining blocks, stops the worker threads, and finalizes the file.
+    ///
+    /// See `WavWriter::finalize()`.
+    pub fn finalize(mut self) -> Result<()> {
+        while self.next_write < self.next_submit {
+            self.write_next()?;
+        }
+        let ParallelWriter { writer, blocks, workers, .. } = self;
+        drop(blocks);
+        for worker in workers {
+            worker.join().map_err(|_| worker_failed())?;
+        }
+        writer.finalize()
+    }
+}
+
+/// Returns the error for a worker thread that stopped, which happens only if it panicked.
+fn worker_failed() -> Error {
+    Error::IoError(io::Error::new(io::ErrorKind::Other, "a conversion thread panicked"))
+}
+
+#[cfg(test)]
+fn parallel_test_input() -> Vec<Vec<f32>> {
+    (0..20_u32).map(|i| {
+        (0..(i * 37 % 101 + 1)).map(|j| ((i * 101 + j) as f32 * 0.013).sin() * 1.1).collect()
+    }).collect()
+}
+
+#[cfg(test)]
+fn write_parallel(threads: usize, dither: Dither, bits: u16) -> Vec<i32> {
+    use crate::read::WavReader;
+    use super::WavSpec;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: bits,
+        sample_format: SampleFormat::Int,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap().with_parallel_conversion(threads).unwrap();
+        writer.set_dither(dither);
+        for block in &parallel_test_input() {
+            writer.write_samples(block).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+    buffer.set_position(0);
+    let mut reader = WavReader::new(buffer).unwrap();
+    reader.samples().map(|r| r.unwrap()).collect()
+}
+
+#[test]
+fn parallel_conversion_matches_sequential_conversion() {
+    let expected: Vec<i32> = parallel_test_input().iter()
+                                                  .flat_map(|b| b.iter())
+                                                  .map(|&x| f64_to_int(x as f64, 16))
+                                                  .collect();
+    assert_eq!(write_parallel(1, Dither::Off, 16), expected);
+    assert_eq!(write_parallel(3, Dither::Off, 16), expected);
+
+    let expected: Vec<i32> = parallel_test_input().iter()
+                                                  .flat_map(|b| b.iter())
+                                                  .map(|&x| f64_to_int(x as f64, 24))
+                                                  .collect();
+    assert_eq!(write_parallel(4, Dither::Off, 24), expected);
+}
+
+#[test]
+fn parallel_dither_is_deterministic_and_small() {
+    let plain = write_parallel(2, Dither::Off, 16);
+    let dithered = write_parallel(2, Dither::Triangular, 16);
+    assert_eq!(dithered, write_parallel(5, Dither::Triangular, 16));
+    assert!(plain != dithered);
+    for (&x, &y) in plain.iter().zip(dithered.iter()) {
+        assert!((x - y).abs() <= 1);
+    }
+}
+
+#[test]
+fn parallel_conversion_rejects_samples_wider_than_32_bits() {
+    use super::WavSpec;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 48,
+        sample_format: SampleFormat::Int,
+    };
+    let writer = WavWriter::new(io::Cursor::new(Vec::new()), spec).unwrap();
+    match writer.with_parallel_conversion(2) {
+        Err(Error::Unsupported) => {}
+        Err(err) => panic!("expected Error::Unsupported, got {:?}", err),
+        Ok(..) => panic!("expected Error::Unsupported"),
+    }
+}
@@ -0,0 +1,455 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::io;
+use crate::read::{ReadExt, WavReader};
+use crate::write::{WavWriter, WriteExt};
+use super::{Error, Result, SampleFormat, WavSpec};
+use crate::convert::{f32_to_int, int_to_f32, signed_from_u8, u8_from_signed};
+
+/// A raw PCM sample format, as used by audio APIs that consume byte streams.
+///
+/// The names follow ffmpeg: `S16Le` is signed 16-bit little-endian, for
+/// example. As in WAVE, 8-bit samples are unsigned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmFormat {
+    /// Unsigned 8-bit integers, with silence at 128.
+    U8,
+    /// Signed 16-bit little-endian integers.
+    S16Le,
+    /// Signed 24-bit little-endian integers, packed in 3 bytes.
+    S24Le,
+    /// Signed 32-bit little-endian integers.
+    S32Le,
+    /// 32-bit little-endian IEEE floats, normally in the range [-1.0, 1.0].
+    F32Le,
+}
+
+impl PcmFormat {
+    /// Returns the number of bytes per sample.
+    pub fn bytes_per_sample(self) -> u16 {
+        self.bits_per_sample() / 8
+    }
+
+    /// Returns the number of bits per sample.
+    pub fn bits_per_sample(self) -> u16 {
+        match self {
+            PcmFormat::U8 => 8,
+            PcmFormat::S16Le => 16,
+            PcmFormat::S24Le => 24,
+            PcmFormat::S32Le | PcmFormat::F32Le => 32,
+        }
+    }
+
+    /// Returns whether samples are integers or floats.
+    pub fn sample_format(self) -> SampleFormat {
+        match self {
+            PcmFormat::F32Le => SampleFormat::Float,
+            _ => SampleFormat::Int,
+        }
+    }
+}
+
+/// A sample as decoded from the file, before conversion.
+#[derive(Clone, Copy)]
+enum Value {
+    /// An integer sample, with the number of bits per sample of the file.
+    Int(i32),
+    /// A float sample.
+    Float(f32),
+}
+
+impl Value {
+    /// Converts the sample to an integer with `to_bits` bits.
+    ///
+    /// Integers are widened by appending zero bits, and narrowed by
+    /// discarding the least significant bits.
+    fn to_int(self, from_bits: u16, to_bits: u16) -> i32 {
+        match self {
+            Value::Int(x) if to_bits >= from_bits => x << (to_bits - from_bits),
+            Value::Int(x) => x >> (from_bits - to_bits),
+            Value::Float(x) => f32_to_int(x, to_bits),
+        }
+    }
+
+    /// Converts the sample to a float, see `int_to_f32`.
+    fn to_f32(self, from_bits: u16) -> f32 {
+        match self {
+            Value::Int(x) => int_to_f32(x, from_bits),
+            Value::Float(x) => x,
+        }
+    }
+}
+
+/// A reader that yields the samples of a WAVE file as raw PCM bytes.
+///
+/// This is returned by `WavReader::into_pcm_read()`. Samples are converted
+/// to the requested `PcmFormat`, and channels remain interleaved. A read may
+/// end in the middle of a sample, the next read continues with the rest.
+///
+/// Errors that occur while decoding are returned as `io::Error`, with the
+/// original `Error` as its inner error, or the original error itself for IO
+/// errors. If an error occurs after some bytes were produced, those bytes are
+/// returned first, and the error is returned by the next read.
+pub struct PcmRead<R> {
+    reader: WavReader<R>,
+    format: PcmFormat,
+
+    /// The encoded bytes of the current sample.
+    buffer: [u8; 4],
+
+    /// The number of bytes of `buffer` that were returned already.
+    buffer_pos: usize,
+
+    /// The number of valid bytes in `buffer`.
+    buffer_len: usize,
+
+    /// An error to be returned by the next read.
+    error: Option<io::Error>,
+}
+
+impl<R> WavReader<R>
+    where R: io::Read
+{
+    /// Converts the reader into an `io::Read` that yields raw PCM bytes.
+    ///
+    /// The samples are decoded and converted to `format`: integers are
+    /// shifted to the requested bit depth, and integers and floats are
+    /// converted into each other as by `int_to_f32()` and `f32_to_int()`.
+    /// This makes it possible to pipe a file of any supported format into an
+    /// API that consumes a fixed PCM format, such as `s16le`.
+    pub fn into_pcm_read(self, format: PcmFormat) -> PcmRead<R> {
+        PcmRead {
+            reader: self,
+            format,
+            buffer: [0; 4],
+            buffer_pos: 0,
+            buffer_len: 0,
+            error: None,
+        }
+    }
+}
+
+impl<R> PcmRead<R>
+    where R: io::Read
+{
+    /// Returns the spec of the PCM stream.
+    ///
+    /// The number of channels and sample rate are those of the file, the
+    /// sample format and bit depth are those of the requested `PcmFormat`.
+    pub fn spec(&self) -> WavSpec {
+        WavSpec {
+            bits_per_sample: self.format.bits_per_sample(),
+            sample_format: self.format.sample_format(),
+            ..self.reader.spec()
+        }
+    }
+
+    /// Destroys the adapter and returns the underlying `WavReader`.
+    ///
+    /// Bytes of a partially returned sample are lost.
+    pub fn into_inner(self) -> WavReader<R> {
+        self.reader
+    }
+
+    /// Decodes the next sample into the buffer, returns false at the end.
+    fn decode_next(&mut self) -> io::Result<bool> {
+        let spec = self.reader.spec();
+        let next = match spec.sample_format {
+            SampleFormat::Int => self.reader.samples::<i32>().next().map(|r| r.map(Value::Int)),
+            SampleFormat::Float => self.reader.samples::<f32>().next().map(|r| r.map(Value::Float)),
+        };
+        let value = match next {
+            None => return Ok(false),
+            Some(result) => result.map_err(io::Error::from)?,
+        };
+
+        let from_bits = spec.bits_per_sample;
+        let len = {
+            let mut out = &mut self.buffer[..];
+            match self.format {
+                PcmFormat::U8 => {
+                    out.write_u8(u8_from_signed(value.to_int(from_bits, 8) as i8))?
+                }
+                PcmFormat::S16Le => out.write_le_i16(value.to_int(from_bits, 16) as i16)?,
+                PcmFormat::S24Le => out.write_le_i24(value.to_int(from_bits, 24))?,
+                PcmFormat::S32Le => out.write_le_i32(value.to_int(from_bits, 32))?,
+                PcmFormat::F32Le => out.write_le_f32(value.to_f32(from_bits))?,
+            }
+            self.format.bytes_per_sample() as usize
+        };
+        self.buffer_pos = 0;
+        self.buffer_len = len;
+        Ok(true)
+    }
+}
+
+impl<R> io::Read for PcmRead<R>
+    where R: io::Read
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        let mut n = 0;
+        while n < buf.len() {
+            if self.buffer_pos == self.buffer_len {
+                match self.decode_next() {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(err) => {
+                        if n == 0 {
+                            return Err(err);
+                        }
+                        self.error = Some(err);
+                        break;
+                    }
+                }
+            }
+            let len = cmp::min(self.buffer_len - self.buffer_pos, buf.len() - n);
+            buf[n..n + len].copy_from_slice(&self.buffer[self.buffer_pos..self.buffer_pos + len]);
+            self.buffer_pos += len;
+            n += len;
+        }
+        Ok(n)
+    }
+}
+
+/// A writer that accepts raw PCM bytes and writes them as samples of a WAVE file.
+///
+/// This is returned by `WavWriter::into_pcm_write()`. The bytes are decoded
+/// as samples in the declared `PcmFormat`, converted to the spec of the
+/// `WavWriter`, and written with `WavWriter::write_sample()`. Channels must be
+/// interleaved. A write may end in the middle of a sample, the remaining
+/// bytes are buffered until the next write completes the sample.
+///
+/// If writing a sample fails, the bytes of that sample are discarded, and the
+/// error is returned as `io::Error`, like in `PcmRead`.
+pub struct PcmWrite<W>
+    where W: io::Write + io::Seek
+{
+    writer: WavWriter<W>,
+    format: PcmFormat,
+
+    /// The bytes of the sample that is being received.
+    buffer: [u8; 4],
+
+    /// The number of bytes of `buffer` that were received.
+    buffer_len: usize,
+}
+
+impl<W> WavWriter<W>
+    where W: io::Write + io::Seek
+{
+    /// Converts the writer into an `io::Write` that accepts raw PCM bytes.
+    ///
+    /// The bytes are interpreted as samples in `format`, and converted to the
+    /// spec of this writer the same way as `WavReader::into_pcm_read()`
+    /// converts them. This makes it possible to record from an API that
+    /// produces a stream of bytes, without decoding the samples first.
+    pub fn into_pcm_write(self, format: PcmFormat) -> PcmWrite<W> {
+        PcmWrite {
+            writer: self,
+            format,
+            buffer: [0; 4],
+            buffer_len: 0,
+        }
+    }
+}
+
+impl<W> PcmWrite<W>
+    where W: io::Write + io::Seek
+{
+    /// Finalizes the underlying `WavWriter`, see `WavWriter::finalize()`.
+    ///
+    /// If the bytes written do not end at a sample boundary, the file is
+    /// finalized without the incomplete sample, and `Error::UnfinishedSample`
+    /// is returned.
+    pub fn finalize(self) -> Result<()> {
+        let unfinished = self.buffer_len > 0;
+        self.writer.finalize()?;
+        if unfinished {
+            Err(Error::UnfinishedSample)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Destroys the adapter and returns the underlying `WavWriter`.
+    ///
+    /// The bytes of an incomplete sample are lost.
+    pub fn into_inner(self) -> WavWriter<W> {
+        self.writer
+    }
+
+    /// Decodes the sample in the buffer and writes it.
+    fn encode_buffer(&mut self) -> Result<()> {
+        self.buffer_len = 0;
+        let mut bytes = &self.buffer[..];
+        let value = match self.format {
+            PcmFormat::U8 => Value::Int(signed_from_u8(bytes.read_u8()?) as i32),
+            PcmFormat::S16Le => Value::Int(bytes.read_le_i16()? as i32),
+            PcmFormat::S24Le => Value::Int(bytes.read_le_i24()?),
+            PcmFormat::S32Le => Value::Int(bytes.read_le_i32()?),
+            PcmFormat::F32Le => Value::Float(bytes.read_le_f32()?),
+        };
+
+        let from_bits = self.format.bits_per_sample();
+        let spec = self.writer.spec();
+        match spec.sample_format {
+            SampleFormat::Int => {
+                self.writer.write_sample(value.to_int(from_bits, spec.bits_per_sample))
+            }
+            SampleFormat::Float => self.writer.write_sample(value.to_f32(from_bits)),
+        }
+    }
+}
+
+impl<W> io::Write for PcmWrite<W>
+    where W: io::Write + io::Seek
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let sample_len = self.format.bytes_per_sample() as usize;
+        let mut n = 0;
+        while n < buf.len() {
+            let len = cmp::min(sample_len - self.buffer_len, buf.len() - n);
+            self.buffer[self.buffer_len..self.buffer_len + len].copy_from_slice(&buf[n..n + len]);
+            self.buffer_len += len;
+            n += len;
+            if self.buffer_len == sample_len {
+                self.encode_buffer().map_err(io::Error::from)?;
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn pcm_read_converts_samples() {
+    use std::io::Read;
+
+    let open = || WavReader::open("testsamples/waveformatex-16bit-44100Hz-stereo.wav").unwrap();
+
+    let mut bytes = Vec::new();
+    open().into_pcm_read(PcmFormat::S16Le).read_to_end(&mut bytes).unwrap();
+    assert_eq!(&bytes[..4], &[2, 0, 0xfd, 0xff]);
+    assert_eq!(bytes.len(), 16);
+
+    let mut bytes = Vec::new();
+    open().into_pcm_read(PcmFormat::S24Le).read_to_end(&mut bytes).unwrap();
+    assert_eq!(&bytes[..6], &[0, 2, 0, 0, 0xfd, 0xff]);
+
+    let mut bytes = Vec::new();
+    open().into_pcm_read(PcmFormat::U8).read_to_end(&mut bytes).unwrap();
+    assert_eq!(&bytes[..], &[128, 127, 128, 127, 128, 127, 128, 127]);
+
+    let mut pcm = open().into_pcm_read(PcmFormat::F32Le);
+    assert_eq!(pcm.spec(), WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    });
+    let mut bytes = Vec::new();
+    pcm.read_to_end(&mut bytes).unwrap();
+    let mut samples = &bytes[..];
+    let first = crate::read::ReadExt::read_le_f32(&mut samples).unwrap();
+    assert_eq!(first, 2.0 / 32768.0);
+}
+
+#[test]
+fn pcm_read_handles_partial_samples() {
+    use std::io::Read;
+
+    let open = || WavReader::open("testsamples/waveformatex-16bit-44100Hz-stereo.wav").unwrap();
+
+    let mut expected = Vec::new();
+    open().into_pcm_read(PcmFormat::S24Le).read_to_end(&mut expected).unwrap();
+
+    // Read in chunks that do not line up with samples.
+    let mut pcm = open().into_pcm_read(PcmFormat::S24Le);
+    let mut bytes = Vec::new();
+    let mut buf = [0u8; 5];
+    loop {
+        let n = pcm.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn pcm_write_converts_and_buffers_partial_samples() {
+    use std::io::Write;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let writer = WavWriter::new(&mut buffer, spec).unwrap();
+        let mut pcm = writer.into_pcm_write(PcmFormat::S16Le);
+
+        // The samples 2, -3, 32767, -32768, split across sample boundaries.
+        pcm.write_all(&[2]).unwrap();
+        pcm.write_all(&[0, 0xfd, 0xff, 0xff]).unwrap();
+        pcm.write_all(&[0x7f, 0x00, 0x80]).unwrap();
+        pcm.finalize().unwrap();
+    }
+
+    buffer.set_position(0);
+    let mut reader = WavReader::new(buffer).unwrap();
+    let samples: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[2 << 8, -3 << 8, 32767 << 8, -32768 << 8]);
+}
+
+#[test]
+fn pcm_write_signals_unfinished_sample() {
+    use std::io::Write;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let writer = WavWriter::new(&mut buffer, spec).unwrap();
+        let mut pcm = writer.into_pcm_write(PcmFormat::U8);
+        pcm.write_all(&[0, 128, 192]).unwrap();
+        pcm.finalize().unwrap();
+    }
+    buffer.set_position(0);
+    let mut reader = WavReader::new(&mut buffer).unwrap();
+    let samples: Vec<f32> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[-1.0, 0.0, 0.5]);
+
+    let writer = WavWriter::new(io::Cursor::new(Vec::new()), spec).unwrap();
+    let mut pcm = writer.into_pcm_write(PcmFormat::S16Le);
+    pcm.write_all(&[1, 0, 2]).unwrap();
+    match pcm.finalize() {
+        Err(Error::UnfinishedSample) => {}
+        _ => panic!("an incomplete sample should signal an error"),
+    }
+}
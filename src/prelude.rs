@@ -0,0 +1,34 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The types needed by most programs that read or write wav files.
+//!
+//! ```
+//! use hound::prelude::*;
+//!
+//! let spec = WavSpec {
+//!     channels: 1,
+//!     sample_rate: 44100,
+//!     bits_per_sample: 16,
+//!     sample_format: SampleFormat::Int,
+//! };
+//! let mut writer = WavWriter::new_in_memory(spec).unwrap();
+//! writer.write_sample(1_i16).unwrap();
+//! let bytes = writer.finalize().unwrap();
+//! let mut reader = WavReader::new(&bytes[..]).unwrap();
+//! assert_eq!(reader.samples::<i16>().next().unwrap().unwrap(), 1);
+//! ```
+
+pub use crate::error::{Error, Result};
+pub use crate::read::WavReader;
+pub use crate::write::WavWriter;
+pub use crate::{Sample, SampleFormat, WavSpec, WavSpecEx};
@@ -0,0 +1,492 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copying of WAVE files chunk by chunk, without decoding the audio.
+
+use std::io;
+use std::io::Read;
+use crate::chunks::ChunkInfo;
+use crate::codec::swap_u32;
+use crate::error::{Error, Result};
+use crate::read::{ReadExt, WavReader};
+use crate::soundbank::read_up_to;
+use super::Endianness;
+
+/// Specifies how `remux()` changes the chunks of a file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RemuxOptions {
+    /// Chunks to write, as pairs of chunk id and contents.
+    ///
+    /// A chunk replaces the first chunk in the file with the same id, and
+    /// later chunks with that id are left out. If the file has no chunk with
+    /// the id before the data chunk, the chunk is written before the data
+    /// chunk instead.
+    pub set_chunks: Vec<([u8; 4], Vec<u8>)>,
+
+    /// The ids of chunks to leave out, such as `*b"LIST"`.
+    pub remove_chunks: Vec<[u8; 4]>,
+}
+
+/// Copies a WAVE file chunk by chunk, adding, replacing, or removing chunks.
+///
+/// The audio data is copied verbatim, it is not decoded, so this is fast even
+/// for huge files, and it works for any format tag. The data chunk is always
+/// copied; a `data` id in the options is ignored. The sizes in the output are
+/// recomputed, which also repairs files that were not finalized properly:
+///
+///  * A chunk that is cut off by the end of the input is shortened to the
+///    bytes that are present, and copying stops there.
+///  * Anything after the RIFF form, such as appended garbage, is dropped. If
+///    the RIFF size ends before the data chunk, it is ignored, and chunks are
+///    copied up to the end of the input.
+///
+/// The writer must be positioned where the output should start. RF64 input
+/// results in `Error::Unsupported`, and output that does not fit in a RIFF
+/// file in `Error::TooLarge`. If the input has no data chunk,
+/// `Error::NoDataChunkFound` is returned, after the other chunks have been
+/// copied.
+pub fn remux<R, W>(mut reader: R, mut writer: W, options: &RemuxOptions) -> Result<()>
+    where R: io::Read,
+          W: io::Write + io::Seek
+{
+    let mut header = [0u8; 12];
+    reader.read_into(&mut header)?;
+    let endianness = match &header[..4] {
+        b"RIFF" => Endianness::Little,
+        b"RIFX" => Endianness::Big,
+        b"RF64" => return Err(Error::Unsupported),
+        _ => return Err(Error::FormatError("no RIFF tag found")),
+    };
+    if &header[8..12] != b"WAVE" {
+        return Err(Error::FormatError("no WAVE tag found"));
+    }
+    let riff_end = 8 + u32_from(&header[4..8], endianness) as u64;
+
+    let start = writer.stream_position()?;
+    writer.write_all(&header)?;
+
+    // The offset in the input, and the length of the output form after its
+    // size field, which counts the form type.
+    let mut offset = 12;
+    let mut form_len: u64 = 4;
+    let mut pending: Vec<&([u8; 4], Vec<u8>)> = options.set_chunks.iter()
+                                                      .filter(|&&(id, _)| &id != b"data")
+                                                      .collect();
+    let mut seen_data = false;
+    while !(seen_data && offset >= riff_end) {
+        let mut chunk_header = [0u8; 8];
+        if read_up_to(&mut reader, &mut chunk_header)? < 8 {
+            break;
+        }
+        offset += 8;
+        let mut id = [0u8; 4];
+        id.copy_from_slice(&chunk_header[..4]);
+        let len = u32_from(&chunk_header[4..], endianness);
+        let padded_len = len as u64 + len as u64 % 2;
+        let is_data = &id == b"data";
+
+        if is_data && !seen_data {
+            for &&(id, ref contents) in &pending {
+                form_len += write_chunk(&mut writer, id, contents, endianness)?;
+            }
+            pending.clear();
+        }
+
+        let is_set = options.set_chunks.iter().any(|&(set_id, _)| set_id == id);
+        if !is_data && (is_set || options.remove_chunks.contains(&id)) {
+            if let Some(i) = pending.iter().position(|&&(set_id, _)| set_id == id) {
+                let (_, ref contents) = *pending.remove(i);
+                form_len += write_chunk(&mut writer, id, contents, endianness)?;
+            }
+            let skipped = io::copy(&mut io::Read::take(&mut reader, padded_len), &mut io::sink())?;
+            offset += skipped;
+            if skipped < padded_len {
+                break;
+            }
+            continue;
+        }
+
+        let header_pos = writer.stream_position()?;
+        writer.write_all(&chunk_header)?;
+        let copied = io::copy(&mut io::Read::take(&mut reader, len as u64), &mut writer)?;
+        seen_data = seen_data || is_data;
+        if copied < len as u64 {
+            // The input ends halfway through the chunk, keep what is there.
+            writer.seek(io::SeekFrom::Start(header_pos + 4))?;
+            writer.write_all(&u32_to(copied as u32, endianness))?;
+            writer.seek(io::SeekFrom::Start(header_pos + 8 + copied))?;
+            if copied % 2 == 1 {
+                writer.write_all(&[0])?;
+            }
+            form_len += 8 + copied + copied % 2;
+            break;
+        }
+        if len % 2 == 1 {
+            read_up_to(&mut reader, &mut [0u8; 1])?;
+            writer.write_all(&[0])?;
+        }
+        offset += padded_len;
+        form_len += 8 + padded_len;
+    }
+
+    if !seen_data {
+        return Err(Error::NoDataChunkFound);
+    }
+    for &&(id, ref contents) in &pending {
+        form_len += write_chunk(&mut writer, id, contents, endianness)?;
+    }
+    if form_len > 0xffff_ffff {
+        return Err(Error::TooLarge);
+    }
+    let end = writer.stream_position()?;
+    writer.seek(io::SeekFrom::Start(start + 4))?;
+    writer.write_all(&u32_to(form_len as u32, endianness))?;
+    writer.seek(io::SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Copies the frames `start..end` of a file to a new file, with its metadata.
+///
+/// The audio data is copied verbatim, the samples are not decoded. The other
+/// chunks of the file are copied too, adjusted to the region:
+///
+///  * Cue points in the region are moved along with the audio, cue points
+///    outside of it are removed. The `cue ` chunk is left out if no cue
+///    points remain.
+///  * The time reference of a `bext` chunk is advanced to the start of the
+///    region, and the sample count of a `fact` chunk is set to its length.
+///  * An `MD5 ` chunk is left out, because it would not match.
+///
+/// The writer must be positioned where the output should start. The file is
+/// read through the underlying reader of `reader`, which is positioned where
+/// it was afterwards. If the region extends beyond the end of the file, or
+/// `start` is after `end`, an error of kind `InvalidInput` is returned. If the
+/// region does not fit in the data chunk of a RIFF file, `Error::TooLarge` is
+/// returned.
+pub fn export_region<R, W>(reader: &mut WavReader<R>, start: u64, end: u64, writer: W) -> Result<()>
+    where R: io::Read + io::Seek,
+          W: io::Write + io::Seek
+{
+    if start > end || end > reader.num_frames() {
+        let err = io::Error::new(io::ErrorKind::InvalidInput,
+                                 "region extends beyond the end of the data chunk");
+        return Err(Error::IoError(err));
+    }
+    let block_align = reader.spec_ex().block_align() as u64;
+    if (end - start) * block_align > 0xffff_ffff {
+        return Err(Error::TooLarge);
+    }
+    let data = reader.data_chunk();
+    let (riff_start, current) = reader.riff_start()?;
+    let region = Region {
+        start,
+        end,
+        block_align,
+    };
+    let result = export_region_at(reader.get_mut(), riff_start, data, region, writer);
+    reader.get_mut().seek(io::SeekFrom::Start(current))?;
+    result
+}
+
+/// A range of frames to export.
+#[derive(Clone, Copy)]
+struct Region {
+    start: u64,
+    end: u64,
+    block_align: u64,
+}
+
+fn export_region_at<R, W>(reader: &mut R,
+                          riff_start: u64,
+                          data: ChunkInfo,
+                          region: Region,
+                          mut writer: W)
+                          -> Result<()>
+    where R: io::Read + io::Seek,
+          W: io::Write + io::Seek
+{
+    reader.seek(io::SeekFrom::Start(riff_start))?;
+    let mut header = [0u8; 12];
+    reader.read_into(&mut header)?;
+    let endianness = match &header[..4] {
+        b"RIFX" => Endianness::Big,
+        _ => Endianness::Little,
+    };
+    let riff_end = 8 + u32_from(&header[4..8], endianness) as u64;
+
+    let start = writer.stream_position()?;
+    writer.write_all(&header)?;
+    let mut form_len: u64 = 4;
+    let mut offset = 12;
+    while offset + 8 <= riff_end || offset <= data.offset {
+        reader.seek(io::SeekFrom::Start(riff_start + offset))?;
+        let mut chunk_header = [0u8; 8];
+        if read_up_to(reader, &mut chunk_header)? < 8 {
+            break;
+        }
+        let mut id = [0u8; 4];
+        id.copy_from_slice(&chunk_header[..4]);
+        let len = u32_from(&chunk_header[4..], endianness);
+        let chunk_offset = offset;
+        offset += 8 + len as u64 + len as u64 % 2;
+
+        if chunk_offset == data.offset {
+            let data_len = (region.end - region.start) * region.block_align;
+            reader.seek(io::SeekFrom::Current(region.start as i64 * region.block_align as i64))?;
+            writer.write_all(b"data")?;
+            writer.write_all(&u32_to(data_len as u32, endianness))?;
+            if io::copy(&mut io::Read::take(&mut *reader, data_len), &mut writer)? < data_len {
+                return Err(Error::IoError(io::ErrorKind::UnexpectedEof.into()));
+            }
+            writer.write_all(&[0][..data_len as usize % 2])?;
+            form_len += 8 + data_len + data_len % 2;
+            continue;
+        }
+
+        let contents = match &id {
+            b"MD5 " => continue,
+            b"cue " | b"fact" | b"bext" => {
+                // The length is not trusted for the allocation.
+                let mut contents = Vec::new();
+                io::Read::take(&mut *reader, len as u64).read_to_end(&mut contents)?;
+                if contents.len() < len as usize {
+                    return Err(Error::IoError(io::ErrorKind::UnexpectedEof.into()));
+                }
+                contents
+            }
+            _ => {
+                writer.write_all(&chunk_header)?;
+                if io::copy(&mut io::Read::take(&mut *reader, len as u64), &mut writer)? < len as u64 {
+                    return Err(Error::IoError(io::ErrorKind::UnexpectedEof.into()));
+                }
+                writer.write_all(&[0][..len as usize % 2])?;
+                form_len += 8 + len as u64 + len as u64 % 2;
+                continue;
+            }
+        };
+        let contents = match &id {
+            b"cue " => match adjust_cue_points(&contents, region, endianness) {
+                Some(cue) => cue,
+                None => continue,
+            },
+            b"fact" if contents.len() >= 4 => {
+                let mut fact = contents;
+                // The length fits, because the data chunk length does.
+                let frames = (region.end - region.start) as u32;
+                fact[..4].copy_from_slice(&u32_to(frames, endianness));
+                fact
+            }
+            b"bext" if contents.len() >= 346 => {
+                // The time reference is a 64-bit sample count at offset 338.
+                let mut bext = contents;
+                let low = u32_from(&bext[338..342], endianness) as u64;
+                let high = u32_from(&bext[342..346], endianness) as u64;
+                let time_reference = (high << 32 | low).wrapping_add(region.start);
+                bext[338..342].copy_from_slice(&u32_to(time_reference as u32, endianness));
+                bext[342..346].copy_from_slice(&u32_to((time_reference >> 32) as u32, endianness));
+                bext
+            }
+            _ => contents,
+        };
+        form_len += write_chunk(&mut writer, id, &contents, endianness)?;
+    }
+
+    let end = writer.stream_position()?;
+    writer.seek(io::SeekFrom::Start(start + 4))?;
+    writer.write_all(&u32_to(form_len as u32, endianness))?;
+    writer.seek(io::SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Returns the contents of a `cue ` chunk with only the cue points in the region.
+///
+/// The cue points are moved to their position relative to the start of the
+/// region. Returns `None` if no cue points remain.
+fn adjust_cue_points(cue: &[u8], region: Region, endianness: Endianness) -> Option<Vec<u8>> {
+    let mut new_cue = vec![0; 4];
+    for point in cue.get(4..)?.chunks_exact(24) {
+        // The field dwSampleOffset is the last field of a cue point, and
+        // dwPosition is usually the same.
+        let position = u32_from(&point[4..8], endianness);
+        let sample_offset = u32_from(&point[20..24], endianness);
+        if (sample_offset as u64) < region.start || sample_offset as u64 >= region.end {
+            continue;
+        }
+        let moved = (sample_offset as u64 - region.start) as u32;
+        let mut point = point.to_vec();
+        point[20..24].copy_from_slice(&u32_to(moved, endianness));
+        if position == sample_offset {
+            point[4..8].copy_from_slice(&u32_to(moved, endianness));
+        }
+        new_cue.extend_from_slice(&point);
+    }
+    if new_cue.len() == 4 {
+        return None;
+    }
+    let num_points = ((new_cue.len() - 4) / 24) as u32;
+    new_cue[..4].copy_from_slice(&u32_to(num_points, endianness));
+    Some(new_cue)
+}
+
+/// Writes a chunk with its padding byte, returns the number of bytes written.
+fn write_chunk<W: io::Write>(writer: &mut W,
+                             id: [u8; 4],
+                             contents: &[u8],
+                             endianness: Endianness)
+                             -> Result<u64> {
+    if contents.len() > 0xffff_ffff {
+        return Err(Error::TooLarge);
+    }
+    writer.write_all(&id)?;
+    writer.write_all(&u32_to(contents.len() as u32, endianness))?;
+    writer.write_all(contents)?;
+    let padding = contents.len() % 2;
+    writer.write_all(&[0][..padding])?;
+    Ok(8 + contents.len() as u64 + padding as u64)
+}
+
+fn u32_from(bytes: &[u8], endianness: Endianness) -> u32 {
+    swap_u32(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]), endianness)
+}
+
+fn u32_to(x: u32, endianness: Endianness) -> [u8; 4] {
+    swap_u32(x, endianness).to_le_bytes()
+}
+
+#[cfg(test)]
+fn remux_to_vec(input: &[u8], options: &RemuxOptions) -> Result<Vec<u8>> {
+    let mut output = io::Cursor::new(Vec::new());
+    remux(input, &mut output, options)?;
+    Ok(output.into_inner())
+}
+
+#[test]
+fn remux_replaces_and_removes_chunks() {
+    use crate::read::WavReader;
+    use crate::testutil::WavBuilder;
+
+    let input = WavBuilder::new()
+        .fmt_pcm(1, 8000, 16)
+        .chunk(b"LIST", b"INFOold")
+        .chunk(b"JUNK", &[0; 6])
+        .chunk(b"data", &[1, 0, 2, 0, 3, 0])
+        .chunk(b"LIST", b"INFOdup")
+        .chunk(b"cue ", &[0; 4])
+        .build();
+    let options = RemuxOptions {
+        set_chunks: vec![(*b"LIST", b"INFOnew".to_vec()), (*b"bext", vec![7; 3])],
+        remove_chunks: vec![*b"JUNK"],
+    };
+    let output = remux_to_vec(&input, &options).unwrap();
+
+    let expected = WavBuilder::new()
+        .fmt_pcm(1, 8000, 16)
+        .chunk(b"LIST", b"INFOnew")
+        .chunk(b"bext", &[7; 3])
+        .chunk(b"data", &[1, 0, 2, 0, 3, 0])
+        .chunk(b"cue ", &[0; 4])
+        .build();
+    assert_eq!(output, expected);
+
+    let mut reader = WavReader::new(&output[..]).unwrap();
+    assert_eq!(reader.samples::<i16>().map(|s| s.unwrap()).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(remux_to_vec(&input, &RemuxOptions::default()).unwrap(), input);
+}
+
+#[test]
+fn remux_repairs_sizes() {
+    use crate::testutil::WavBuilder;
+
+    // A file that was never finalized, with a stale RIFF size and a data
+    // chunk that claims more bytes than there are, followed by garbage.
+    let mut input = WavBuilder::new()
+        .fmt_pcm(1, 8000, 8)
+        .chunk_with_len(b"data", 1000, &[1, 2, 3])
+        .riff_len(36)
+        .build();
+    let output = remux_to_vec(&input, &RemuxOptions::default()).unwrap();
+    let expected = WavBuilder::new()
+        .fmt_pcm(1, 8000, 8)
+        .chunk(b"data", &[1, 2, 3])
+        .build();
+    assert_eq!(output, expected);
+
+    input = expected.clone();
+    input.extend_from_slice(b"garbage!");
+    assert_eq!(remux_to_vec(&input, &RemuxOptions::default()).unwrap(), expected);
+
+    let no_data = WavBuilder::new().fmt_pcm(1, 8000, 8).build();
+    match remux_to_vec(&no_data, &RemuxOptions::default()) {
+        Err(Error::NoDataChunkFound) => {}
+        other => panic!("expected NoDataChunkFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn export_region_copies_frames_and_adjusts_metadata() {
+    use crate::testutil::WavBuilder;
+
+    fn cue_point(name: u8, frame: u8) -> Vec<u8> {
+        let mut point = vec![name, 0, 0, 0, frame, 0, 0, 0];
+        point.extend_from_slice(b"data");
+        point.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, frame, 0, 0, 0]);
+        point
+    }
+    let mut cue = vec![3, 0, 0, 0];
+    for &(name, frame) in &[(1, 2), (2, 5), (3, 9)] {
+        cue.extend_from_slice(&cue_point(name, frame));
+    }
+    let mut bext = vec![0; 602];
+    bext[338] = 100;
+    let samples: Vec<u8> = (0..20).collect();
+    let input = WavBuilder::new()
+        .fmt_pcm(1, 8000, 16)
+        .chunk(b"bext", &bext)
+        .chunk(b"fact", &[10, 0, 0, 0])
+        .chunk(b"data", &samples)
+        .chunk(b"cue ", &cue)
+        .chunk(b"MD5 ", &[0; 16])
+        .chunk(b"LIST", b"INFO")
+        .build();
+
+    let mut reader = WavReader::new_seekable(io::Cursor::new(input)).unwrap();
+    assert_eq!(reader.samples::<i16>().next().unwrap().unwrap(), 0x0100);
+    let mut output = io::Cursor::new(Vec::new());
+    export_region(&mut reader, 3, 8, &mut output).unwrap();
+    // The reader continues where it was.
+    assert_eq!(reader.samples::<i16>().next().unwrap().unwrap(), 0x0302);
+
+    let mut expected_cue = vec![1, 0, 0, 0];
+    expected_cue.extend_from_slice(&cue_point(2, 2));
+    bext[338] = 103;
+    let expected = WavBuilder::new()
+        .fmt_pcm(1, 8000, 16)
+        .chunk(b"bext", &bext)
+        .chunk(b"fact", &[5, 0, 0, 0])
+        .chunk(b"data", &samples[6..16])
+        .chunk(b"cue ", &expected_cue)
+        .chunk(b"LIST", b"INFO")
+        .build();
+    assert_eq!(output.into_inner(), expected);
+
+    // Without cue points in the region, the cue chunk is left out.
+    let mut output = io::Cursor::new(Vec::new());
+    export_region(&mut reader, 6, 7, &mut output).unwrap();
+    let mut exported = WavReader::new(&output.get_ref()[..]).unwrap();
+    assert_eq!(exported.samples::<i16>().map(|s| s.unwrap()).collect::<Vec<_>>(), vec![0x0d0c]);
+    assert!(!output.get_ref().windows(4).any(|w| w == b"cue "));
+
+    match export_region(&mut reader, 5, 11, io::Cursor::new(Vec::new())) {
+        Err(Error::IoError(ref err)) if err.kind() == io::ErrorKind::InvalidInput => {}
+        other => panic!("expected InvalidInput, got {:?}", other),
+    }
+}
@@ -0,0 +1,226 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::UnsafeCell;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::write::WavWriter;
+use super::{Result, Sample};
+
+/// The ring buffer shared by a `WavSink` and its `WavSinkDrain`.
+///
+/// There is exactly one producer and one consumer. The producer owns the
+/// slots from `head` up to `tail + capacity`, the consumer owns the slots from
+/// `tail` up to `head`. Both indices only increase; they are reduced modulo
+/// the capacity when indexing.
+struct Ring<S> {
+    slots: Box<[UnsafeCell<S>]>,
+
+    /// The number of samples pushed so far.
+    head: AtomicUsize,
+
+    /// The number of samples drained so far.
+    tail: AtomicUsize,
+
+    /// The number of frames that were dropped because the buffer was full.
+    overruns: AtomicUsize,
+}
+
+// The producer and consumer never access the same slot at the same time, and
+// ownership of slots is transferred through the atomic indices.
+unsafe impl<S: Send> Sync for Ring<S> {}
+
+/// The real-time half of a capture buffer, which accepts frames.
+///
+/// A `WavSink` is intended to be used from an audio callback: pushing frames
+/// never blocks, locks, or allocates. The frames are written to a file by the
+/// corresponding `WavSinkDrain`, typically on a different thread.
+///
+/// # Example
+///
+/// ```
+/// use std::io;
+/// use hound::{SampleFormat, WavSink, WavSpec, WavWriter};
+///
+/// let spec = WavSpec {
+///     channels: 2,
+///     sample_rate: 44100,
+///     bits_per_sample: 16,
+///     sample_format: SampleFormat::Int,
+/// };
+/// let mut writer = WavWriter::new(io::Cursor::new(Vec::new()), spec).unwrap();
+/// let (mut sink, mut drain) = WavSink::<i16>::new(2, 1024);
+///
+/// // In the audio callback:
+/// sink.push_frame(&[3, -3]);
+///
+/// // On the disk thread:
+/// drain.drain_to(&mut writer).unwrap();
+/// ```
+pub struct WavSink<S> {
+    ring: Arc<Ring<S>>,
+    channels: usize,
+}
+
+/// The disk half of a capture buffer, which writes frames to a `WavWriter`.
+pub struct WavSinkDrain<S> {
+    ring: Arc<Ring<S>>,
+}
+
+impl<S: Sample + Copy + Default> WavSink<S> {
+    /// Creates a capture buffer that holds `capacity` frames of `channels` samples.
+    ///
+    /// This is the only point at which memory is allocated.
+    pub fn new(channels: u16, capacity: usize) -> (WavSink<S>, WavSinkDrain<S>) {
+        assert!(channels > 0, "a sink must have at least one channel");
+        let len = capacity * channels as usize;
+        let slots: Vec<UnsafeCell<S>> = (0..len).map(|_| UnsafeCell::new(S::default())).collect();
+        let ring = Arc::new(Ring {
+            slots: slots.into_boxed_slice(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            overruns: AtomicUsize::new(0),
+        });
+        let sink = WavSink {
+            ring: ring.clone(),
+            channels: channels as usize,
+        };
+        (sink, WavSinkDrain { ring })
+    }
+
+    /// Pushes one frame, with one sample for every channel.
+    ///
+    /// Returns false if the buffer is full. In that case the frame is
+    /// dropped, and the overrun is counted.
+    ///
+    /// Panics if the length of `frame` is not the number of channels.
+    pub fn push_frame(&mut self, frame: &[S]) -> bool {
+        assert_eq!(frame.len(), self.channels);
+        let ring = &*self.ring;
+        let capacity = ring.slots.len();
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) + frame.len() > capacity {
+            ring.overruns.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        for (i, &sample) in frame.iter().enumerate() {
+            let slot = &ring.slots[head.wrapping_add(i) % capacity];
+            unsafe { *slot.get() = sample; }
+        }
+        ring.head.store(head.wrapping_add(frame.len()), Ordering::Release);
+        true
+    }
+
+    /// Returns the number of frames that were dropped because the buffer was full.
+    pub fn overruns(&self) -> usize {
+        self.ring.overruns.load(Ordering::Relaxed)
+    }
+}
+
+impl<S: Sample + Copy> WavSinkDrain<S> {
+    /// Writes all frames in the buffer to `writer`, and returns the number of samples written.
+    ///
+    /// If writing a sample fails, the samples before it have been removed
+    /// from the buffer, and the offending sample is removed as well.
+    pub fn drain_to<W>(&mut self, writer: &mut WavWriter<W>) -> Result<usize>
+        where W: io::Write + io::Seek
+    {
+        let ring = &*self.ring;
+        let capacity = ring.slots.len();
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        let n = head.wrapping_sub(tail);
+        for i in 0..n {
+            let sample = unsafe { *ring.slots[tail.wrapping_add(i) % capacity].get() };
+            if let Err(err) = writer.write_sample(sample) {
+                ring.tail.store(tail.wrapping_add(i + 1), Ordering::Release);
+                return Err(err);
+            }
+        }
+        ring.tail.store(head, Ordering::Release);
+        Ok(n)
+    }
+
+    /// Returns the number of frames that were dropped because the buffer was full.
+    pub fn overruns(&self) -> usize {
+        self.ring.overruns.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+fn sink_spec() -> crate::WavSpec {
+    crate::WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: crate::SampleFormat::Int,
+    }
+}
+
+#[test]
+fn sink_drops_frames_when_full() {
+    let mut buffer = io::Cursor::new(Vec::new());
+    let (mut sink, mut drain) = WavSink::<i16>::new(2, 2);
+    assert!(sink.push_frame(&[1, -1]));
+    assert!(sink.push_frame(&[2, -2]));
+    assert!(!sink.push_frame(&[3, -3]));
+    assert_eq!(drain.overruns(), 1);
+    {
+        let mut writer = WavWriter::new(&mut buffer, sink_spec()).unwrap();
+        assert_eq!(drain.drain_to(&mut writer).unwrap(), 4);
+        assert_eq!(drain.drain_to(&mut writer).unwrap(), 0);
+        assert!(sink.push_frame(&[4, -4]));
+        assert_eq!(drain.drain_to(&mut writer).unwrap(), 2);
+        writer.finalize().unwrap();
+    }
+
+    buffer.set_position(0);
+    let mut reader = crate::read::WavReader::new(buffer).unwrap();
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1, -1, 2, -2, 4, -4]);
+}
+
+#[test]
+fn sink_transfers_frames_between_threads() {
+    use std::thread;
+
+    let (mut sink, mut drain) = WavSink::<i16>::new(2, 16);
+    let producer = thread::spawn(move || {
+        let mut i = 0;
+        while i < 1000 {
+            if sink.push_frame(&[i, -i]) {
+                i += 1;
+            } else {
+                thread::yield_now();
+            }
+        }
+    });
+
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, sink_spec()).unwrap();
+        let mut written = 0;
+        while written < 2000 {
+            written += drain.drain_to(&mut writer).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+    producer.join().unwrap();
+
+    buffer.set_position(0);
+    let mut reader = crate::read::WavReader::new(buffer).unwrap();
+    for (i, frame) in reader.blocks::<i16>(1).enumerate() {
+        assert_eq!(frame.unwrap(), vec![i as i16, -(i as i16)]);
+    }
+}
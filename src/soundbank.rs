@@ -0,0 +1,198 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Splitting of blobs that contain several WAVE files back to back.
+//!
+//! Game soundbanks and broadcast logs often store many WAVE files in one
+//! large file, sometimes with alignment padding or other data in between.
+
+use std::cmp;
+use std::io;
+use crate::chunks::ChunkInfo;
+use crate::read::SubReader;
+
+/// The number of bytes that are searched for a RIFF header at a time.
+const SCAN_BUFFER_LEN: usize = 4096;
+
+/// Finds the RIFF and RIFX forms of type WAVE in the underlying reader.
+///
+/// The reader is scanned from its start. After each form, the scan continues
+/// at the end of the form as stated in its header; anything that is not a
+/// WAVE form is skipped byte by byte. The offsets of the returned forms are
+/// relative to the start of the reader, and their length is the size field
+/// of the RIFF header, which excludes the 8-byte RIFF header itself. A form
+/// may claim to extend past the end of the reader, if the last file is
+/// truncated.
+///
+/// The position of the reader is unspecified afterwards.
+pub fn find_wav_forms<R>(reader: &mut R) -> io::Result<Vec<ChunkInfo>>
+    where R: io::Read + io::Seek
+{
+    let end = reader.seek(io::SeekFrom::End(0))?;
+    let mut buffer = [0u8; SCAN_BUFFER_LEN];
+    let mut forms = Vec::new();
+    let mut offset = 0;
+    while offset + 12 <= end {
+        reader.seek(io::SeekFrom::Start(offset))?;
+        let n = read_up_to(reader, &mut buffer)?;
+        match find_wave_header(&buffer[..n]) {
+            Some(i) => {
+                let header = &buffer[i..i + 12];
+                let mut id = [0u8; 4];
+                id.copy_from_slice(&header[..4]);
+                let size = [header[4], header[5], header[6], header[7]];
+                let len = if &id == b"RIFX" { u32::from_be_bytes(size) } else { u32::from_le_bytes(size) };
+                let form_offset = offset + i as u64;
+                forms.push(ChunkInfo { id, offset: form_offset, len });
+                // A form must at least contain its type. Forms of odd length
+                // are followed by a padding byte.
+                let form_len = cmp::max(len as u64, 4) + len as u64 % 2;
+                offset = form_offset + 8 + form_len;
+            }
+            // The last 11 bytes may be the start of a header, search them again.
+            None if n >= 12 => offset += n as u64 - 11,
+            None => break,
+        }
+    }
+    Ok(forms)
+}
+
+/// Splits the underlying reader into one sub-reader per WAVE file.
+///
+/// The files are found with `find_wav_forms()`. Every sub-reader covers one
+/// file, including its RIFF header, and a file that claims to extend past
+/// the end of the reader ends where the reader does. The sub-readers are
+/// independent of each other, which requires a reader that can be cloned,
+/// such as `io::Cursor<&[u8]>` or `&fs::File`. Clones of a `&fs::File` share
+/// one cursor, but every sub-reader seeks to its own position before it
+/// reads. Pass a sub-reader to `WavReader::new_seekable()` to decode its file.
+pub fn split_wav_files<R>(mut reader: R) -> io::Result<Vec<SubReader<R>>>
+    where R: io::Read + io::Seek + Clone
+{
+    let end = reader.seek(io::SeekFrom::End(0))?;
+    let forms = find_wav_forms(&mut reader)?;
+    let mut files = Vec::with_capacity(forms.len());
+    for form in forms {
+        let len = cmp::min(form.len as u64 + 8, end - form.offset);
+        files.push(SubReader::new(reader.clone(), form.offset, len)?);
+    }
+    Ok(files)
+}
+
+/// Returns the index of the first RIFF or RIFX header of a WAVE form in `bytes`.
+fn find_wave_header(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 12 {
+        return None;
+    }
+    (0..bytes.len() - 11).find(|&i| {
+        (&bytes[i..i + 4] == b"RIFF" || &bytes[i..i + 4] == b"RIFX") && &bytes[i + 8..i + 12] == b"WAVE"
+    })
+}
+
+/// Reads until the buffer is full or the end of the reader is reached.
+pub(crate) fn read_up_to<R: io::Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buffer.len() {
+        match reader.read(&mut buffer[n..]) {
+            Ok(0) => break,
+            Ok(progress) => n += progress,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(n)
+}
+
+#[test]
+fn split_wav_files_finds_concatenated_files() {
+    use crate::read::WavReader;
+    use crate::write::WavWriter;
+    use crate::{Endianness, SampleFormat, WavSpec};
+
+    let write = |channels: u16, samples: &[i16], endianness: Endianness| {
+        let spec = WavSpec {
+            channels,
+            sample_rate: 22050,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut cursor = io::Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new(&mut cursor, spec).unwrap();
+            writer.set_endianness(endianness).unwrap();
+            writer.write_samples(samples).unwrap();
+            writer.finalize().unwrap();
+        }
+        cursor.into_inner()
+    };
+
+    // A bank header, a file, alignment padding, a big-endian file, and a
+    // file that is cut off.
+    let mut blob = b"BANK\x03\x00\x00\x00RIFF".to_vec();
+    blob.extend_from_slice(&write(1, &[1, 2, 3], Endianness::Little));
+    blob.extend_from_slice(&[0; 7]);
+    blob.extend_from_slice(&write(2, &[4, 5, 6, 7], Endianness::Big));
+    let last = write(1, &[8, 9, 10, 11], Endianness::Little);
+    blob.extend_from_slice(&last[..last.len() - 2]);
+
+    let forms = find_wav_forms(&mut io::Cursor::new(&blob[..])).unwrap();
+    assert_eq!(forms.iter().map(|f| &f.id).collect::<Vec<_>>(), [b"RIFF", b"RIFX", b"RIFF"]);
+    assert_eq!(forms[0].offset, 12);
+
+    let files = split_wav_files(io::Cursor::new(&blob[..])).unwrap();
+    assert_eq!(files.len(), 3);
+    let mut decoded = Vec::new();
+    for file in files {
+        let mut reader = WavReader::new_seekable(file).unwrap();
+        decoded.push(reader.samples::<i16>().map(|r| r.unwrap_or(0)).collect::<Vec<_>>());
+    }
+    assert_eq!(decoded, [vec![1, 2, 3], vec![4, 5, 6, 7], vec![8, 9, 10, 0]]);
+}
+
+#[test]
+fn split_wav_files_reads_interleaved_from_a_shared_file() {
+    use std::{env, fs, process};
+    use crate::read::WavReader;
+    use crate::write::WavWriter;
+    use crate::{SampleFormat, WavSpec};
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 22050,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut blob = Vec::new();
+    for samples in &[[1_i16, 2, 3], [4, 5, 6]] {
+        let mut writer = WavWriter::new_in_memory(spec).unwrap();
+        writer.write_samples(&samples[..]).unwrap();
+        blob.extend_from_slice(&writer.finalize().unwrap());
+    }
+    let path = env::temp_dir().join(format!("hound-soundbank-{}.bin", process::id()));
+    fs::write(&path, &blob).unwrap();
+
+    // Clones of a `&fs::File` share one cursor, reading from the readers in
+    // turn must not mix up their positions.
+    let file = fs::File::open(&path).unwrap();
+    let mut readers: Vec<_> = split_wav_files(&file).unwrap()
+        .into_iter()
+        .map(|f| WavReader::new_seekable(f).unwrap())
+        .collect();
+    let mut decoded = vec![Vec::new(), Vec::new()];
+    for _ in 0..3 {
+        for (reader, samples) in readers.iter_mut().zip(&mut decoded) {
+            samples.push(reader.samples::<i16>().next().unwrap().unwrap());
+        }
+    }
+    fs::remove_file(&path).unwrap();
+    assert_eq!(decoded, [vec![1, 2, 3], vec![4, 5, 6]]);
+}
@@ -0,0 +1,283 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of streams that consist of several WAVE files back to back.
+
+use std::io;
+use std::marker;
+use std::mem;
+use crate::read::WavReader;
+use super::{Result, Sample, WavSpec};
+
+/// The number of bytes that are read from the underlying reader at a time.
+const READ_AHEAD_LEN: usize = 4096;
+
+/// An item of a `WavStream`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamEvent<S> {
+    /// A WAVE header was found, the samples that follow have this spec.
+    Spec(WavSpec),
+    /// The next sample of the current file.
+    Sample(S),
+}
+
+/// A reader that can put bytes back, so headers can be looked for ahead.
+struct Pushback<R> {
+    inner: R,
+    /// Bytes that were read from `inner` ahead, starting at `pos`.
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: io::Read> Pushback<R> {
+    /// Returns up to `n` of the next bytes, without consuming them.
+    ///
+    /// Fewer bytes are returned only at the end of the stream. More bytes
+    /// than requested are read ahead, so that scanning for a header does not
+    /// read byte by byte from the underlying reader.
+    fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.buffer.len() - self.pos < n {
+            self.buffer.drain(..self.pos);
+            self.pos = 0;
+            let mut bytes = [0u8; READ_AHEAD_LEN];
+            while self.buffer.len() < n {
+                match self.inner.read(&mut bytes) {
+                    Ok(0) => break,
+                    Ok(k) => self.buffer.extend_from_slice(&bytes[..k]),
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        let end = self.buffer.len().min(self.pos + n);
+        Ok(&self.buffer[self.pos..end])
+    }
+
+    /// Returns whether the end of the stream has been reached.
+    fn at_end(&mut self) -> io::Result<bool> {
+        Ok(self.peek(1)?.is_empty())
+    }
+
+    /// Returns whether the next bytes are the header of a RIFF or RIFX form of type WAVE.
+    fn at_wave_header(&mut self) -> io::Result<bool> {
+        let header = self.peek(12)?;
+        Ok(header.len() == 12 &&
+           (&header[..4] == b"RIFF" || &header[..4] == b"RIFX") &&
+           &header[8..12] == b"WAVE")
+    }
+}
+
+impl<R: io::Read> io::Read for Pushback<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.buffer.len() {
+            let n = buf.len().min(self.buffer.len() - self.pos);
+            buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        } else {
+            self.inner.read(buf)
+        }
+    }
+}
+
+enum State<R> {
+    /// Looking for the next WAVE header.
+    Scanning(Pushback<R>),
+    /// Reading the samples of a file.
+    Samples(WavReader<Pushback<R>>),
+    /// The stream ended, or a header could not be parsed.
+    Done,
+}
+
+/// An iterator over a stream of several WAVE files, such as a long capture of an internet radio stream.
+///
+/// Such streams restart with a new RIFF header whenever the source
+/// reconnects or changes format. Instead of failing at the next header,
+/// `WavStream` yields a `StreamEvent::Spec` for every file, followed by its
+/// samples, so the stream can be segmented. Anything between files, such as
+/// chunks after a data chunk or garbage, is skipped.
+///
+/// Streaming encoders often cannot fill in the length of the data chunk. If
+/// it is 0xffffffff, or 0 in a file whose RIFF length is 0 or 0xffffffff as
+/// well, the samples continue until the next header or the end of the
+/// stream. A file that was finalized with zero samples yields only its spec.
+/// A header is also recognized where a frame would start
+/// inside a data chunk whose stated length has not been reached, because a
+/// capture may be cut off anywhere. An error in a header ends the stream
+/// after the error is yielded.
+pub struct WavStream<R, S> {
+    state: State<R>,
+    phantom_sample: marker::PhantomData<fn() -> S>,
+}
+
+impl<R, S> WavStream<R, S>
+    where R: io::Read,
+          S: Sample
+{
+    /// Creates a stream that decodes samples as `S` from the underlying reader.
+    ///
+    /// The first file need not start at the start of the reader, bytes
+    /// before the first header are skipped.
+    pub fn new(reader: R) -> WavStream<R, S> {
+        let pushback = Pushback {
+            inner: reader,
+            buffer: Vec::new(),
+            pos: 0,
+        };
+        WavStream {
+            state: State::Scanning(pushback),
+            phantom_sample: marker::PhantomData,
+        }
+    }
+
+    /// Skips bytes until the next header, and starts reading the file there.
+    fn next_file(&mut self, mut source: Pushback<R>) -> Option<Result<StreamEvent<S>>> {
+        loop {
+            match source.at_wave_header().and_then(|header| Ok((header, source.at_end()?))) {
+                Ok((true, _)) => break,
+                Ok((false, true)) => return None,
+                Ok((false, false)) => source.pos += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+        match WavReader::new_streaming(source) {
+            Ok(reader) => {
+                let spec = reader.spec();
+                self.state = State::Samples(reader);
+                Some(Ok(StreamEvent::Spec(spec)))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Destroys the stream, returns the underlying reader.
+    ///
+    /// Bytes that were read ahead to look for a header are lost.
+    pub fn into_inner(self) -> Option<R> {
+        match self.state {
+            State::Scanning(source) => Some(source.inner),
+            State::Samples(reader) => Some(reader.into_inner().inner),
+            State::Done => None,
+        }
+    }
+}
+
+impl<R, S> Iterator for WavStream<R, S>
+    where R: io::Read,
+          S: Sample
+{
+    type Item = Result<StreamEvent<S>>;
+
+    fn next(&mut self) -> Option<Result<StreamEvent<S>>> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Done => return None,
+                State::Scanning(source) => return self.next_file(source),
+                State::Samples(mut reader) => {
+                    let channels = reader.spec().channels as u64;
+                    if reader.samples_read() % channels == 0 {
+                        let source = reader.get_mut();
+                        match source.at_wave_header().and_then(|header| Ok((header, source.at_end()?))) {
+                            Ok((true, _)) => {
+                                self.state = State::Scanning(reader.into_inner());
+                                continue;
+                            }
+                            // In a data chunk of unknown length, the end of the
+                            // stream is the end of the data.
+                            Ok((false, true)) => return None,
+                            Ok((false, false)) => {}
+                            Err(err) => {
+                                self.state = State::Samples(reader);
+                                return Some(Err(err.into()));
+                            }
+                        }
+                    }
+                    match reader.samples::<S>().next() {
+                        Some(sample) => {
+                            self.state = State::Samples(reader);
+                            return Some(sample.map(StreamEvent::Sample));
+                        }
+                        None => self.state = State::Scanning(reader.into_inner()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn wav_bytes(spec: WavSpec, samples: &[i16]) -> Vec<u8> {
+    use crate::write::WavWriter;
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    writer.write_samples(samples).unwrap();
+    writer.finalize().unwrap()
+}
+
+#[test]
+fn stream_yields_a_spec_for_every_file() {
+    use super::SampleFormat;
+
+    let mono = WavSpec {
+        channels: 1,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let stereo = WavSpec { channels: 2, sample_rate: 44100, ..mono };
+    let mut bytes = b"junk".to_vec();
+    bytes.extend(wav_bytes(mono, &[1, 2, 3]));
+    bytes.extend_from_slice(&[0; 5]);
+    bytes.extend(wav_bytes(stereo, &[]));
+    bytes.extend(wav_bytes(stereo, &[4, 5, 6, 7]));
+
+    let events: Vec<StreamEvent<i16>> = WavStream::new(&bytes[..]).map(|e| e.unwrap()).collect();
+    assert_eq!(events, vec![
+        StreamEvent::Spec(mono),
+        StreamEvent::Sample(1), StreamEvent::Sample(2), StreamEvent::Sample(3),
+        StreamEvent::Spec(stereo),
+        StreamEvent::Spec(stereo),
+        StreamEvent::Sample(4), StreamEvent::Sample(5), StreamEvent::Sample(6), StreamEvent::Sample(7),
+    ]);
+}
+
+#[test]
+fn stream_handles_placeholder_and_cut_off_data_chunks() {
+    use super::SampleFormat;
+    use crate::testutil::WavBuilder;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    // A capture that is cut off by a new header, with a placeholder length,
+    // and with a length that is never reached.
+    let mut bytes = WavBuilder::new()
+        .fmt_pcm(2, 8000, 16)
+        .chunk_with_len(b"data", 1000, &[1, 0, 2, 0])
+        .riff_len(0)
+        .build();
+    bytes.extend(WavBuilder::new()
+        .fmt_pcm(2, 8000, 16)
+        .chunk_with_len(b"data", 0xffff_ffff, &[3, 0, 4, 0, 5, 0, 6, 0])
+        .riff_len(0)
+        .build());
+
+    let events: Vec<StreamEvent<i16>> = WavStream::new(&bytes[..]).map(|e| e.unwrap()).collect();
+    assert_eq!(events, vec![
+        StreamEvent::Spec(spec),
+        StreamEvent::Sample(1), StreamEvent::Sample(2),
+        StreamEvent::Spec(spec),
+        StreamEvent::Sample(3), StreamEvent::Sample(4), StreamEvent::Sample(5), StreamEvent::Sample(6),
+    ]);
+}
@@ -0,0 +1,263 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use crate::write::WavWriter;
+use super::{Endianness, Error, Result, SampleFormat};
+#[cfg(test)]
+use super::WavSpec;
+
+/// The number of samples encoded per call to `WavWriter::write_raw_data()`.
+const BLOCK_LEN: usize = 1024;
+
+/// A writer for 16-bit integer files, with the sample type fixed at compile time.
+///
+/// `WavWriter::write_sample()` dispatches on the spec for every sample. This
+/// writer checks the spec once, when it is created, and encodes slices of
+/// samples in a straight-line loop that the optimizer can vectorize. Unlike
+/// `SampleWriter16`, it needs no buffer reserved up front, and it does not
+/// panic.
+///
+/// Samples written through a typed writer are not inspected by clip
+/// detection. Size limits do apply.
+pub struct WavWriter16<W>
+    where W: io::Write + io::Seek
+{
+    writer: WavWriter<W>,
+    big_endian: bool,
+}
+
+/// A writer for 32-bit float files, with the sample type fixed at compile time.
+///
+/// See `WavWriter16` for the rationale. This writer is only available with
+/// the `float` feature.
+#[cfg(feature = "float")]
+pub struct WavWriterF32<W>
+    where W: io::Write + io::Seek
+{
+    writer: WavWriter<W>,
+    big_endian: bool,
+}
+
+impl<W> WavWriter16<W>
+    where W: io::Write + io::Seek
+{
+    /// Wraps a writer whose spec has 16 bits per integer sample.
+    ///
+    /// Returns `Error::InvalidSampleFormat` for any other spec, including
+    /// 16 bits per sample stored in a wider container.
+    pub fn new(writer: WavWriter<W>) -> Result<WavWriter16<W>> {
+        let spec_ex = writer.spec_ex();
+        if spec_ex.spec.sample_format != SampleFormat::Int ||
+           spec_ex.spec.bits_per_sample != 16 ||
+           spec_ex.bytes_per_sample != 2 {
+            return Err(Error::InvalidSampleFormat);
+        }
+        Ok(WavWriter16 {
+            big_endian: writer.endianness() == Endianness::Big,
+            writer,
+        })
+    }
+
+    /// Writes a single sample for one channel.
+    pub fn write_sample(&mut self, sample: i16) -> Result<()> {
+        self.write_samples(&[sample])
+    }
+
+    /// Writes all samples in the slice.
+    ///
+    /// If an error occurs, some of the samples may have been written.
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        let mut bytes = [0u8; BLOCK_LEN * 2];
+        for block in samples.chunks(BLOCK_LEN) {
+            let bytes = &mut bytes[..block.len() * 2];
+            if self.big_endian {
+                for (dst, &x) in bytes.chunks_mut(2).zip(block) {
+                    dst[0] = (x >> 8) as u8;
+                    dst[1] = x as u8;
+                }
+            } else {
+                for (dst, &x) in bytes.chunks_mut(2).zip(block) {
+                    dst[0] = x as u8;
+                    dst[1] = (x >> 8) as u8;
+                }
+            }
+            self.writer.write_raw_data(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn get_ref(&self) -> &WavWriter<W> {
+        &self.writer
+    }
+
+    /// Destroys the typed writer and returns the underlying writer.
+    pub fn into_inner(self) -> WavWriter<W> {
+        self.writer
+    }
+
+    /// Updates the header and flushes the underlying writer.
+    ///
+    /// See `WavWriter::finalize()`.
+    pub fn finalize(self) -> Result<()> {
+        self.writer.finalize()
+    }
+}
+
+#[cfg(feature = "float")]
+impl<W> WavWriterF32<W>
+    where W: io::Write + io::Seek
+{
+    /// Wraps a writer whose spec has 32 bits per float sample.
+    ///
+    /// Returns `Error::InvalidSampleFormat` for any other spec.
+    pub fn new(writer: WavWriter<W>) -> Result<WavWriterF32<W>> {
+        let spec_ex = writer.spec_ex();
+        if spec_ex.spec.sample_format != SampleFormat::Float ||
+           spec_ex.spec.bits_per_sample != 32 ||
+           spec_ex.bytes_per_sample != 4 {
+            return Err(Error::InvalidSampleFormat);
+        }
+        Ok(WavWriterF32 {
+            big_endian: writer.endianness() == Endianness::Big,
+            writer,
+        })
+    }
+
+    /// Writes a single sample for one channel.
+    pub fn write_sample(&mut self, sample: f32) -> Result<()> {
+        self.write_samples(&[sample])
+    }
+
+    /// Writes all samples in the slice.
+    ///
+    /// If an error occurs, some of the samples may have been written.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        let mut bytes = [0u8; BLOCK_LEN * 4];
+        for block in samples.chunks(BLOCK_LEN) {
+            let bytes = &mut bytes[..block.len() * 4];
+            for (dst, &x) in bytes.chunks_mut(4).zip(block) {
+                let bits = if self.big_endian { x.to_bits().swap_bytes() } else { x.to_bits() };
+                dst[0] = bits as u8;
+                dst[1] = (bits >> 8) as u8;
+                dst[2] = (bits >> 16) as u8;
+                dst[3] = (bits >> 24) as u8;
+            }
+            self.writer.write_raw_data(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn get_ref(&self) -> &WavWriter<W> {
+        &self.writer
+    }
+
+    /// Destroys the typed writer and returns the underlying writer.
+    pub fn into_inner(self) -> WavWriter<W> {
+        self.writer
+    }
+
+    /// Updates the header and flushes the underlying writer.
+    ///
+    /// See `WavWriter::finalize()`.
+    pub fn finalize(self) -> Result<()> {
+        self.writer.finalize()
+    }
+}
+
+#[cfg(test)]
+fn write_both<F, G>(spec: WavSpec, big_endian: bool, typed: F, dynamic: G) -> (Vec<u8>, Vec<u8>)
+    where F: FnOnce(WavWriter<&mut io::Cursor<Vec<u8>>>),
+          G: FnOnce(&mut WavWriter<&mut io::Cursor<Vec<u8>>>)
+{
+    let mut a = io::Cursor::new(Vec::new());
+    let mut b = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut a, spec).unwrap();
+        if big_endian {
+            writer.set_endianness(Endianness::Big).unwrap();
+        }
+        typed(writer);
+    }
+    {
+        let mut writer = WavWriter::new(&mut b, spec).unwrap();
+        if big_endian {
+            writer.set_endianness(Endianness::Big).unwrap();
+        }
+        dynamic(&mut writer);
+        writer.finalize().unwrap();
+    }
+    (a.into_inner(), b.into_inner())
+}
+
+#[test]
+fn typed_writers_match_write_sample() {
+    let spec16 = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    #[cfg(feature = "float")]
+    let spec32 = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let ints: Vec<i16> = (0..2999).map(|i| (i * 37 - 30000) as i16).collect();
+    #[cfg(feature = "float")]
+    let floats: Vec<f32> = (0..3000).map(|i| (i as f32 * 0.01).sin()).collect();
+
+    for &big_endian in &[false, true] {
+        let (typed, dynamic) = write_both(spec16, big_endian, |writer| {
+            let mut writer = WavWriter16::new(writer).unwrap();
+            writer.write_sample(-1).unwrap();
+            writer.write_samples(&ints).unwrap();
+            writer.finalize().unwrap();
+        }, |writer| {
+            writer.write_sample(-1_i16).unwrap();
+            writer.write_samples(&ints).unwrap();
+        });
+        assert_eq!(typed, dynamic);
+
+        #[cfg(feature = "float")]
+        {
+            let (typed, dynamic) = write_both(spec32, big_endian, |writer| {
+                let mut writer = WavWriterF32::new(writer).unwrap();
+                writer.write_samples(&floats).unwrap();
+                writer.finalize().unwrap();
+            }, |writer| {
+                writer.write_samples(&floats).unwrap();
+            });
+            assert_eq!(typed, dynamic);
+        }
+    }
+}
+
+#[test]
+fn typed_writers_reject_other_specs() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let mut cursor = io::Cursor::new(Vec::new());
+    assert!(WavWriter16::new(WavWriter::new(&mut cursor, spec).unwrap()).is_err());
+    #[cfg(feature = "float")]
+    let mut cursor = io::Cursor::new(Vec::new());
+    #[cfg(feature = "float")]
+    assert!(WavWriterF32::new(WavWriter::new(&mut cursor, spec).unwrap()).is_err());
+}
@@ -10,16 +10,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Encoding of WAVE files: `WavWriter`, and the writers built on top of it.
+
+use std::cmp;
 use std::fs;
 use std::io;
 use std::mem;
+use std::ops;
 use std::io::Write;
 use std::path;
-use super::{Error, Result, Sample, SampleFormat, WavSpec};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::read::ReadExt;
+use super::{AmbisonicFormat, ChannelMask, Endianness, Error, Result, Sample, SampleFormat, WavSpec, WavSpecEx};
+use crate::chunks::{self, swap_fmt_fields};
+use crate::codec;
+use crate::md5::Md5;
 
 /// Extends the functionality of `io::Write` with additional methods.
 ///
-/// The methods may be used on any type that implements `io::Write`.
+/// The methods may be used on any type that implements `io::Write`. They are
+/// the counterpart of `ReadExt`.
+///
+/// ```
+/// use hound::WriteExt;
+///
+/// let mut buffer = Vec::new();
+/// buffer.write_le_u16(0x0102).unwrap();
+/// buffer.write_le_i24(-2).unwrap();
+/// assert_eq!(&buffer[..], &[0x02, 0x01, 0xfe, 0xff, 0xff]);
+/// ```
 pub trait WriteExt: io::Write {
     /// Writes an unsigned 8-bit integer.
     fn write_u8(&mut self, x: u8) -> io::Result<()>;
@@ -46,6 +66,9 @@ pub trait WriteExt: io::Write {
     /// Writes an unsigned 32-bit integer in little endian format.
     fn write_le_u32(&mut self, x: u32) -> io::Result<()>;
 
+    /// Writes an unsigned 64-bit integer in little endian format.
+    fn write_le_u64(&mut self, x: u64) -> io::Result<()>;
+
     /// Writes an IEEE float in little endian format.
     fn write_le_f32(&mut self, x: f32) -> io::Result<()>;
 }
@@ -80,8 +103,8 @@ impl<W> WriteExt for W
     #[inline(always)]
     fn write_le_u24(&mut self, x: u32) -> io::Result<()> {
         let mut buf = [0u8; 3];
-        buf[0] = ((x >> 00) & 0xff) as u8;
-        buf[1] = ((x >> 08) & 0xff) as u8;
+        buf[0] = (x & 0xff) as u8;
+        buf[1] = ((x >> 8) & 0xff) as u8;
         buf[2] = ((x >> 16) & 0xff) as u8;
         self.write_all(&buf)
     }
@@ -94,25 +117,81 @@ impl<W> WriteExt for W
     #[inline(always)]
     fn write_le_u32(&mut self, x: u32) -> io::Result<()> {
         let mut buf = [0u8; 4];
-        buf[0] = ((x >> 00) & 0xff) as u8;
-        buf[1] = ((x >> 08) & 0xff) as u8;
+        buf[0] = (x & 0xff) as u8;
+        buf[1] = ((x >> 8) & 0xff) as u8;
         buf[2] = ((x >> 16) & 0xff) as u8;
         buf[3] = ((x >> 24) & 0xff) as u8;
         self.write_all(&buf)
     }
 
+    #[inline(always)]
+    fn write_le_u64(&mut self, x: u64) -> io::Result<()> {
+        self.write_le_u32((x & 0xffff_ffff) as u32)?;
+        self.write_le_u32((x >> 32) as u32)
+    }
+
     #[inline(always)]
     fn write_le_f32(&mut self, x: f32) -> io::Result<()> {
-        let u = unsafe { mem::transmute(x) };
-        self.write_le_u32(u)
+        self.write_le_u32(x.to_bits())
+    }
+}
+
+/// Returns the layout that stores samples in the smallest number of whole bytes.
+fn spec_ex_for(spec: WavSpec) -> WavSpecEx {
+    WavSpecEx {
+        spec,
+        bytes_per_sample: ((spec.bits_per_sample as u32 + 7) / 8) as u16,
+    }
+}
+
+/// Writes a sample in the layout of `spec_ex`, checking its range only if `checked` is true.
+#[inline(always)]
+fn write_padded<S, W>(sample: S, writer: &mut W, spec_ex: WavSpecEx, checked: bool) -> Result<()>
+    where S: Sample,
+          W: io::Write
+{
+    let bits = spec_ex.spec.bits_per_sample;
+    if checked {
+        sample.write_padded(writer, bits, spec_ex.bytes_per_sample)
+    } else {
+        sample.write_padded_unchecked(writer, bits, spec_ex.bytes_per_sample)
     }
 }
 
 /// Generates a bitmask with `channels` ones in the least significant bits.
+///
+/// Only 18 speaker positions are defined, so for more than 18 channels the
+/// mask is 0, which indicates that the channels have no speaker position.
 fn channel_mask(channels: u16) -> u32 {
+    if channels > 18 {
+        return 0;
+    }
     (0..channels).map(|c| 1 << c).fold(0, |a, c| a | c)
 }
 
+#[test]
+fn write_ext_writes_little_endian_values() {
+    let mut buffer = Vec::new();
+    buffer.write_u8(0x80).unwrap();
+    buffer.write_le_i16(-32767).unwrap();
+    buffer.write_le_u16(0x8001).unwrap();
+    buffer.write_le_i24(-8_388_095).unwrap();
+    buffer.write_le_u24(0x80_02_01).unwrap();
+    buffer.write_le_i32(-2_147_286_527).unwrap();
+    buffer.write_le_u32(0x80_03_02_01).unwrap();
+    buffer.write_le_u64(0x80_07_06_05_04_03_02_01).unwrap();
+    buffer.write_le_f32(1.0).unwrap();
+    assert_eq!(&buffer[..], &[0x80,
+                              0x01, 0x80,
+                              0x01, 0x80,
+                              0x01, 0x02, 0x80,
+                              0x01, 0x02, 0x80,
+                              0x01, 0x02, 0x03, 0x80,
+                              0x01, 0x02, 0x03, 0x80,
+                              0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x80,
+                              0x00, 0x00, 0x80, 0x3f]);
+}
+
 #[test]
 fn verify_channel_mask() {
     assert_eq!(channel_mask(0), 0);
@@ -120,8 +199,113 @@ fn verify_channel_mask() {
     assert_eq!(channel_mask(2), 3);
     assert_eq!(channel_mask(3), 7);
     assert_eq!(channel_mask(4), 15);
+    assert_eq!(channel_mask(18), 0x3ffff);
+    assert_eq!(channel_mask(19), 0);
+    assert_eq!(channel_mask(64), 0);
+}
+
+/// Specifies how `WavWriter` treats samples at full scale.
+///
+/// A sample at full scale is a sample with the minimum or maximum value that
+/// can be represented with the number of bits per sample of the file, or for
+/// floating point samples, a sample with a magnitude of 1.0 or more. Such
+/// samples are usually the result of clipping somewhere earlier in the signal
+/// chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipDetection {
+    /// Do not inspect samples. This is the default.
+    Off,
+    /// Write samples at full scale, but count them.
+    Count,
+    /// Count samples at full scale, and return `Error::Clipped` instead of
+    /// writing them.
+    Error,
+}
+
+/// Specifies what `WavWriter` does when the data outgrows a RIFF file.
+///
+/// The sizes in a RIFF header are 32-bit fields, so the data chunk of a WAVE
+/// file cannot exceed 4 GiB. At 48 kHz, a stereo recording of 32-bit samples
+/// reaches this limit after about three hours. The RF64 format (EBU Tech 3306)
+/// lifts the limit with 64-bit sizes in a `ds64` chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeLimit {
+    /// Return `Error::TooLarge` instead of writing samples that do not fit.
+    /// This is the default.
+    Error,
+    /// Silently discard samples that do not fit. The file contains as many
+    /// whole frames as fit, and it is finalized normally.
+    Cap,
+    /// Keep writing, and finalize the file as RF64 if the data does not fit.
+    /// If it does fit, the file is a regular WAVE file, with a JUNK chunk of
+    /// 28 bytes that was reserved for the `ds64` chunk. This requires the
+    /// `rf64` feature. `WavReader` reads such files too.
+    Rf64,
+}
+
+/// Specifies the layout of the fmt chunk that `WavWriter` writes.
+///
+/// Specs that need more than a `WAVEFORMATEX` struct, because they have more
+/// than two channels, more than 16 bits per integer sample, or padding bits,
+/// are always written as `WAVEFORMATEXTENSIBLE`. For the other specs, tools
+/// disagree on the layout, and some legacy software accepts only the layout
+/// that it writes itself. Writing with the layout of such a tool, see
+/// `WavWriter::new_with_header_layout()`, makes the header byte-identical to
+/// the one that it writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderLayout {
+    /// Integer samples of 8 or 16 bits get a 16-byte `PCMWAVEFORMAT` struct,
+    /// float samples a `WAVEFORMATEXTENSIBLE` struct. This is the default.
+    Minimal,
+    /// Integer and float samples get an 18-byte `WAVEFORMATEX` struct with a
+    /// `cbSize` of 0, as written by tools based on the Windows multimedia
+    /// API (`mmioWrite`).
+    WaveFormatEx,
+    /// Integer samples of 8 or 16 bits get a 16-byte `PCMWAVEFORMAT` struct,
+    /// and float samples an 18-byte `WAVEFORMATEX` struct, as written by sox.
+    Sox,
+}
+
+/// A field of the header whose value is known only once the samples are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    /// The length field of the RIFF header.
+    RiffLen,
+    /// The JUNK chunk that becomes a `ds64` chunk if the file becomes RF64.
+    Ds64,
+    /// The sample length field of the fact chunk, the number of frames.
+    FactSampleLen,
+    /// The length field of the data chunk.
+    DataLen,
 }
 
+/// A field that is written as a placeholder, and patched on finalize or flush.
+#[derive(Clone, Copy, Debug)]
+struct Placeholder {
+    field: Field,
+    /// The offset of the field from the start of the file.
+    offset: u32,
+}
+
+/// Returns the placeholders of a header with the given layout, in file order.
+fn header_placeholders(ds64: bool, fact_len_offset: Option<u32>, data_len_offset: u32) -> Vec<Placeholder> {
+    let mut placeholders = vec![Placeholder { field: Field::RiffLen, offset: 4 }];
+    if ds64 {
+        placeholders.push(Placeholder { field: Field::Ds64, offset: 12 });
+    }
+    if let Some(offset) = fact_len_offset {
+        placeholders.push(Placeholder { field: Field::FactSampleLen, offset });
+    }
+    placeholders.push(Placeholder { field: Field::DataLen, offset: data_len_offset });
+    placeholders
+}
+
+/// The size of the contents of a `ds64` chunk without a table.
+const DS64_LEN: u32 = 28;
+
+/// The size of an `MD5 ` chunk, including its header.
+const MD5_CHUNK_LEN: u32 = 24;
+
 /// A writer that accepts samples and writes the WAVE format.
 ///
 /// The writer needs a `WavSpec` that describes the audio properties. Then
@@ -145,8 +329,24 @@ pub struct WavWriter<W>
 
     /// The number of bytes written to the data section.
     ///
-    /// This is an `u32` because WAVE cannot accomodate more data.
-    data_bytes_written: u32,
+    /// This is an `u64` because RF64 files can accomodate more data than
+    /// would fit in an `u32`.
+    data_bytes_written: u64,
+
+    /// What to do when the data outgrows a RIFF file.
+    size_limit: SizeLimit,
+
+    /// The maximum number of bytes that the data section can hold.
+    max_data_bytes: u64,
+
+    /// The number of bytes occupied by JUNK chunks written by `reserve_junk`.
+    reserved_junk: u32,
+
+    /// The number of bytes occupied by chunks written by `write_chunk`.
+    written_chunks: u32,
+
+    /// The chunks written by `write_chunk`, to write again in `roll_over_to`.
+    chunks: Vec<([u8; 4], Vec<u8>)>,
 
     /// Whether `finalize_internal` has been called.
     finalized: bool,
@@ -157,6 +357,37 @@ pub struct WavWriter<W>
 
     /// Write `WAVEFORMATEXTENSIBLE` format instead of `WAVEFORMATEX`
     extensible: bool,
+
+    /// The layout of the fmt chunk, for specs that do not need `WAVEFORMATEXTENSIBLE`.
+    header_layout: HeaderLayout,
+
+    /// Write the ambisonic B-Format subformat instead of plain PCM.
+    ambisonic: bool,
+
+    /// The channel mask set with `set_channel_mask()`, which overrides the
+    /// default assignment and forces `WAVEFORMATEXTENSIBLE`.
+    channel_mask: Option<ChannelMask>,
+
+    /// How samples at full scale are treated.
+    clip_detection: ClipDetection,
+
+    /// The number of samples at full scale encountered so far.
+    clip_count: u64,
+
+    /// The raw fmt chunk to write, when the writer was constructed with
+    /// `new_with_fmt_chunk`, as opposed to from a `WavSpec`.
+    raw_fmt_chunk: Option<Vec<u8>>,
+
+    /// The fields of the header that are patched when the file is finalized
+    /// or flushed, in file order.
+    placeholders: Vec<Placeholder>,
+
+    /// The byte order of the file, big-endian for RIFX.
+    endianness: Endianness,
+
+    /// The hash of the data written so far, if an `MD5 ` chunk is to be
+    /// written after the data chunk.
+    md5: Option<Md5>,
 }
 
 impl<W> WavWriter<W>
@@ -170,12 +401,94 @@ impl<W> WavWriter<W>
     /// does this automatically.
     ///
     /// This writes parts of the header immediately, hence a `Result` is
-    /// returned.
+    /// returned. `Error::Unsupported` is returned if the spec cannot be
+    /// represented in a WAVE header, for instance because the number of
+    /// channels is zero, because a frame would be larger than 64 KiB, or
+    /// because float samples do not have 32 bits.
+    ///
+    /// The header is a `WAVEFORMATEX` struct for 8 and 16-bit integer samples
+    /// with at most two channels. Other specs are written as
+    /// `WAVEFORMATEXTENSIBLE`, where the container size is rounded up to whole
+    /// bytes and the exact number of bits goes in `wValidBitsPerSample`. Float
+    /// files also get a fact chunk, which the format requires for anything
+    /// that is not integer PCM.
     pub fn new(writer: W, spec: WavSpec) -> Result<WavWriter<W>> {
+        WavWriter::new_internal(writer, spec_ex_for(spec), false, HeaderLayout::Minimal)
+    }
+
+    /// Creates a writer that stores samples in containers of a given size.
+    ///
+    /// This is like `new()`, but the number of bytes per sample need not be
+    /// the smallest number that holds `bits_per_sample` bits. For example, a
+    /// DAC that takes 20-bit data may expect it in 24-bit or 32-bit
+    /// containers. The header is a `WAVEFORMATEXTENSIBLE` struct in that case.
+    /// Samples are written with `write_sample()` as values of
+    /// `bits_per_sample` bits, and they are shifted into the most significant
+    /// bits of the container. `Error::Unsupported` is returned if the
+    /// container cannot hold the bits.
+    pub fn new_with_spec_ex(writer: W, spec_ex: WavSpecEx) -> Result<WavWriter<W>> {
+        WavWriter::new_internal(writer, spec_ex, false, HeaderLayout::Minimal)
+    }
+
+    /// Creates a writer that writes an ambisonic B-Format (.amb) file.
+    ///
+    /// This is like `new()`, but the header will be a `WAVEFORMATEXTENSIBLE`
+    /// struct with the ambisonic B-Format subformat, and no speaker positions.
+    /// Channels must be written in Furse-Malham order. The number of channels
+    /// must correspond to one of the layouts in `AmbisonicFormat`, otherwise
+    /// `Error::Unsupported` is returned.
+    pub fn new_ambisonic(writer: W, spec: WavSpec) -> Result<WavWriter<W>> {
+        if AmbisonicFormat::from_channels(spec.channels).is_none() {
+            return Err(Error::Unsupported);
+        }
+        WavWriter::new_internal(writer, spec_ex_for(spec), true, HeaderLayout::Minimal)
+    }
+
+    /// Creates a writer with the given layout of the fmt chunk.
+    ///
+    /// This is like `new()`, but specs that do not need a
+    /// `WAVEFORMATEXTENSIBLE` struct are written in the layout of `layout`,
+    /// so the header is byte-identical to that of files written by other
+    /// tools. See `HeaderLayout`.
+    pub fn new_with_header_layout(writer: W, spec: WavSpec, layout: HeaderLayout) -> Result<WavWriter<W>> {
+        WavWriter::new_internal(writer, spec_ex_for(spec), false, layout)
+    }
+
+    fn new_internal(writer: W,
+                    spec_ex: WavSpecEx,
+                    ambisonic: bool,
+                    header_layout: HeaderLayout)
+                    -> Result<WavWriter<W>> {
+        let spec = spec_ex.spec;
+        let bytes_per_sample = spec_ex.bytes_per_sample;
+        if bytes_per_sample == 0 || spec.bits_per_sample as u32 > bytes_per_sample as u32 * 8 {
+            return Err(Error::Unsupported);
+        }
+
+        // The size of the container is stored in bits, in a 16-bit field.
+        if bytes_per_sample as u32 * 8 > 0xffff {
+            return Err(Error::Unsupported);
+        }
+
+        // The nBlockAlign field (bytes per frame) is 16 bits, and the
+        // nAvgBytesPerSec field is 32 bits. Both must be able to hold their
+        // value.
+        let block_align = (bytes_per_sample as u32) * (spec.channels as u32);
+        if spec.channels == 0 || block_align > 0xffff ||
+           block_align.checked_mul(spec.sample_rate).is_none() {
+            return Err(Error::Unsupported);
+        }
+
+        // Only 32-bit IEEE float is supported.
+        let float = spec.sample_format == SampleFormat::Float;
+        if float && (spec.bits_per_sample != 32 || bytes_per_sample != 4) {
+            return Err(Error::Unsupported);
+        }
+
         let mut writer = WavWriter {
-            spec: spec,
-            bytes_per_sample: (spec.bits_per_sample as f32 / 8.0).ceil() as u16,
-            writer: writer,
+            spec,
+            bytes_per_sample,
+            writer,
             data_bytes_written: 0,
             sample_writer_buffer: Vec::new(),
             finalized: false,
@@ -183,35 +496,224 @@ impl<W> WavWriter<W>
             // Formats that support more than two channels or sample sizes of more
             // than 16 bits can be described in a WAVEFORMATEXTENSIBLE structure,
             // which includes the WAVEFORMAT structure.
-            extensible: spec.channels > 2 || spec.bits_per_sample > 16,
+            extensible: false,
+            header_layout,
+            ambisonic,
+            channel_mask: None,
+            clip_detection: ClipDetection::Off,
+            clip_count: 0,
+            raw_fmt_chunk: None,
+            placeholders: Vec::new(),
+            size_limit: SizeLimit::Error,
+            max_data_bytes: 0,
+            reserved_junk: 0,
+            written_chunks: 0,
+            chunks: Vec::new(),
+            endianness: Endianness::Little,
+            md5: None,
         };
+        writer.plan_header();
+        writer.max_data_bytes = writer.compute_max_data_bytes();
 
         // Write the header immediately. This way we don't have to check whether
         // to write the header when writing samples.
-        try!(writer.write_header());
+        writer.write_header()?;
+        trace_event!(DEBUG,
+                     spec = %spec,
+                     bytes_per_sample = bytes_per_sample,
+                     extensible = writer.extensible,
+                     "wrote header");
+
+        Ok(writer)
+    }
+
+    /// Creates a writer that writes the given fmt chunk verbatim.
+    ///
+    /// This can be used to write data that has been encoded elsewhere, in a
+    /// format that Hound does not understand itself, such as ADPCM. The fmt
+    /// chunk must start with a `WAVEFORMAT` struct, that is, it must be at
+    /// least 16 bytes long. The data chunk can be written with
+    /// `write_raw_data()`. Hound does not verify that the data matches the
+    /// format. Such a writer does not encode samples itself, `write_sample()`
+    /// returns `Error::Unsupported`.
+    ///
+    /// Note that the WAVE format requires non-PCM files to contain a fact
+    /// chunk, which Hound does not write.
+    pub fn new_with_fmt_chunk(writer: W, fmt_chunk: &[u8]) -> Result<WavWriter<W>> {
+        if fmt_chunk.len() < 16 || fmt_chunk.len() > 18 + 0xffff {
+            return Err(Error::FormatError("invalid fmt chunk size"));
+        }
+
+        // Extract the fields of the WAVEFORMAT struct that the writer needs
+        // to know about. The fields nAvgBytesPerSec and nBlockAlign are not
+        // needed.
+        let mut cursor = io::Cursor::new(fmt_chunk);
+        let format_tag = cursor.read_le_u16()?;
+        let channels = cursor.read_le_u16()?;
+        let sample_rate = cursor.read_le_u32()?;
+        cursor.skip_bytes(6)?;
+        let bits_per_sample = cursor.read_le_u16()?;
+
+        if channels == 0 {
+            return Err(Error::FormatError("file contains zero channels"));
+        }
+
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample,
+            // WAVE_FORMAT_IEEE_FLOAT is 3, anything else is not float, but not
+            // necessarily integer either.
+            sample_format: if format_tag == 3 { SampleFormat::Float } else { SampleFormat::Int },
+        };
+
+        let mut writer = WavWriter {
+            spec,
+            bytes_per_sample: ((bits_per_sample as u32 + 7) / 8) as u16,
+            writer,
+            data_bytes_written: 0,
+            sample_writer_buffer: Vec::new(),
+            finalized: false,
+            extensible: format_tag == 0xfffe,
+            header_layout: HeaderLayout::Minimal,
+            ambisonic: false,
+            channel_mask: None,
+            clip_detection: ClipDetection::Off,
+            clip_count: 0,
+            raw_fmt_chunk: Some(fmt_chunk.to_vec()),
+            // The RIFF header, fmt chunk header, fmt chunk, padding byte, and
+            // data chunk id precede the data chunk length.
+            placeholders: header_placeholders(false, None, (12 + 8 + fmt_chunk.len() + fmt_chunk.len() % 2 + 4) as u32),
+            size_limit: SizeLimit::Error,
+            max_data_bytes: 0,
+            reserved_junk: 0,
+            written_chunks: 0,
+            chunks: Vec::new(),
+            endianness: Endianness::Little,
+            md5: None,
+        };
+        writer.max_data_bytes = writer.compute_max_data_bytes();
+
+        writer.write_header()?;
 
         Ok(writer)
     }
 
+    /// Returns the length of the fmt chunk for a spec that does not need
+    /// `WAVEFORMATEXTENSIBLE`, or `None` if it does.
+    fn waveformatex_len(&self) -> Option<u32> {
+        // WAVEFORMATEX can only describe integer samples of 8 or 16 bits, it
+        // has no way to distinguish the container size from the number of
+        // valid bits.
+        let spec = self.spec;
+        if self.ambisonic || self.channel_mask.is_some() || spec.channels > 2 ||
+           spec.bits_per_sample as u32 != self.bytes_per_sample as u32 * 8 {
+            return None;
+        }
+        match (spec.sample_format, self.header_layout) {
+            (SampleFormat::Int, _) if spec.bits_per_sample != 8 && spec.bits_per_sample != 16 => None,
+            (SampleFormat::Float, HeaderLayout::Minimal) => None,
+            (SampleFormat::Int, HeaderLayout::Minimal) => Some(16),
+            (SampleFormat::Int, HeaderLayout::Sox) => Some(16),
+            (_, _) => Some(18),
+        }
+    }
+
+    /// Determines the struct of the fmt chunk, and the offsets of the fields
+    /// that are updated when the file is finalized.
+    fn plan_header(&mut self) {
+        let fmt_len = self.waveformatex_len();
+        self.extensible = fmt_len.is_none();
+
+        // The fmt chunk follows the RIFF header, and the reserved ds64 chunk
+        // if there is one. A fact chunk adds 12 bytes before the data chunk,
+        // whose length is the last field.
+        let ds64 = self.placeholder(Field::Ds64).is_some();
+        let ds64_len = if ds64 { 8 + DS64_LEN } else { 0 };
+        let fmt_end = 12 + ds64_len + 8 + fmt_len.unwrap_or(40);
+        let float = self.spec.sample_format == SampleFormat::Float;
+        let fact_len_offset = if float { Some(fmt_end + 8) } else { None };
+        let data_len_offset = fmt_end + if float { 12 } else { 0 } + 4;
+        self.placeholders = header_placeholders(ds64, fact_len_offset, data_len_offset);
+    }
+
+    /// Returns the offset of the placeholder for the field, if the header has one.
+    fn placeholder(&self, field: Field) -> Option<u32> {
+        self.placeholders.iter().find(|p| p.field == field).map(|p| p.offset)
+    }
+
+    /// Returns the offset of the length field of the data chunk.
+    ///
+    /// This is the last field of the header, the data follows it.
+    fn data_len_offset(&self) -> u32 {
+        self.placeholder(Field::DataLen).expect("the header contains a data chunk")
+    }
+
+    /// Moves the placeholders at or after `offset` by `len` bytes, after
+    /// `len` bytes were inserted into the header at `offset`.
+    fn shift_placeholders(&mut self, offset: u32, len: u32) {
+        for placeholder in &mut self.placeholders {
+            if placeholder.offset >= offset {
+                placeholder.offset += len;
+            }
+        }
+    }
+
     /// Writes the RIFF WAVE header
     fn write_header(&mut self) -> io::Result<()> {
-        if self.extensible {
+        if self.raw_fmt_chunk.is_some() {
+            self.write_raw_fmt()
+        } else if self.extensible {
             self.write_waveformatextensible()
         } else {
             self.write_waveformatex()
         }
     }
 
+    /// Writes the header with the fmt chunk provided by the user.
+    fn write_raw_fmt(&mut self) -> io::Result<()> {
+        let fmt_chunk = self.raw_fmt_chunk.take().expect("write_raw_fmt requires a raw fmt chunk");
+
+        // Chunks are aligned to 2 bytes, so an odd-sized chunk is followed
+        // by a padding byte.
+        let padding = fmt_chunk.len() % 2;
+        let mut header = vec![0u8; self.data_len_offset() as usize + 4];
+
+        {
+            let mut buffer: io::Cursor<&mut [u8]> = io::Cursor::new(&mut header);
+            self.write_initial_block(&mut buffer)?;
+            buffer.write_le_u32(fmt_chunk.len() as u32)?;
+            buffer.write_all(&fmt_chunk)?;
+            buffer.write_all(&[0u8; 1][..padding])?;
+            self.write_data_part(&mut buffer)?;
+        }
+
+        self.header_to_file_order(&mut header);
+        self.writer.write_all(&header)?;
+        self.raw_fmt_chunk = Some(fmt_chunk);
+
+        Ok(())
+    }
+
     /// Writes magic bytes and size of file with wav struct prefix
     fn write_initial_block(&mut self, buffer: &mut io::Cursor<&mut [u8]>)
                            -> io::Result<()> {
-        try!(buffer.write_all("RIFF".as_bytes()));
+        buffer.write_all("RIFF".as_bytes())?;
 
         // Skip 4 bytes that will be filled with the file size afterwards.
-        try!(buffer.write_le_u32(0));
+        buffer.write_le_u32(0)?;
+
+        buffer.write_all("WAVE".as_bytes())?;
+
+        // Space for a ds64 chunk, should the file become RF64. It must be the
+        // first chunk, so it cannot be inserted later.
+        if self.placeholder(Field::Ds64).is_some() {
+            buffer.write_all("JUNK".as_bytes())?;
+            buffer.write_le_u32(DS64_LEN)?;
+            buffer.write_all(&[0u8; DS64_LEN as usize])?;
+        }
 
-        try!(buffer.write_all("WAVE".as_bytes()));
-        try!(buffer.write_all("fmt ".as_bytes()));
+        buffer.write_all("fmt ".as_bytes())?;
 
         Ok(())
     }
@@ -221,19 +723,34 @@ impl<W> WavWriter<W>
                         -> io::Result<()> {
         let spec = &self.spec;
         // The field nChannels.
-        try!(buffer.write_le_u16(spec.channels));
+        buffer.write_le_u16(spec.channels)?;
 
         // The field nSamplesPerSec.
-        try!(buffer.write_le_u32(spec.sample_rate));
-        let bytes_per_sec = spec.sample_rate
-                          * self.bytes_per_sample as u32
-                          * spec.channels as u32;
+        buffer.write_le_u32(spec.sample_rate)?;
+
+        // These were verified not to overflow when the writer was constructed.
+        let block_align = self.bytes_per_sample as u32 * spec.channels as u32;
+        let bytes_per_sec = spec.sample_rate * block_align;
 
         // The field nAvgBytesPerSec;
-        try!(buffer.write_le_u32(bytes_per_sec));
+        buffer.write_le_u32(bytes_per_sec)?;
 
         // The field nBlockAlign. Block align * sample rate = bytes per sec.
-        try!(buffer.write_le_u16((bytes_per_sec / spec.sample_rate) as u16));
+        buffer.write_le_u16(block_align as u16)?;
+
+        Ok(())
+    }
+
+    /// Writes the fact chunk, if the header has one.
+    fn write_fact_part(&mut self, buffer: &mut io::Cursor<&mut [u8]>)
+                       -> io::Result<()> {
+        if self.placeholder(Field::FactSampleLen).is_some() {
+            // The field dwSampleLength, the number of samples per channel. It
+            // is not known at this point, the 0 will be overwritten later.
+            buffer.write_all("fact".as_bytes())?;
+            buffer.write_le_u32(4)?;
+            buffer.write_le_u32(0)?;
+        }
 
         Ok(())
     }
@@ -244,8 +761,8 @@ impl<W> WavWriter<W>
         // We will only write the header here, actual data are the samples.
         // The number of bytes that this will take is not known at this point.
         // The 0 will be overwritten later.
-        try!(buffer.write_all("data".as_bytes()));
-        try!(buffer.write_le_u32(0));
+        buffer.write_all("data".as_bytes())?;
+        buffer.write_le_u32(0)?;
 
         Ok(())
     }
@@ -255,15 +772,17 @@ impl<W> WavWriter<W>
         // Useful links:
         // https://msdn.microsoft.com/en-us/library/ms713497.aspx
         // http://soundfile.sapp.org/doc/WaveFormat/
-        let mut header = [0u8; 44];
+        let mut header = [0u8; 46 + 12 + 8 + DS64_LEN as usize];
+        let header_len = self.data_len_offset() as usize + 4;
+        let fmt_len = self.waveformatex_len().expect("the spec fits in WAVEFORMATEX");
 
         // Write the header in-memory first.
         {
             let mut buffer: io::Cursor<&mut [u8]> = io::Cursor::new(&mut header);
 
-            try!(self.write_initial_block(&mut buffer));
+            self.write_initial_block(&mut buffer)?;
 
-            try!(buffer.write_le_u32(16)); // Size of the WAVE header chunk.
+            buffer.write_le_u32(fmt_len)?; // Size of the WAVE header chunk.
 
             // The following is based on the WAVEFORMATEX struct as
             // documented on MSDN.
@@ -272,30 +791,32 @@ impl<W> WavWriter<W>
             match self.spec.sample_format {
                 // WAVE_FORMAT_PCM
                 SampleFormat::Int => {
-                    try!(buffer.write_le_u16(1));
+                    buffer.write_le_u16(1)?;
                 },
-                // WAVE_FORMAT_IEEE_FLOAT
+                // WAVE_FORMAT_IEEE_FLOAT, the bits per sample were verified
+                // to be 32 when the writer was constructed.
                 SampleFormat::Float => {
-                    if self.spec.bits_per_sample == 32 {
-                        try!(buffer.write_le_u16(3));
-                    } else {
-                        panic!("Invalid number of bits per sample. \
-                               When writing SampleFormat::Float, \
-                               bits_per_sample must be 32.");
-                    }
+                    buffer.write_le_u16(3)?;
                 },
             };
 
-            try!(self.write_spec_block(&mut buffer));
+            self.write_spec_block(&mut buffer)?;
 
             // The field wBitsPerSample, the real number of bits per sample.
-            try!(buffer.write_le_u16(self.spec.bits_per_sample));
+            buffer.write_le_u16(self.spec.bits_per_sample)?;
 
-            try!(self.write_data_part(&mut buffer));
+            // The field cbSize, which is absent in PCMWAVEFORMAT.
+            if fmt_len == 18 {
+                buffer.write_le_u16(0)?;
+            }
+
+            self.write_fact_part(&mut buffer)?;
+            self.write_data_part(&mut buffer)?;
         }
 
         // Then write the entire header at once.
-        try!(self.writer.write_all(&header));
+        self.header_to_file_order(&mut header[..header_len]);
+        self.writer.write_all(&header[..header_len])?;
 
         Ok(())
     }
@@ -306,194 +827,1002 @@ impl<W> WavWriter<W>
         // https://msdn.microsoft.com/en-us/library/ms713496.aspx
         // https://msdn.microsoft.com/en-us/library/ms713462.aspx
 
-        let mut header = [0u8; 68];
+        let mut header = [0u8; 68 + 12 + 8 + DS64_LEN as usize];
+        let header_len = self.data_len_offset() as usize + 4;
 
         // Write the header in-memory first.
         {
             let mut buffer: io::Cursor<&mut [u8]> = io::Cursor::new(&mut header);
-            try!(self.write_initial_block(&mut buffer));
+            self.write_initial_block(&mut buffer)?;
 
-            try!(buffer.write_le_u32(40)); // Size of the WAVE header chunk.
+            buffer.write_le_u32(40)?; // Size of the WAVE header chunk.
 
             // The following is based on the WAVEFORMATEXTENSIBLE struct as
             // documented on MSDN.
 
             // The field wFormatTag, value 1 means WAVE_FORMAT_PCM, but we use
             // the slightly more sophisticated WAVE_FORMAT_EXTENSIBLE.
-            try!(buffer.write_le_u16(0xfffe));
+            buffer.write_le_u16(0xfffe)?;
 
-            try!(self.write_spec_block(&mut buffer));
+            self.write_spec_block(&mut buffer)?;
 
             // The field wBitsPerSample. This is actually the size of the
             // container, so this is a multiple of 8.
-            try!(buffer.write_le_u16(self.bytes_per_sample as u16 * 8));
+            buffer.write_le_u16(self.bytes_per_sample * 8)?;
             // The field cbSize, the number of remaining bytes in the struct.
-            try!(buffer.write_le_u16(22));
+            buffer.write_le_u16(22)?;
             // The field wValidBitsPerSample, the real number of bits per sample.
-            try!(buffer.write_le_u16(self.spec.bits_per_sample));
+            buffer.write_le_u16(self.spec.bits_per_sample)?;
             // The field dwChannelMask.
-            // TODO: add the option to specify the channel mask. For now, use
-            // the default assignment.
-            try!(buffer.write_le_u32(channel_mask(self.spec.channels)));
+            buffer.write_le_u32(self.extensible_channel_mask().0)?;
 
             // The field SubFormat.
-            let subformat_guid = match self.spec.sample_format {
+            let subformat_guid = match (self.spec.sample_format, self.ambisonic) {
                 // PCM audio with integer samples.
-                SampleFormat::Int => super::KSDATAFORMAT_SUBTYPE_PCM,
+                (SampleFormat::Int, false) => chunks::KSDATAFORMAT_SUBTYPE_PCM,
+                (SampleFormat::Int, true) => chunks::SUBTYPE_AMBISONIC_B_FORMAT_PCM,
                 // PCM audio with 32-bit IEEE float samples.
-                SampleFormat::Float => {
-                    if self.spec.bits_per_sample == 32 {
-                        super::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT
-                    } else {
-                        panic!("Invalid number of bits per sample. \
-                               When writing SampleFormat::Float, \
-                               bits_per_sample must be 32.");
-                    }
-                }
+                (SampleFormat::Float, false) => chunks::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+                (SampleFormat::Float, true) => chunks::SUBTYPE_AMBISONIC_B_FORMAT_IEEE_FLOAT,
             };
-            try!(buffer.write_all(&subformat_guid));
+            buffer.write_all(&subformat_guid)?;
 
-            try!(self.write_data_part(&mut buffer));
+            self.write_fact_part(&mut buffer)?;
+            self.write_data_part(&mut buffer)?;
         }
 
         // Then write the entire header at once.
-        try!(self.writer.write_all(&header));
+        self.header_to_file_order(&mut header[..header_len]);
+        self.writer.write_all(&header[..header_len])?;
 
         Ok(())
     }
 
-    /// Writes a single sample for one channel.
+    /// Returns the channel mask for the `WAVEFORMATEXTENSIBLE` struct.
     ///
-    /// WAVE interleaves channel data, so the channel that this writes the
-    /// sample to depends on previous writes. This will return an error if the
-    /// sample does not fit in the number of bits specified in the `WavSpec`.
-    #[inline]
-    pub fn write_sample<S: Sample>(&mut self, sample: S) -> Result<()> {
-        try!(sample.write(&mut self.writer, self.spec.bits_per_sample));
-        self.data_bytes_written += self.bytes_per_sample as u32;
-        Ok(())
+    /// Unless a mask was set, the channels are assigned to speakers in order.
+    /// Ambisonic channels are not associated with speakers, so the mask is 0
+    /// for B-Format.
+    fn extensible_channel_mask(&self) -> ChannelMask {
+        match self.channel_mask {
+            Some(mask) => mask,
+            None if self.ambisonic => ChannelMask(0),
+            None => ChannelMask(channel_mask(self.spec.channels)),
+        }
     }
 
-    /// Create an efficient writer that writes 16-bit integer samples only.
-    ///
-    /// When it is known what the kind of samples will be, many dynamic checks
-    /// can be omitted. Furthermore, this writer employs buffering internally,
-    /// which allows omitting return value checks except on flush. The internal
-    /// buffer will be sized such that exactly `num_samples` samples can be
-    /// written to it, and the buffer is recycled across calls to
-    /// `get_i16_writer()` if the previous buffer was sufficiently large.
+    /// Converts a header that was written in little-endian order to the byte order of the file.
     ///
-    /// # Panics
+    /// For RIFX, this changes the magic and reverses the size fields of all
+    /// chunks, and the fields of the fmt and fact chunks.
+    fn header_to_file_order(&self, header: &mut [u8]) {
+        if self.endianness == Endianness::Little {
+            return;
+        }
+        header[..4].copy_from_slice(b"RIFX");
+        header[4..8].reverse();
+        let mut offset = 12;
+        while offset + 8 <= header.len() {
+            let mut id = [0u8; 4];
+            id.copy_from_slice(&header[offset..offset + 4]);
+            let len = (&header[offset + 4..offset + 8]).read_le_u32()
+                                                        .expect("reading from a slice does not fail");
+            header[offset + 4..offset + 8].reverse();
+            let start = offset + 8;
+            let end = cmp::min(start + len as usize, header.len());
+            match &id {
+                b"fmt " => swap_fmt_fields(&mut header[start..end]),
+                b"fact" => header[start..end].reverse(),
+                _ => {}
+            }
+            offset = start + len as usize + len as usize % 2;
+        }
+    }
+
+    /// Converts a 32-bit field to the byte order of the file, for writing with `write_le_u32()`.
+    fn u32_to_file_order(&self, x: u32) -> u32 {
+        codec::swap_u32(x, self.endianness)
+    }
+
+    /// Sets the byte order of the file.
     ///
-    /// Panics if the spec does not match a 16 bits per sample integer format.
+    /// The default is `Endianness::Little`, which produces a regular WAVE
+    /// file. With `Endianness::Big`, the file is written as RIFX instead,
+    /// where all header fields and samples are big-endian, as some legacy
+    /// tools expect. For a writer constructed with `new_with_fmt_chunk()`,
+    /// the fields of the fmt chunk are converted; raw data written with
+    /// `write_raw_data()` is written verbatim.
     ///
-    /// Attempting to write more than `num_samples` samples to the writer will
-    /// panic too.
-    pub fn get_i16_writer<'s>(&'s mut self,
-                              num_samples: u32)
-                              -> SampleWriter16<'s, W> {
-        if self.spec.sample_format != SampleFormat::Int {
-            panic!("When calling get_i16_writer, the sample format must be int.");
+    /// This rewrites the header, so it must be done before any samples are
+    /// written, and before `reserve_junk()` or `write_chunk()` is called;
+    /// otherwise this function will panic. RIFX has no 64-bit variant, so
+    /// `Error::Unsupported` is returned for big-endian files with
+    /// `SizeLimit::Rf64`, and for samples stored in more than 4 bytes.
+    pub fn set_endianness(&mut self, endianness: Endianness) -> Result<()> {
+        if endianness == self.endianness {
+            return Ok(());
         }
-        if self.spec.bits_per_sample != 16 {
-            panic!("When calling get_i16_writer, the number of bits per sample must be 16.");
+        assert!(self.data_bytes_written == 0 && self.reserved_junk == 0 && self.written_chunks == 0,
+                "The endianness must be set before writing samples, reserving junk, or writing chunks.");
+        if endianness == Endianness::Big &&
+           (self.size_limit == SizeLimit::Rf64 || self.bytes_per_sample > 4) {
+            return Err(Error::Unsupported);
         }
+        self.endianness = endianness;
+        self.writer.seek(io::SeekFrom::Start(0))?;
+        self.write_header()?;
+        Ok(())
+    }
 
-        let num_bytes = num_samples as usize * 2;
-
-        if self.sample_writer_buffer.len() < num_bytes {
-            // We need a bigger buffer. There is no point in growing the old
-            // one, as we are going to overwrite the samples anyway, so just
-            // allocate a new one.
-            let mut new_buffer = Vec::with_capacity(num_bytes);
+    /// Returns the byte order of the file.
+    pub fn endianness(&self) -> Endianness {
+        self.endianness
+    }
 
-            // The potentially garbage memory here will not be exposed: the
-            // buffer is only exposed when flushing, but `flush()` asserts that
-            // all samples have been written.
-            unsafe { new_buffer.set_len(num_bytes); }
+    /// Returns the layout of the fmt chunk, see `new_with_header_layout()`.
+    pub fn header_layout(&self) -> HeaderLayout {
+        self.header_layout
+    }
 
-            self.sample_writer_buffer = new_buffer;
+    /// Sets the channel mask to write, instead of letting Hound choose one.
+    ///
+    /// By default, files with at most two channels of 8 or 16 bits get a
+    /// `WAVEFORMATEX` header, which has no channel mask, and other files get
+    /// a mask that assigns the channels to the first speaker positions in
+    /// order, or 0 when there are more than 18 channels. Setting a mask
+    /// forces a `WAVEFORMATEXTENSIBLE` header with exactly that mask. Some
+    /// consumers require `ChannelMask(0)` for a layout that is unspecified.
+    ///
+    /// This rewrites the header, so it must be done before any samples are
+    /// written, and before `reserve_junk()` or `write_chunk()` is called;
+    /// otherwise this function will panic. `Error::Unsupported` is returned if the mask
+    /// assigns more speakers than there are channels, if the mask is not 0
+    /// for an ambisonic writer, and for a writer constructed with
+    /// `new_with_fmt_chunk()`.
+    pub fn set_channel_mask(&mut self, mask: ChannelMask) -> Result<()> {
+        assert!(self.data_bytes_written == 0 && self.reserved_junk == 0 && self.written_chunks == 0,
+                "The channel mask must be set before writing samples, reserving junk, or writing chunks.");
+        if self.raw_fmt_chunk.is_some() ||
+           mask.num_speakers() > self.spec.channels as u32 ||
+           (self.ambisonic && mask.0 != 0) {
+            return Err(Error::Unsupported);
         }
+        self.channel_mask = Some(mask);
+        self.plan_header();
+        self.max_data_bytes = self.compute_max_data_bytes();
+        self.writer.seek(io::SeekFrom::Start(0))?;
+        self.write_header()?;
+        Ok(())
+    }
 
-        SampleWriter16 {
-            writer: &mut self.writer,
-            buffer: &mut self.sample_writer_buffer[..num_bytes],
-            data_bytes_written: &mut self.data_bytes_written,
-            index: 0,
+    /// Returns the channel mask in the header, or `None` if the header has none.
+    ///
+    /// Only a `WAVEFORMATEXTENSIBLE` header has a channel mask, see
+    /// `set_channel_mask()`.
+    pub fn channel_mask(&self) -> Option<ChannelMask> {
+        match self.raw_fmt_chunk {
+            Some(ref fmt) if self.extensible && fmt.len() >= 24 => {
+                Some(ChannelMask(u32::from_le_bytes([fmt[20], fmt[21], fmt[22], fmt[23]])))
+            }
+            Some(_) => None,
+            None if self.extensible => Some(self.extensible_channel_mask()),
+            None => None,
         }
     }
 
-    /// Performs finalization. After calling this, the writer should be destructed.
-    fn finalize_internal(&mut self) -> Result<()> {
-        self.finalized = true;
+    /// Reserves space for metadata by writing a JUNK chunk before the data chunk.
+    ///
+    /// The JUNK chunk has `len` bytes of content, so it occupies `len + 8`
+    /// bytes (plus a padding byte if `len` is odd). Metadata chunks such as
+    /// `bext` can later be written into this space by a `WavEditor`, without
+    /// moving the audio data. This is common practice for broadcast wave files.
+    ///
+    /// This must be called before any samples are written, otherwise this
+    /// function will panic.
+    pub fn reserve_junk(&mut self, len: u32) -> Result<()> {
+        assert!(self.data_bytes_written == 0,
+                "reserve_junk must be called before writing samples.");
+        let size = self.insert_chunk(b"JUNK", &vec![0u8; len as usize])?;
+        self.reserved_junk += size;
+        Ok(())
+    }
 
-        // Flush remaining samples via the BufWriter.
-        try!(self.writer.flush());
+    /// Writes a chunk before the data chunk, such as a metadata chunk.
+    ///
+    /// Chunks are written in the order of the calls, after the fmt chunk and
+    /// before the data chunk. The contents are written verbatim, also for a
+    /// big-endian writer. Metadata chunks such as `bext` and `LIST` can be
+    /// encoded with `BroadcastExtension::encode()` and
+    /// `InfoEntry::encode_list()`, or written all at once from a
+    /// `MetadataTemplate`. For a layout that this writer cannot produce,
+    /// such as metadata chunks after the data chunk, a file can
+    /// be written chunk by chunk with a `RiffWriter` instead.
+    ///
+    /// This must be called before any samples are written, otherwise this
+    /// function will panic. `Error::Unsupported` is returned for the ids of
+    /// the fmt and data chunks, and `Error::TooLarge` if the header would not
+    /// leave room for data in a RIFF file.
+    pub fn write_chunk(&mut self, id: &[u8; 4], data: &[u8]) -> Result<()> {
+        assert!(self.data_bytes_written == 0,
+                "write_chunk must be called before writing samples.");
+        if id == b"fmt " || id == b"data" {
+            return Err(Error::Unsupported);
+        }
+        let size = self.insert_chunk(id, data)?;
+        self.written_chunks += size;
+        self.chunks.push((*id, data.to_vec()));
+        Ok(())
+    }
+
+    /// Inserts a chunk before the data chunk, returns the number of bytes it occupies.
+    fn insert_chunk(&mut self, id: &[u8; 4], data: &[u8]) -> Result<u32> {
+        let padding = data.len() % 2;
+        let size = 8 + data.len() as u64 + padding as u64;
+        // The header must leave room for the MD5 chunk in the RIFF form.
+        let data_len_offset = self.data_len_offset();
+        if data_len_offset as u64 + size > 0xffff_ffff - (MD5_CHUNK_LEN as u64 + 1) {
+            return Err(Error::TooLarge);
+        }
 
-        // Extract the underlying writer and rewind it to the start, to update
-        // the header fields of which we now know the value.
+        let mut chunk = vec![0u8; size as usize + 8];
+        {
+            let mut buffer: io::Cursor<&mut [u8]> = io::Cursor::new(&mut chunk);
+            buffer.write_all(id)?;
+            buffer.write_le_u32(self.u32_to_file_order(data.len() as u32))?;
+            buffer.write_all(data)?;
+            buffer.set_position(size);
+            self.write_data_part(&mut buffer)?;
+        }
 
-        // The header minus magic and 32-bit filesize.
-        let header_size = if self.extensible { 64 } else { 40 };
+        // Overwrite the data chunk header, and write a new one after the chunk.
+        self.writer.seek(io::SeekFrom::Start(data_len_offset as u64 - 4))?;
+        self.writer.write_all(&chunk)?;
+        self.shift_placeholders(data_len_offset - 4, size as u32);
+        self.max_data_bytes = self.compute_max_data_bytes();
 
-        let file_size = self.data_bytes_written + (header_size - 4);
-        try!(self.writer.seek(io::SeekFrom::Start(4)));
-        try!(self.writer.write_le_u32(file_size));
-        try!(self.writer.seek(io::SeekFrom::Start(header_size as u64)));
-        try!(self.writer.write_le_u32(self.data_bytes_written));
+        Ok(size as u32)
+    }
 
-        // Signal error if the last sample was not finished, but do so after
-        // everything has been written, so that no data is lost, even though
-        // the file is now ill-formed.
-        if (self.data_bytes_written / self.bytes_per_sample as u32)
-            % self.spec.channels as u32 != 0 {
-            return Err(Error::UnfinishedSample);
+    /// Sets whether an `MD5 ` chunk with the hash of the audio data is written.
+    ///
+    /// The `MD5 ` chunk, as used by broadcast wave tools, holds the MD5 hash
+    /// of the contents of the data chunk, as stored in the file. The hash is
+    /// computed while samples are written, and the chunk is written after
+    /// the data chunk on finalization. Use `WavReader::verify_md5()` to check
+    /// it. The chunk is off by default.
+    ///
+    /// This must be done before any samples are written, otherwise this
+    /// function will panic. `Error::Unsupported` is returned for samples
+    /// stored in more than 4 bytes.
+    pub fn set_md5_chunk(&mut self, enabled: bool) -> Result<()> {
+        assert!(self.data_bytes_written == 0,
+                "The MD5 chunk must be enabled before writing samples.");
+        if enabled && self.bytes_per_sample > 4 {
+            return Err(Error::Unsupported);
         }
-
+        self.md5 = if enabled { Some(Md5::new()) } else { None };
+        self.max_data_bytes = self.compute_max_data_bytes();
         Ok(())
     }
 
-    /// Writes the parts of the WAVE format that require knowing all samples.
+    /// Returns whether an `MD5 ` chunk will be written, see `set_md5_chunk()`.
+    pub fn md5_chunk(&self) -> bool {
+        self.md5.is_some()
+    }
+
+    /// Sets what happens when the data outgrows a RIFF file.
     ///
-    /// This method must be called after all samples have been written. If it
-    /// is not called, the destructor will finalize the file, but any errors
-    /// that occur in the process cannot be observed in that manner.
-    pub fn finalize(mut self) -> Result<()> {
-        self.finalize_internal()
+    /// The default is `SizeLimit::Error`. Selecting `SizeLimit::Rf64` rewrites
+    /// the header to make room for a `ds64` chunk, so it must be done before
+    /// any samples are written, and before `reserve_junk()` or `write_chunk()`
+    /// is called; otherwise this function will panic. RIFX files cannot become RF64, so
+    /// `SizeLimit::Rf64` returns `Error::Unsupported` for a big-endian writer,
+    /// and for any writer when the `rf64` feature is disabled.
+    pub fn set_size_limit(&mut self, limit: SizeLimit) -> Result<()> {
+        if limit == SizeLimit::Rf64 && (cfg!(not(feature = "rf64")) || self.endianness == Endianness::Big) {
+            return Err(Error::Unsupported);
+        }
+        if limit == SizeLimit::Rf64 && self.placeholder(Field::Ds64).is_none() {
+            assert!(self.data_bytes_written == 0 && self.reserved_junk == 0 && self.written_chunks == 0,
+                    "SizeLimit::Rf64 must be set before writing samples, reserving junk, or writing chunks.");
+            // The ds64 chunk must be the first chunk, it goes after the RIFF header.
+            self.shift_placeholders(12, 8 + DS64_LEN);
+            self.placeholders.insert(1, Placeholder { field: Field::Ds64, offset: 12 });
+            self.writer.seek(io::SeekFrom::Start(0))?;
+            self.write_header()?;
+        }
+        self.size_limit = limit;
+        self.max_data_bytes = self.compute_max_data_bytes();
+        Ok(())
     }
-}
 
-impl<W> Drop for WavWriter<W>
-    where W: io::Write + io::Seek
-{
-    fn drop(&mut self) {
-        // `finalize_internal` must be called only once. If that is done via
-        // `finalize`, then this method is a no-op. If the user did not
-        // finalize explicitly, then we should do it now. This can fail, but
-        // drop should not panic, so a failure is ignored silently here.
-        if !self.finalized {
-            let _r = self.finalize_internal();
+    /// Returns the maximum size of the data chunk under the current size limit.
+    fn compute_max_data_bytes(&self) -> u64 {
+        if self.size_limit == SizeLimit::Rf64 {
+            return u64::MAX;
         }
+        // The RIFF chunk contains everything after its 8-byte header, and its
+        // size must fit in 32 bits. Only whole frames are kept. Room is left
+        // for the MD5 chunk and the padding byte before it.
+        let trailer_len = if self.md5.is_some() { MD5_CHUNK_LEN as u64 + 1 } else { 0 };
+        let max_bytes = 0xffff_ffff - (self.data_len_offset() as u64 - 4) - trailer_len;
+        let block_align = cmp::max(1, self.bytes_per_sample as u64 * self.spec.channels as u64);
+        max_bytes - max_bytes % block_align
     }
-}
 
-impl WavWriter<io::BufWriter<fs::File>> {
-    /// Creates a writer that writes the WAVE format to a file.
+    /// Returns whether `num_bytes` more bytes can be written, or an error if
+    /// they do not fit and the size limit is `SizeLimit::Error`.
+    #[inline]
+    fn check_size(&self, num_bytes: u64) -> Result<bool> {
+        if self.data_bytes_written + num_bytes <= self.max_data_bytes {
+            Ok(true)
+        } else if self.size_limit == SizeLimit::Cap {
+            trace_event!(TRACE, num_bytes = num_bytes, "discarding data beyond size limit");
+            Ok(false)
+        } else {
+            trace_event!(DEBUG, num_bytes = num_bytes, "data exceeds size limit");
+            Err(Error::TooLarge)
+        }
+    }
+
+    /// Writes a single sample for one channel.
     ///
-    /// This is a convenience constructor that creates the file, wraps it in a
-    /// `BufWriter`, and then constructs a `WavWriter` from it. The file will
-    /// be overwritten if it exists.
-    pub fn create<P: AsRef<path::Path>>(filename: P,
-                                        spec: WavSpec)
-                                        -> Result<WavWriter<io::BufWriter<fs::File>>> {
-        let file = try!(fs::File::create(filename));
-        let buf_writer = io::BufWriter::new(file);
-        WavWriter::new(buf_writer, spec)
+    /// WAVE interleaves channel data, so the channel that this writes the
+    /// sample to depends on previous writes. This will return an error if the
+    /// sample does not fit in the number of bits specified in the `WavSpec`.
+    ///
+    /// If clip detection is enabled, samples at full scale are counted, and
+    /// with `ClipDetection::Error`, `Error::Clipped` is returned instead of
+    /// writing the sample. If the sample would not fit in the file, the size
+    /// limit determines what happens, see `set_size_limit()`.
+    #[inline]
+    pub fn write_sample<S: Sample>(&mut self, sample: S) -> Result<()> {
+        self.write_sample_impl(sample, true)
     }
-}
 
-/// A writer that specifically only writes integer samples of 16 bits per sample.
-///
+    /// Writes a single sample, checking that it fits only in debug builds.
+    ///
+    /// This is like `write_sample()`, but in release builds, the check that
+    /// the sample fits in the number of bits of the spec is skipped, to
+    /// recover throughput in tight export loops. A sample that does not fit
+    /// wraps around: its excess high bits are discarded. In debug builds, such
+    /// a sample causes a panic. The size limit and clip detection still apply.
+    /// Use `write_sample()` unless profiling shows that the check matters.
+    #[inline]
+    pub fn write_sample_unchecked<S: Sample>(&mut self, sample: S) -> Result<()> {
+        self.write_sample_impl(sample, false)
+    }
+
+    /// Writes one frame of a mono file.
+    ///
+    /// This is `write_sample()`, for code that writes frames rather than
+    /// samples. In debug builds, this panics if the spec does not have one
+    /// channel.
+    #[inline]
+    pub fn write_frame_mono<S: Sample>(&mut self, sample: S) -> Result<()> {
+        debug_assert_eq!(self.spec.channels, 1, "write_frame_mono() requires a mono spec");
+        self.write_sample(sample)
+    }
+
+    /// Writes one frame of a stereo file, the left sample first.
+    ///
+    /// This avoids interleaving the channels by hand. In debug builds, this
+    /// panics if the spec does not have two channels, or if an earlier write
+    /// left a frame incomplete. The same checks as for `write_sample()`
+    /// apply to both samples.
+    #[inline]
+    pub fn write_frame_stereo<S: Sample>(&mut self, left: S, right: S) -> Result<()> {
+        debug_assert_eq!(self.spec.channels, 2, "write_frame_stereo() requires a stereo spec");
+        debug_assert!(self.data_bytes_written % (2 * self.bytes_per_sample as u64) == 0,
+                      "write_frame_stereo() called in the middle of a frame");
+        self.write_sample(left)?;
+        self.write_sample(right)
+    }
+
+    /// Returns the number of bytes per sample if samples are packed in little-endian order.
+    ///
+    /// For such layouts, `write_sample_with_layout()` has specialized
+    /// versions. Returns 0 for other layouts.
+    #[inline(always)]
+    fn packed_layout(&self) -> u16 {
+        let bytes = self.bytes_per_sample;
+        if self.endianness == Endianness::Little && self.md5.is_none() && bytes <= 4 &&
+           self.spec.bits_per_sample == bytes * 8 && self.raw_fmt_chunk.is_none() {
+            bytes
+        } else {
+            0
+        }
+    }
+
+    #[inline(always)]
+    fn write_sample_impl<S: Sample>(&mut self, sample: S, checked: bool) -> Result<()> {
+        match self.packed_layout() {
+            1 => self.write_sample_with_layout::<S, 1>(sample, checked),
+            2 => self.write_sample_with_layout::<S, 2>(sample, checked),
+            3 => self.write_sample_with_layout::<S, 3>(sample, checked),
+            4 => self.write_sample_with_layout::<S, 4>(sample, checked),
+            _ => self.write_sample_with_layout::<S, 0>(sample, checked),
+        }
+    }
+
+    /// Writes a sample to a file with `N` bytes per sample, see `packed_layout()`.
+    ///
+    /// With `N` = 0, the layout is taken from the spec at runtime. Otherwise
+    /// the layout is a constant, so the compiler can resolve the encoding.
+    #[inline(always)]
+    fn write_sample_with_layout<S: Sample, const N: u16>(&mut self, sample: S, checked: bool) -> Result<()> {
+        let (bits, bytes) = if N == 0 {
+            (self.spec.bits_per_sample, self.bytes_per_sample)
+        } else {
+            (N * 8, N)
+        };
+        // The format of a raw fmt chunk is not known to be PCM, see
+        // `new_with_fmt_chunk()`. Such writers never have a packed layout.
+        if N == 0 && self.raw_fmt_chunk.is_some() {
+            return Err(Error::Unsupported);
+        }
+        if !self.check_size(bytes as u64)? {
+            return Ok(());
+        }
+        if self.clip_detection != ClipDetection::Off && sample.is_full_scale(bits) {
+            self.clip_count += 1;
+            if self.clip_detection == ClipDetection::Error {
+                return Err(Error::Clipped);
+            }
+        }
+        if N == 0 && (self.endianness == Endianness::Big || self.md5.is_some()) {
+            // The sample is encoded in little-endian order first. Big-endian
+            // writers, and writers that hash the data, store samples in at
+            // most 4 bytes.
+            let n = bytes as usize;
+            let mut buffer = [0u8; 4];
+            write_padded(sample, &mut &mut buffer[..n], self.spec_ex(), checked)?;
+            if self.endianness == Endianness::Big {
+                codec::swap_sample_bytes(&mut buffer[..n], bytes);
+            }
+            if let Some(ref mut md5) = self.md5 {
+                md5.update(&buffer[..n]);
+            }
+            self.writer.write_all(&buffer[..n])?;
+        } else if checked {
+            sample.write_padded(&mut self.writer, bits, bytes)?;
+        } else {
+            sample.write_padded_unchecked(&mut self.writer, bits, bytes)?;
+        }
+        self.data_bytes_written += bytes as u64;
+        Ok(())
+    }
+
+    /// Writes all samples in the slice.
+    ///
+    /// This is equivalent to calling `write_sample()` for every sample, and
+    /// the same checks apply. If an error occurs, the samples before the
+    /// offending sample have been written.
+    pub fn write_samples<S: Sample + Copy>(&mut self, samples: &[S]) -> Result<()> {
+        trace_span!(TRACE, "write_samples", len = samples.len());
+        // Resolve the layout once, rather than for every sample.
+        match self.packed_layout() {
+            1 => self.write_samples_with_layout::<S, 1>(samples),
+            2 => self.write_samples_with_layout::<S, 2>(samples),
+            3 => self.write_samples_with_layout::<S, 3>(samples),
+            4 => self.write_samples_with_layout::<S, 4>(samples),
+            _ => self.write_samples_with_layout::<S, 0>(samples),
+        }
+    }
+
+    fn write_samples_with_layout<S: Sample + Copy, const N: u16>(&mut self, samples: &[S]) -> Result<()> {
+        for &sample in samples {
+            self.write_sample_with_layout::<S, N>(sample, true)?;
+        }
+        Ok(())
+    }
+
+    /// Writes bytes to the data chunk verbatim.
+    ///
+    /// The bytes must already be encoded in the format of the file. This is
+    /// mostly useful in combination with `new_with_fmt_chunk()`, to write data
+    /// that was encoded elsewhere. With `SizeLimit::Cap`, the bytes that do
+    /// not fit are discarded.
+    pub fn write_raw_data(&mut self, bytes: &[u8]) -> Result<()> {
+        trace_span!(TRACE, "write_raw_data", len = bytes.len());
+        let bytes = if self.check_size(bytes.len() as u64)? {
+            bytes
+        } else {
+            &bytes[..(self.max_data_bytes - self.data_bytes_written) as usize]
+        };
+        if let Some(ref mut md5) = self.md5 {
+            md5.update(bytes);
+        }
+        self.writer.write_all(bytes)?;
+        self.data_bytes_written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Sets how samples at full scale are treated by `write_sample()`.
+    ///
+    /// Clip detection is off by default. Samples written through a
+    /// `SampleWriter16`, a `ParallelWriter`, or a typed writer such as
+    /// `WavWriter16` are never inspected.
+    pub fn set_clip_detection(&mut self, detection: ClipDetection) {
+        self.clip_detection = detection;
+    }
+
+    /// Returns the number of samples at full scale encountered so far.
+    ///
+    /// This is always 0 when clip detection is off. With
+    /// `ClipDetection::Error`, rejected samples are counted too.
+    pub fn clip_count(&self) -> u64 {
+        self.clip_count
+    }
+
+    /// Returns the spec that the samples are written with.
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    /// Returns the spec that the samples are written with, including the container size.
+    pub fn spec_ex(&self) -> WavSpecEx {
+        WavSpecEx {
+            spec: self.spec,
+            bytes_per_sample: self.bytes_per_sample,
+        }
+    }
+
+    /// Returns the number of whole frames that have been written.
+    ///
+    /// A frame holds one sample for every channel. Samples of a frame that
+    /// is not complete yet do not count, so this is the number of frames that
+    /// the file would contain if it were finalized now. It is also the value
+    /// of the sample length field of the fact chunk.
+    pub fn frames_written(&self) -> u64 {
+        let block_align = cmp::max(1, self.bytes_per_sample as u64 * self.spec.channels as u64);
+        self.data_bytes_written / block_align
+    }
+
+    /// Returns the size of the file so far, including the header.
+    ///
+    /// Bytes that are buffered, for instance by the `BufWriter` that
+    /// `create()` uses, count as written.
+    pub fn bytes_written(&self) -> u64 {
+        self.data_len_offset() as u64 + 4 + self.data_bytes_written
+    }
+
+    /// Returns the size the file will have after writing `extra_frames` more frames.
+    ///
+    /// This takes the size limit into account: with `SizeLimit::Error` and
+    /// `SizeLimit::Cap`, the data chunk cannot grow beyond 4 GiB. The MD5
+    /// chunk that finalizing writes after the data, if enabled with
+    /// `set_md5_chunk()`, is included.
+    pub fn estimated_final_size(&self, extra_frames: u64) -> u64 {
+        let block_align = self.bytes_per_sample as u64 * self.spec.channels as u64;
+        let extra_bytes = extra_frames.saturating_mul(block_align);
+        let data_bytes = cmp::min(self.data_bytes_written.saturating_add(extra_bytes),
+                                  self.max_data_bytes);
+        let trailer_len = match self.md5 {
+            Some(..) => data_bytes % 2 + MD5_CHUNK_LEN as u64,
+            None => 0,
+        };
+        self.data_len_offset() as u64 + 4 + data_bytes + trailer_len
+    }
+
+    /// Returns whether `extra_frames` more frames fit in `free_bytes` bytes of storage.
+    ///
+    /// The standard library offers no portable way to query the free space
+    /// of a device, so it must be provided by the caller. A recorder can use
+    /// this to warn before a take runs out of space, rather than failing with
+    /// `Error::DiskFull` halfway.
+    pub fn fits_in_free_space(&self, extra_frames: u64, free_bytes: u64) -> bool {
+        self.estimated_final_size(extra_frames) - self.bytes_written() <= free_bytes
+    }
+
+    /// Create an efficient writer that writes 16-bit integer samples only.
+    ///
+    /// When it is known what the kind of samples will be, many dynamic checks
+    /// can be omitted. Furthermore, this writer employs buffering internally,
+    /// which allows omitting return value checks except on flush. The internal
+    /// buffer will be sized such that exactly `num_samples` samples can be
+    /// written to it, and the buffer is recycled across calls to
+    /// `get_i16_writer()` if the previous buffer was sufficiently large.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the spec does not match a 16 bits per sample integer format.
+    ///
+    /// Attempting to write more than `num_samples` samples to the writer will
+    /// panic too.
+    pub fn get_i16_writer<'s>(&'s mut self,
+                              num_samples: u32)
+                              -> SampleWriter16<'s, W> {
+        if self.spec.sample_format != SampleFormat::Int {
+            panic!("When calling get_i16_writer, the sample format must be int.");
+        }
+        if self.spec.bits_per_sample != 16 || self.bytes_per_sample != 2 {
+            panic!("When calling get_i16_writer, the number of bits per sample must be 16.");
+        }
+
+        let num_bytes = num_samples as usize * 2;
+
+        if self.sample_writer_buffer.len() < num_bytes {
+            // We need a bigger buffer. There is no point in growing the old
+            // one, as we are going to overwrite the samples anyway, so just
+            // allocate a new one.
+            self.sample_writer_buffer = vec![0; num_bytes];
+        }
+
+        SampleWriter16 {
+            writer: &mut self.writer,
+            buffer: &mut self.sample_writer_buffer[..num_bytes],
+            data_bytes_written: &mut self.data_bytes_written,
+            max_data_bytes: self.max_data_bytes,
+            size_limit: self.size_limit,
+            big_endian: self.endianness == Endianness::Big,
+            md5: self.md5.as_mut(),
+            index: 0,
+        }
+    }
+
+    /// Performs finalization. After calling this, the writer should be destructed.
+    fn finalize_internal(&mut self) -> Result<()> {
+        trace_span!(DEBUG, "finalize");
+        self.finalized = true;
+
+        // Flush remaining samples via the BufWriter.
+        self.writer.flush()?;
+        self.patch_header()?;
+
+        // Signal error if the last sample was not finished, but do so after
+        // everything has been written, so that no data is lost, even though
+        // the file is now ill-formed. For a raw fmt chunk, the data layout is
+        // not known, so it cannot be verified.
+        if self.raw_fmt_chunk.is_none() &&
+           (self.data_bytes_written / self.bytes_per_sample as u64)
+            % self.spec.channels as u64 != 0 {
+            return Err(Error::UnfinishedSample);
+        }
+
+        Ok(())
+    }
+
+    /// Writes the chunks that go after the data chunk, returns their length.
+    ///
+    /// The length includes the padding byte of the data chunk, if the data
+    /// is followed by a chunk.
+    fn write_trailer(&mut self) -> io::Result<u64> {
+        let data_end = self.data_len_offset() as u64 + 4 + self.data_bytes_written;
+        let padding = self.data_bytes_written % 2;
+        let mut trailer = vec![0u8; padding as usize];
+        if let Some(ref md5) = self.md5 {
+            trailer.extend_from_slice(b"MD5 ");
+            trailer.extend_from_slice(&self.u32_to_file_order(16).to_le_bytes());
+            trailer.extend_from_slice(&md5.finish());
+        }
+        if trailer.len() as u64 == padding {
+            return Ok(0);
+        }
+        self.writer.seek(io::SeekFrom::Start(data_end))?;
+        self.writer.write_all(&trailer)?;
+        Ok(trailer.len() as u64)
+    }
+
+    /// Writes the trailer, and fills in every placeholder with the sizes so far.
+    fn patch_header(&mut self) -> io::Result<()> {
+        let trailer_len = self.write_trailer()?;
+        let file_size = self.data_bytes_written + (self.data_len_offset() as u64 - 4) + trailer_len;
+        let num_frames = self.frames_written();
+
+        // For an RF64 file, the 32-bit size fields are set to 0xffffffff, and
+        // the real sizes go in the ds64 chunk instead.
+        let rf64 = file_size > 0xffff_ffff;
+        let clamp = |x: u64| if rf64 { 0xffff_ffff } else { x as u32 };
+        trace_event!(DEBUG,
+                     data_bytes = self.data_bytes_written,
+                     num_frames = num_frames,
+                     rf64 = rf64,
+                     "updating header");
+
+        for i in 0..self.placeholders.len() {
+            let Placeholder { field, offset } = self.placeholders[i];
+            self.writer.seek(io::SeekFrom::Start(offset as u64))?;
+            let value = match field {
+                Field::RiffLen => clamp(file_size),
+                Field::FactSampleLen => clamp(num_frames),
+                Field::DataLen => clamp(self.data_bytes_written),
+                // Without the `rf64` feature, the size limit caps the data
+                // before the file outgrows a RIFF file, and the JUNK chunk
+                // stays.
+                #[cfg(feature = "rf64")]
+                Field::Ds64 if rf64 => {
+                    self.writer.write_all(b"ds64")?;
+                    self.writer.write_le_u32(DS64_LEN)?;
+                    self.writer.write_le_u64(file_size)?;
+                    self.writer.write_le_u64(self.data_bytes_written)?;
+                    self.writer.write_le_u64(num_frames)?;
+                    // The field tableLength, there are no other chunks over 4 GiB.
+                    self.writer.write_le_u32(0)?;
+                    self.writer.seek(io::SeekFrom::Start(0))?;
+                    self.writer.write_all(b"RF64")?;
+                    continue;
+                }
+                Field::Ds64 => continue,
+            };
+            let value = self.u32_to_file_order(value);
+            self.writer.write_le_u32(value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Updates the header for the samples written so far, and flushes the underlying writer.
+    ///
+    /// The length fields of the header are normally filled in by
+    /// `finalize()`, so a file that is never finalized, because the process
+    /// is killed during a long recording for instance, is not readable. After
+    /// `flush()`, the file is a valid file that contains the samples written
+    /// so far, including the `MD5 ` chunk if enabled. Writing can continue
+    /// afterwards as usual.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.patch_header()?;
+        let data_end = self.data_len_offset() as u64 + 4 + self.data_bytes_written;
+        self.writer.seek(io::SeekFrom::Start(data_end))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the parts of the WAVE format that require knowing all samples.
+    ///
+    /// This method must be called after all samples have been written. If it
+    /// is not called, the destructor will finalize the file, but any errors
+    /// that occur in the process cannot be observed in that manner.
+    pub fn finalize(mut self) -> Result<()> {
+        self.finalize_internal()
+    }
+
+    /// Finalizes the current file, and continues with a new one in `writer`.
+    ///
+    /// The new file is written with `spec`, but otherwise it is set up like
+    /// the current one: clip detection, the size limit, the endianness, the
+    /// MD5 chunk, the channel mask set with `set_channel_mask()`, space
+    /// reserved with `reserve_junk()`, the ambisonic B-Format header (if the
+    /// writer was constructed with `new_ambisonic()`), and the chunks written
+    /// with `write_chunk()`, such as those of a `MetadataTemplate`, carry
+    /// over. The clip count starts at 0 again.
+    ///
+    /// This is useful when the input changes format halfway through a
+    /// recording, such as when a capture device is reconfigured.
+    ///
+    /// If the new file cannot be started, the current writer is left
+    /// untouched. If finalizing the current file fails, the error is
+    /// returned, but the writer has moved on to the new file regardless.
+    pub fn roll_over_to(&mut self, writer: W, spec: WavSpec) -> Result<()> {
+        trace_span!(DEBUG, "roll_over", spec = %spec);
+        let mut next = if self.ambisonic {
+            WavWriter::new_ambisonic(writer, spec)?
+        } else {
+            WavWriter::new_with_header_layout(writer, spec, self.header_layout)?
+        };
+        next.clip_detection = self.clip_detection;
+        next.set_endianness(self.endianness)?;
+        next.set_size_limit(self.size_limit)?;
+        next.set_md5_chunk(self.md5.is_some())?;
+        if let Some(mask) = self.channel_mask {
+            next.set_channel_mask(mask)?;
+        }
+        if self.reserved_junk > 0 {
+            next.reserve_junk(self.reserved_junk - 8)?;
+        }
+        for (id, data) in &self.chunks {
+            next.write_chunk(id, data)?;
+        }
+        mem::swap(&mut next.sample_writer_buffer, &mut self.sample_writer_buffer);
+
+        let previous = mem::replace(self, next);
+        previous.finalize()
+    }
+}
+
+impl<W> Drop for WavWriter<W>
+    where W: io::Write + io::Seek
+{
+    fn drop(&mut self) {
+        // `finalize_internal` must be called only once. If that is done via
+        // `finalize`, then this method is a no-op. If the user did not
+        // finalize explicitly, then we should do it now. This can fail, but
+        // drop should not panic, so a failure is ignored silently here.
+        if !self.finalized {
+            let _r = self.finalize_internal();
+        }
+    }
+}
+
+impl WavWriter<io::BufWriter<fs::File>> {
+    /// Creates a writer that writes the WAVE format to a file.
+    ///
+    /// This is a convenience constructor that creates the file, wraps it in a
+    /// `BufWriter`, and then constructs a `WavWriter` from it. The file will
+    /// be overwritten if it exists.
+    pub fn create<P: AsRef<path::Path>>(filename: P,
+                                        spec: WavSpec)
+                                        -> Result<WavWriter<io::BufWriter<fs::File>>> {
+        let file = fs::File::create(filename)?;
+        let buf_writer = io::BufWriter::new(file);
+        WavWriter::new(buf_writer, spec)
+    }
+
+    /// Finalizes the current file, and continues writing to a new file.
+    ///
+    /// The new file is buffered like the current one. See `roll_over_to()`
+    /// for which settings carry over, and for how errors are handled.
+    pub fn roll_over<P: AsRef<path::Path>>(&mut self,
+                                           spec: WavSpec,
+                                           filename: P)
+                                           -> Result<()> {
+        let file = fs::File::create(filename)?;
+        let buf_writer = io::BufWriter::with_capacity(self.writer.capacity(), file);
+        self.roll_over_to(buf_writer, spec)
+    }
+
+    /// Creates a writer that writes to a temporary file, and moves it into place when finalized.
+    ///
+    /// The temporary file is created in the same directory as `filename`,
+    /// with a name that starts with a dot and ends in `.tmp`. The name is
+    /// unique, so several writers for the same destination do not interfere;
+    /// the one that is finalized last wins.
+    ///
+    /// When the returned writer is finalized successfully, the file is synced
+    /// to disk and renamed to `filename`, replacing a file that exists there.
+    /// If finalization fails, or the writer is dropped without finalizing,
+    /// the temporary file is deleted. Consumers that watch a directory
+    /// therefore never observe a half-written file under `filename`.
+    pub fn create_atomic<P: AsRef<path::Path>>(filename: P,
+                                               spec: WavSpec)
+                                               -> Result<WavAtomicWriter> {
+        let path = filename.as_ref().to_path_buf();
+        let file_name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => return Err(Error::IoError(io::Error::new(io::ErrorKind::InvalidInput,
+                                                             "path has no file name"))),
+        };
+        // The process id makes the name unique among processes, the counter
+        // among writers in this process. A name can still be taken by a file
+        // left behind by an earlier process, then the next one is tried.
+        let (file, temp_path) = loop {
+            let n = ATOMIC_WRITER_COUNT.fetch_add(1, Ordering::Relaxed);
+            let temp_name = format!(".{}.{}.{}.tmp", file_name, process::id(), n);
+            let temp_path = path.with_file_name(temp_name);
+            match fs::OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+                Ok(file) => break (file, temp_path),
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(Error::from(err)),
+            }
+        };
+
+        let writer = WavWriter::new(io::BufWriter::new(file), spec).map_err(|err| {
+            let _ = fs::remove_file(&temp_path);
+            err
+        })?;
+        Ok(WavAtomicWriter {
+            writer: Some(writer),
+            temp_path,
+            path,
+        })
+    }
+
+    /// Finalizes the file, and ensures that it is written to disk.
+    fn finalize_and_sync(&mut self) -> Result<()> {
+        self.finalize_internal()?;
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(())
+    }
+}
+
+/// The number of temporary files created by `WavWriter::create_atomic()`.
+static ATOMIC_WRITER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A `WavWriter` that writes a file atomically, see `WavWriter::create_atomic()`.
+pub struct WavAtomicWriter {
+    /// The writer for the temporary file, `None` once finalized.
+    writer: Option<WavWriter<io::BufWriter<fs::File>>>,
+    temp_path: path::PathBuf,
+    path: path::PathBuf,
+}
+
+impl WavAtomicWriter {
+    /// Finalizes the file, and moves it into place.
+    ///
+    /// See `WavWriter::finalize()`. If an error occurs, the temporary file is
+    /// deleted, and nothing is written to the destination.
+    pub fn finalize(mut self) -> Result<()> {
+        let mut writer = self.writer.take().expect("the writer is present until finalized");
+        let result = writer.finalize_and_sync();
+        drop(writer);
+        match result {
+            Ok(()) => {
+                if let Err(err) = fs::rename(&self.temp_path, &self.path) {
+                    let _ = fs::remove_file(&self.temp_path);
+                    return Err(Error::from(err));
+                }
+                Ok(())
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&self.temp_path);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for WavAtomicWriter {
+    fn drop(&mut self) {
+        // If the writer was not finalized, the file is abandoned. It need not
+        // be finalized, but an error while deleting it cannot be reported.
+        if let Some(mut writer) = self.writer.take() {
+            writer.finalized = true;
+            drop(writer);
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+impl ops::Deref for WavAtomicWriter {
+    type Target = WavWriter<io::BufWriter<fs::File>>;
+
+    fn deref(&self) -> &WavWriter<io::BufWriter<fs::File>> {
+        self.writer.as_ref().expect("the writer is present until finalized")
+    }
+}
+
+impl ops::DerefMut for WavAtomicWriter {
+    fn deref_mut(&mut self) -> &mut WavWriter<io::BufWriter<fs::File>> {
+        self.writer.as_mut().expect("the writer is present until finalized")
+    }
+}
+
+impl WavWriter<io::Cursor<Vec<u8>>> {
+    /// Creates a writer that writes the WAVE format to memory.
+    ///
+    /// The returned writer dereferences to a `WavWriter`, so samples are
+    /// written in the usual way. Its `finalize()` returns the complete file,
+    /// with the header filled in. This is convenient for generating files
+    /// that are sent elsewhere, such as in an HTTP response.
+    pub fn new_in_memory(spec: WavSpec) -> Result<WavMemoryWriter> {
+        let writer = WavWriter::new(io::Cursor::new(Vec::new()), spec)?;
+        Ok(WavMemoryWriter { writer })
+    }
+}
+
+/// A `WavWriter` that writes to memory, see `WavWriter::new_in_memory()`.
+pub struct WavMemoryWriter {
+    writer: WavWriter<io::Cursor<Vec<u8>>>,
+}
+
+impl WavMemoryWriter {
+    /// Finalizes the file, and returns its bytes.
+    ///
+    /// See `WavWriter::finalize()`. If finalization fails, the bytes are lost.
+    pub fn finalize(mut self) -> Result<Vec<u8>> {
+        self.writer.finalize_internal()?;
+        Ok(mem::take(self.writer.writer.get_mut()))
+    }
+}
+
+impl ops::Deref for WavMemoryWriter {
+    type Target = WavWriter<io::Cursor<Vec<u8>>>;
+
+    fn deref(&self) -> &WavWriter<io::Cursor<Vec<u8>>> {
+        &self.writer
+    }
+}
+
+impl ops::DerefMut for WavMemoryWriter {
+    fn deref_mut(&mut self) -> &mut WavWriter<io::Cursor<Vec<u8>>> {
+        &mut self.writer
+    }
+}
+
+/// A writer that specifically only writes integer samples of 16 bits per sample.
+///
 /// The writer buffers written samples internally so they can be written in a
 /// single batch later on. This has two advantages when performance is
 /// important:
@@ -516,7 +1845,19 @@ pub struct SampleWriter16<'parent, W> where W: io::Write + io::Seek + 'parent {
     buffer: &'parent mut [u8],
 
     /// Reference to the `data_bytes_written` field of the writer.
-    data_bytes_written: &'parent mut u32,
+    data_bytes_written: &'parent mut u64,
+
+    /// The maximum size of the data section, copied from the writer.
+    max_data_bytes: u64,
+
+    /// What to do when the data outgrows a RIFF file, copied from the writer.
+    size_limit: SizeLimit,
+
+    /// Whether samples are stored big-endian, copied from the writer.
+    big_endian: bool,
+
+    /// The hash of the data of the writer, if it writes an `MD5 ` chunk.
+    md5: Option<&'parent mut Md5>,
 
     /// The index into the buffer where the next bytes will be written.
     index: u32,
@@ -554,9 +1895,8 @@ impl<'parent, W: io::Write + io::Seek> SampleWriter16<'parent, W> {
     unsafe fn write_u16_le_unchecked(&mut self, value: u16) {
         // x86_64 is little endian, so we do not need to shuffle bytes around;
         // we can just store the 16-bit integer in the buffer directly.
-        use std::mem;
-        let ptr: *mut u16 = mem::transmute(self.buffer.get_unchecked_mut(self.index as usize));
-        *ptr = value;
+        let ptr = self.buffer.as_mut_ptr().add(self.index as usize) as *mut u16;
+        ptr.write_unaligned(value);
     }
 
     #[cfg(not(target_arch = "x86_64"))]
@@ -571,6 +1911,8 @@ impl<'parent, W: io::Write + io::Seek> SampleWriter16<'parent, W> {
     /// Like `write_sample()`, but does not perform a bounds check when writing
     /// to the internal buffer.
     ///
+    /// # Safety
+    ///
     /// It is the responsibility of the programmer to ensure that no more
     /// samples are written than allocated when the writer was created.
     #[inline(always)]
@@ -585,20 +1927,42 @@ impl<'parent, W: io::Write + io::Seek> SampleWriter16<'parent, W> {
     ///
     /// Panics if insufficient samples (less than specified when the writer was
     /// constructed) have been written with `write_sample()`.
+    ///
+    /// If the samples do not fit in the file, the size limit of the parent
+    /// writer determines what happens, see `WavWriter::set_size_limit()`.
     pub fn flush(self) -> Result<()> {
+        trace_span!(TRACE, "write_block", len = self.buffer.len());
         if self.index as usize != self.buffer.len() {
             panic!("Insufficient samples written to the sample writer.");
         }
 
-        try!(self.writer.write_all(&self.buffer));
-        *self.data_bytes_written += self.buffer.len() as u32;
-        Ok(())
-    }
-}
+        let mut len = self.buffer.len() as u64;
+        if *self.data_bytes_written + len > self.max_data_bytes {
+            if self.size_limit != SizeLimit::Cap {
+                return Err(Error::TooLarge);
+            }
+            len = self.max_data_bytes - *self.data_bytes_written;
+        }
+
+        // Samples are written to the buffer in little-endian order.
+        if self.big_endian {
+            for sample in self.buffer.chunks_mut(2) {
+                sample.swap(0, 1);
+            }
+        }
+
+        if let Some(md5) = self.md5 {
+            md5.update(&self.buffer[..len as usize]);
+        }
+        self.writer.write_all(&self.buffer[..len as usize])?;
+        *self.data_bytes_written += len;
+        Ok(())
+    }
+}
 
 #[test]
 fn short_write_should_signal_error() {
-    use SampleFormat;
+    use crate::SampleFormat;
 
     let mut buffer = io::Cursor::new(Vec::new());
 
@@ -656,3 +2020,1292 @@ fn wide_write_should_signal_error() {
         assert!(writer.write_sample(8_388_608_i32).is_err());
     }
 }
+
+#[test]
+fn absurd_container_size_is_unsupported() {
+    let spec_ex = WavSpecEx {
+        spec: WavSpec {
+            channels: 1,
+            sample_rate: 1,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        },
+        bytes_per_sample: 0x2000,
+    };
+    match WavWriter::new_with_spec_ex(io::Cursor::new(Vec::new()), spec_ex) {
+        Err(Error::Unsupported) => {}
+        Err(err) => panic!("Expected Unsupported, got {}.", err),
+        Ok(..) => panic!("Expected Unsupported, got a writer."),
+    }
+}
+
+#[test]
+fn unchecked_write_matches_checked_write() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 20,
+        sample_format: SampleFormat::Int,
+    };
+    let mut checked = io::Cursor::new(Vec::new());
+    let mut unchecked = io::Cursor::new(Vec::new());
+    {
+        let mut a = WavWriter::new(&mut checked, spec).unwrap();
+        let mut b = WavWriter::new(&mut unchecked, spec).unwrap();
+        for &x in &[0, 1, -1, 524287, -524288, 12345] {
+            a.write_sample(x).unwrap();
+            b.write_sample_unchecked(x).unwrap();
+        }
+    }
+    assert_eq!(checked.into_inner(), unchecked.into_inner());
+}
+
+#[test]
+#[cfg(not(debug_assertions))]
+fn unchecked_write_wraps_out_of_range_samples() {
+    let mut buffer = Vec::new();
+    70000_i32.write_padded_unchecked(&mut buffer, 16, 2).unwrap();
+    assert_eq!(&buffer[..], &[0x70, 0x11]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+fn unchecked_write_checks_range_in_debug_builds() {
+    let mut buffer = Vec::new();
+    let _ = 70000_i32.write_padded_unchecked(&mut buffer, 16, 2);
+}
+
+#[test]
+fn clip_detection_counts_full_scale_samples() {
+    let mut buffer = io::Cursor::new(Vec::new());
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+
+    let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+    writer.write_sample(32767_i16).unwrap();
+    assert_eq!(writer.clip_count(), 0);
+
+    writer.set_clip_detection(ClipDetection::Count);
+    writer.write_sample(32767_i16).unwrap();
+    writer.write_sample(-32768_i32).unwrap();
+    writer.write_sample(32766_i16).unwrap();
+    assert_eq!(writer.clip_count(), 2);
+
+    writer.set_clip_detection(ClipDetection::Error);
+    match writer.write_sample(-32768_i16) {
+        Err(Error::Clipped) => {}
+        _ => panic!("Clipped error should have been returned."),
+    }
+    assert!(writer.write_sample(0_i16).is_ok());
+    assert_eq!(writer.clip_count(), 3);
+    writer.finalize().unwrap();
+}
+
+#[test]
+#[cfg(feature = "float")]
+fn clip_detection_applies_to_float_samples() {
+    let mut buffer = io::Cursor::new(Vec::new());
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+
+    let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+    writer.set_clip_detection(ClipDetection::Count);
+    for &s in &[0.5_f32, 1.0, -1.5, 0.999] {
+        writer.write_sample(s).unwrap();
+    }
+    assert_eq!(writer.clip_count(), 2);
+}
+
+#[test]
+fn reserve_junk_leaves_space_for_metadata() {
+    use crate::edit::WavEditor;
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+        writer.reserve_junk(127).unwrap();
+        writer.write_sample(3_i16).unwrap();
+        writer.write_sample(-4_i16).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    let mut editor = WavEditor::new(buffer).unwrap();
+    let data_offset = editor.chunks()[2].offset;
+    assert_eq!(&editor.chunks()[1].id, b"JUNK");
+    assert_eq!(editor.chunks()[1].len, 127);
+    assert_eq!(data_offset, 36 + 8 + 128);
+
+    editor.set_chunk(b"bext", &[1; 100]).unwrap();
+    assert_eq!(&editor.chunks()[1].id, b"bext");
+    assert_eq!(editor.chunks()[3].offset, data_offset);
+
+    let bytes = editor.into_inner().unwrap().into_inner();
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[3, -4]);
+}
+
+#[test]
+fn write_with_raw_fmt_chunk_roundtrips() {
+    use crate::read::WavReader;
+
+    // A PCMWAVEFORMAT struct for 16-bit mono at 44.1 kHz.
+    let fmt = [0x01, 0x00, 0x01, 0x00, 0x44, 0xac, 0x00, 0x00,
+               0x88, 0x58, 0x01, 0x00, 0x02, 0x00, 0x10, 0x00];
+
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new_with_fmt_chunk(&mut buffer, &fmt).unwrap();
+        writer.write_raw_data(&[0x02, 0x00, 0xfd, 0xff]).unwrap();
+        writer.write_raw_data(&[0x05, 0x00]).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    buffer.set_position(0);
+    let mut reader = WavReader::new(&mut buffer).unwrap();
+    assert_eq!(reader.fmt_chunk_bytes(), &fmt[..]);
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[2, -3, 5]);
+}
+
+#[test]
+fn write_with_raw_fmt_chunk_pads_odd_sized_chunk() {
+    use crate::read::ReadExt;
+
+    // A WAVE_FORMAT_ADPCM header with a bogus one-byte extension.
+    let fmt = [0x02, 0x00, 0x01, 0x00, 0x44, 0xac, 0x00, 0x00,
+               0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x04, 0x00, 0x07];
+
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new_with_fmt_chunk(&mut buffer, &fmt).unwrap();
+        match writer.write_sample(1_i16) {
+            Err(Error::Unsupported) => {}
+            other => panic!("expected Error::Unsupported, got {:?}", other),
+        }
+        writer.write_raw_data(&[1, 2, 3, 4, 5, 6]).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    let bytes = buffer.into_inner();
+    assert_eq!(bytes.len(), 12 + 8 + 18 + 8 + 6);
+    assert_eq!(&bytes[38..42], b"data");
+    let mut cursor = io::Cursor::new(&bytes[..]);
+    cursor.set_position(4);
+    assert_eq!(cursor.read_le_u32().unwrap(), bytes.len() as u32 - 8);
+    cursor.set_position(42);
+    assert_eq!(cursor.read_le_u32().unwrap(), 6);
+}
+
+#[test]
+fn writer_reports_size_and_estimates_final_size() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    assert_eq!(writer.bytes_written(), 44);
+    writer.write_samples(&[1i16, -1, 2, -2]).unwrap();
+    assert_eq!(writer.bytes_written(), 52);
+    assert_eq!(writer.estimated_final_size(0), 52);
+    assert_eq!(writer.estimated_final_size(10), 92);
+    assert!(writer.fits_in_free_space(10, 40));
+    assert!(!writer.fits_in_free_space(10, 39));
+
+    // The data chunk of a RIFF file cannot grow beyond 4 GiB.
+    let max = writer.estimated_final_size(u64::MAX);
+    assert!(max <= 0xffff_ffff + 8);
+    writer.set_size_limit(SizeLimit::Cap).unwrap();
+    assert_eq!(writer.estimated_final_size(u64::MAX), max);
+
+    assert_eq!(writer.finalize().unwrap().len(), 52);
+
+    // The MD5 chunk, and the padding byte before it, are written when
+    // finalizing.
+    let spec = WavSpec { channels: 1, bits_per_sample: 8, ..spec };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    writer.set_md5_chunk(true).unwrap();
+    writer.write_samples(&[1i8, 2, 3]).unwrap();
+    assert_eq!(writer.bytes_written(), 47);
+    assert_eq!(writer.estimated_final_size(0), 72);
+    assert!(writer.fits_in_free_space(0, 25));
+    assert!(!writer.fits_in_free_space(0, 24));
+    assert_eq!(writer.finalize().unwrap().len(), 72);
+}
+
+#[test]
+#[cfg(unix)]
+fn full_disk_should_signal_disk_full() {
+    struct FullDisk;
+
+    impl io::Write for FullDisk {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from_raw_os_error(28))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl io::Seek for FullDisk {
+        fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+            Ok(0)
+        }
+    }
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    match WavWriter::new(FullDisk, spec) {
+        Err(Error::DiskFull(_)) => {}
+        _ => panic!("a full disk should signal Error::DiskFull"),
+    }
+}
+
+#[test]
+fn atomic_writer_moves_file_into_place_on_finalize() {
+    use crate::read::WavReader;
+    use std::env;
+
+    let dir = env::temp_dir().join(format!("hound-atomic-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("take.wav");
+    let num_files = || fs::read_dir(&dir).unwrap().count();
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+
+    // The file appears only when the writer is finalized.
+    let mut writer = WavWriter::create_atomic(&path, spec).unwrap();
+    writer.write_sample(7i16).unwrap();
+    assert_eq!(num_files(), 1);
+    assert!(!path.exists());
+    writer.finalize().unwrap();
+    assert_eq!(num_files(), 1);
+    let mut reader = WavReader::open(&path).unwrap();
+    assert_eq!(reader.samples::<i16>().next().unwrap().unwrap(), 7);
+    fs::remove_file(&path).unwrap();
+
+    // Dropping the writer abandons the file.
+    {
+        let mut writer = WavWriter::create_atomic(&path, spec).unwrap();
+        writer.write_sample(7i16).unwrap();
+    }
+    assert_eq!(num_files(), 0);
+
+    // So does a failure to finalize.
+    let spec = WavSpec { channels: 2, ..spec };
+    let mut writer = WavWriter::create_atomic(&path, spec).unwrap();
+    writer.write_sample(7i16).unwrap();
+    assert!(writer.finalize().is_err());
+    assert_eq!(num_files(), 0);
+
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn atomic_writers_for_the_same_file_do_not_interfere() {
+    use crate::read::WavReader;
+    use std::env;
+
+    let dir = env::temp_dir().join(format!("hound-atomic-shared-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("take.wav");
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+
+    let mut first = WavWriter::create_atomic(&path, spec).unwrap();
+    first.write_sample(1i16).unwrap();
+    let mut second = WavWriter::create_atomic(&path, spec).unwrap();
+    second.write_sample(2i16).unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    // Abandoning one writer leaves the other one intact.
+    drop(second);
+    first.write_sample(3i16).unwrap();
+    first.finalize().unwrap();
+    let mut reader = WavReader::open(&path).unwrap();
+    let samples: Vec<i16> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, [1, 3]);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn in_memory_writer_returns_finalized_file() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    writer.write_samples(&[1i16, -1, 2, -2]).unwrap();
+    let bytes = writer.finalize().unwrap();
+
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+        writer.write_samples(&[1i16, -1, 2, -2]).unwrap();
+    }
+    assert_eq!(bytes, buffer.into_inner());
+
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.num_samples(), 4);
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1, -1, 2, -2]);
+}
+
+#[test]
+fn write_read_many_channels_is_lossless() {
+    use crate::read::WavReader;
+
+    for &(channels, sample_rate) in &[(32, 352_800), (64, 48_000)] {
+        let mut buffer = io::Cursor::new(Vec::new());
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Int,
+        };
+
+        {
+            let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+            for frame in 0..8 {
+                for ch in 0..channels as i32 {
+                    writer.write_sample(frame * 1_000_000 - ch).unwrap();
+                }
+            }
+            writer.finalize().unwrap();
+        }
+
+        buffer.set_position(0);
+        let mut reader = WavReader::new(&mut buffer).unwrap();
+        assert_eq!(reader.spec(), spec);
+        assert_eq!(reader.num_frames(), 8);
+        // The header must be WAVEFORMATEXTENSIBLE.
+        assert_eq!(reader.fmt_chunk_bytes().len(), 40);
+        for (i, sample) in reader.samples::<i32>().enumerate() {
+            let (frame, ch) = (i as i32 / channels as i32, i as i32 % channels as i32);
+            assert_eq!(sample.unwrap(), frame * 1_000_000 - ch);
+        }
+    }
+}
+
+#[test]
+fn unrepresentable_spec_should_signal_error() {
+    let spec = WavSpec {
+        channels: 16_384,
+        sample_rate: 48_000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Int,
+    };
+    match WavWriter::new(io::Cursor::new(Vec::new()), spec) {
+        Err(Error::Unsupported) => {}
+        _ => panic!("Unsupported error should have been returned."),
+    }
+
+    let spec = WavSpec { channels: 0, ..spec };
+    assert!(WavWriter::new(io::Cursor::new(Vec::new()), spec).is_err());
+
+    let spec = WavSpec { channels: 8_192, sample_rate: 1_000_000, ..spec };
+    assert!(WavWriter::new(io::Cursor::new(Vec::new()), spec).is_err());
+
+    let spec = WavSpec { channels: 2, sample_rate: 48_000, bits_per_sample: 24,
+                         sample_format: SampleFormat::Float };
+    match WavWriter::new(io::Cursor::new(Vec::new()), spec) {
+        Err(Error::Unsupported) => {}
+        _ => panic!("Unsupported error should have been returned."),
+    }
+}
+
+#[test]
+fn write_header_matches_format_matrix() {
+    use crate::read::WavReader;
+
+    // The expected values are what ffprobe reports for these files: the codec
+    // follows from the format tag and subformat, the sample format from the
+    // container size, and the channel layout from the mask.
+    let ex = 1;
+    let ext = 0xfffe;
+    let cases: &[(SampleFormat, u16, u16, u16, u16, u32)] = &[
+        // (format, bits, channels, format tag, container bits, channel mask)
+        (SampleFormat::Int, 8, 1, ex, 8, 0),
+        (SampleFormat::Int, 16, 2, ex, 16, 0),
+        (SampleFormat::Int, 12, 1, ext, 16, 0x1),
+        (SampleFormat::Int, 16, 6, ext, 16, 0x3f),
+        (SampleFormat::Int, 20, 2, ext, 24, 0x3),
+        (SampleFormat::Int, 24, 1, ext, 24, 0x1),
+        (SampleFormat::Int, 32, 4, ext, 32, 0xf),
+        (SampleFormat::Int, 16, 19, ext, 16, 0),
+        (SampleFormat::Float, 32, 1, ext, 32, 0x1),
+        (SampleFormat::Float, 32, 2, ext, 32, 0x3),
+        (SampleFormat::Float, 32, 8, ext, 32, 0xff),
+        (SampleFormat::Float, 32, 24, ext, 32, 0),
+    ];
+
+    for &(format, bits, channels, format_tag, container_bits, mask) in cases {
+        let spec = WavSpec {
+            channels,
+            sample_rate: 48_000,
+            bits_per_sample: bits,
+            sample_format: format,
+        };
+        let mut buffer = io::Cursor::new(Vec::new());
+        {
+            // Samples of 12 or 20 bits cannot be written with `write_sample`,
+            // but only the header matters here; write three frames of silence.
+            let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+            let frame_bytes = container_bits as usize / 8 * channels as usize;
+            writer.write_raw_data(&vec![0; 3 * frame_bytes]).unwrap();
+            writer.finalize().unwrap();
+        }
+
+        buffer.set_position(0);
+        let reader = WavReader::new(&mut buffer).unwrap();
+        assert_eq!(reader.spec(), spec);
+        assert_eq!(reader.num_frames(), 3);
+
+        let fmt = reader.fmt_chunk_bytes().to_vec();
+        let mut cursor = io::Cursor::new(&fmt[..]);
+        assert_eq!(cursor.read_le_u16().unwrap(), format_tag);
+        cursor.set_position(12);
+        assert_eq!(cursor.read_le_u16().unwrap(), container_bits / 8 * channels);
+        assert_eq!(cursor.read_le_u16().unwrap(), container_bits);
+        if format_tag == ext {
+            assert_eq!(fmt.len(), 40);
+            assert_eq!(cursor.read_le_u16().unwrap(), 22);
+            // The field wValidBitsPerSample.
+            assert_eq!(cursor.read_le_u16().unwrap(), bits);
+            assert_eq!(cursor.read_le_u32().unwrap(), mask);
+            let subformat = match format {
+                SampleFormat::Int => chunks::KSDATAFORMAT_SUBTYPE_PCM,
+                SampleFormat::Float => chunks::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+            };
+            assert_eq!(&fmt[24..], &subformat[..]);
+        } else {
+            assert_eq!(fmt.len(), 16);
+        }
+
+        // Non-PCM files must have a fact chunk with the number of frames.
+        let fact = reader.file_map().iter().find(|c| &c.id == b"fact").cloned();
+        match format {
+            SampleFormat::Int => assert!(fact.is_none()),
+            SampleFormat::Float => {
+                let fact = fact.expect("float files must have a fact chunk");
+                assert_eq!(fact.len, 4);
+                let bytes = reader.into_inner().get_ref().clone();
+                let start = fact.offset as usize + 8;
+                let mut cursor = io::Cursor::new(&bytes[start..start + 4]);
+                assert_eq!(cursor.read_le_u32().unwrap(), 3);
+            }
+        }
+    }
+}
+
+#[test]
+fn header_layouts_match_reference_headers() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new_with_header_layout(&mut buffer, spec, HeaderLayout::WaveFormatEx).unwrap();
+        writer.write_sample(1_i16).unwrap();
+        writer.write_sample(2_i16).unwrap();
+        writer.finalize().unwrap();
+    }
+    // As written by mmioWrite-based tools: WAVEFORMATEX with cbSize 0.
+    let expected: &[u8] = &[
+        b'R', b'I', b'F', b'F', 42, 0, 0, 0, b'W', b'A', b'V', b'E',
+        b'f', b'm', b't', b' ', 18, 0, 0, 0,
+        1, 0, 2, 0, 0x44, 0xac, 0, 0, 0x10, 0xb1, 2, 0, 4, 0, 16, 0, 0, 0,
+        b'd', b'a', b't', b'a', 4, 0, 0, 0, 1, 0, 2, 0,
+    ];
+    assert_eq!(&buffer.get_ref()[..], expected);
+
+    #[cfg(feature = "float")]
+    {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut buffer = io::Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new_with_header_layout(&mut buffer, spec, HeaderLayout::Sox).unwrap();
+            writer.write_sample(0.5_f32).unwrap();
+            writer.finalize().unwrap();
+        }
+        // As written by sox: WAVE_FORMAT_IEEE_FLOAT with cbSize 0, and a fact chunk.
+        let expected: &[u8] = &[
+            b'R', b'I', b'F', b'F', 54, 0, 0, 0, b'W', b'A', b'V', b'E',
+            b'f', b'm', b't', b' ', 18, 0, 0, 0,
+            3, 0, 1, 0, 0x80, 0xbb, 0, 0, 0x00, 0xee, 2, 0, 4, 0, 32, 0, 0, 0,
+            b'f', b'a', b'c', b't', 4, 0, 0, 0, 1, 0, 0, 0,
+            b'd', b'a', b't', b'a', 4, 0, 0, 0, 0, 0, 0, 0x3f,
+        ];
+        assert_eq!(&buffer.get_ref()[..], expected);
+    }
+}
+
+#[test]
+fn header_layout_matrix_is_readable() {
+    use crate::read::WavReader;
+
+    let layouts = [HeaderLayout::Minimal, HeaderLayout::WaveFormatEx, HeaderLayout::Sox];
+    let cases: &[(SampleFormat, u16, u16, [u32; 3])] = &[
+        // (format, bits, channels, fmt length per layout)
+        (SampleFormat::Int, 8, 1, [16, 18, 16]),
+        (SampleFormat::Int, 16, 2, [16, 18, 16]),
+        (SampleFormat::Int, 24, 1, [40, 40, 40]),
+        (SampleFormat::Int, 16, 6, [40, 40, 40]),
+        (SampleFormat::Float, 32, 1, [40, 18, 18]),
+        (SampleFormat::Float, 32, 2, [40, 18, 18]),
+        (SampleFormat::Float, 32, 3, [40, 40, 40]),
+    ];
+    for &(format, bits, channels, ref fmt_lens) in cases {
+        for (&layout, &fmt_len) in layouts.iter().zip(fmt_lens) {
+            for &endianness in &[Endianness::Little, Endianness::Big] {
+                for &rf64 in &[false, true] {
+                    if rf64 && (endianness == Endianness::Big || cfg!(not(feature = "rf64"))) {
+                        continue;
+                    }
+                    let spec = WavSpec {
+                        channels,
+                        sample_rate: 8000,
+                        bits_per_sample: bits,
+                        sample_format: format,
+                    };
+                    let mut buffer = io::Cursor::new(Vec::new());
+                    {
+                        let mut writer = WavWriter::new_with_header_layout(&mut buffer, spec, layout).unwrap();
+                        if rf64 {
+                            writer.set_size_limit(SizeLimit::Rf64).unwrap();
+                        }
+                        writer.set_endianness(endianness).unwrap();
+                        assert_eq!(writer.header_layout(), layout);
+                        writer.reserve_junk(3).unwrap();
+                        let frame = vec![0x11; bits as usize / 8 * channels as usize];
+                        writer.write_raw_data(&frame).unwrap();
+                        writer.write_raw_data(&frame).unwrap();
+                        writer.finalize().unwrap();
+                    }
+
+                    buffer.set_position(0);
+                    let reader = WavReader::new(&mut buffer).unwrap();
+                    assert_eq!(reader.spec(), spec);
+                    assert_eq!(reader.num_frames(), 2);
+                    assert_eq!(reader.fmt_chunk_bytes().len() as u32, fmt_len,
+                               "{:?} {:?} {:?}", layout, spec, endianness);
+                    let fact = reader.file_map().iter().any(|c| &c.id == b"fact");
+                    assert_eq!(fact, format == SampleFormat::Float);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "float")]
+fn float_with_junk_keeps_fact_chunk() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 48_000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+        writer.reserve_junk(10).unwrap();
+        writer.write_sample(0.25f32).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    buffer.set_position(0);
+    let mut reader = WavReader::new(&mut buffer).unwrap();
+    let ids: Vec<&[u8; 4]> = reader.file_map().iter().map(|c| &c.id).collect();
+    assert_eq!(&ids[..], &[b"fmt ", b"fact", b"JUNK", b"data"]);
+    assert_eq!(reader.samples::<f32>().next().unwrap().unwrap(), 0.25);
+}
+
+#[test]
+#[cfg(feature = "float")]
+fn write_read_ambisonic_is_lossless() {
+    use crate::read::WavReader;
+
+    let mut buffer = io::Cursor::new(Vec::new());
+    let spec = WavSpec {
+        channels: 4,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+
+    {
+        let mut writer = WavWriter::new_ambisonic(&mut buffer, spec).unwrap();
+        for s in 0..16 {
+            writer.write_sample(s as f32 / 16.0).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    buffer.set_position(0);
+    let mut reader = WavReader::new(&mut buffer).unwrap();
+    assert_eq!(reader.spec(), spec);
+    let format = reader.ambisonic_format().unwrap();
+    assert_eq!((format.horizontal_order, format.vertical_order), (1, 1));
+    assert_eq!(format.channel_names(), &["W", "X", "Y", "Z"]);
+    // The channel mask must be zero for B-Format.
+    assert_eq!(&reader.fmt_chunk_bytes()[20..24], &[0, 0, 0, 0]);
+    for (i, sample) in reader.samples::<f32>().enumerate() {
+        assert_eq!(sample.unwrap(), i as f32 / 16.0);
+    }
+}
+
+#[test]
+fn ambisonic_writer_rejects_invalid_channel_count() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 48000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    assert!(WavWriter::new_ambisonic(io::Cursor::new(Vec::new()), spec).is_err());
+}
+
+#[test]
+fn write_i32_matches_reference_file() {
+    use std::fs;
+    use std::io::Read;
+
+    // The reference file was produced by a different tool, Hound should
+    // produce exactly the same bytes for the same samples.
+    let mut reference = Vec::new();
+    fs::File::open("testsamples/waveformatextensible-32bit-48kHz-stereo.wav").unwrap()
+        .read_to_end(&mut reference).unwrap();
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 48000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Int,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+        writer.write_samples(&[19_i32, -229_373, 33_587_161, -2_147_483_497]).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    assert_eq!(buffer.into_inner(), reference);
+}
+
+#[test]
+fn write_read_i32_extremes_is_lossless() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Int,
+    };
+    let samples = [i32::MIN, i32::MIN + 1, -1, 0, 1, i32::MAX - 1, i32::MAX];
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+        writer.write_samples(&samples).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    buffer.set_position(0);
+    let mut reader = WavReader::new(&mut buffer).unwrap();
+    let mut read = [0_i32; 7];
+    assert_eq!(reader.read_samples(&mut read).unwrap(), 7);
+    assert_eq!(read, samples);
+}
+
+/// A writer that keeps only the first bytes written, to test huge files.
+#[cfg(test)]
+struct HeaderOnlyWriter {
+    header: [u8; 128],
+    position: u64,
+    /// The length of the stream, including the bytes that were not kept.
+    len: u64,
+}
+
+#[cfg(test)]
+impl HeaderOnlyWriter {
+    fn new() -> HeaderOnlyWriter {
+        HeaderOnlyWriter { header: [0; 128], position: 0, len: 0 }
+    }
+
+    fn read_le_u32(&self, offset: usize) -> u32 {
+        io::Cursor::new(&self.header[offset..]).read_le_u32().unwrap()
+    }
+
+    #[cfg(feature = "rf64")]
+    fn read_le_u64(&self, offset: usize) -> u64 {
+        self.read_le_u32(offset) as u64 | (self.read_le_u32(offset + 4) as u64) << 32
+    }
+}
+
+#[cfg(test)]
+impl io::Write for HeaderOnlyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.position < self.header.len() as u64 {
+            let start = self.position as usize;
+            let n = cmp::min(buf.len(), self.header.len() - start);
+            self.header[start..start + n].copy_from_slice(&buf[..n]);
+        }
+        self.position += buf.len() as u64;
+        self.len = cmp::max(self.len, self.position);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl io::Seek for HeaderOnlyWriter {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(pos) => (pos, 0),
+            io::SeekFrom::Current(offset) => (self.position, offset),
+            io::SeekFrom::End(offset) => (self.len, offset),
+        };
+        match (base as i64).checked_add(offset) {
+            Some(position) if position >= 0 => self.position = position as u64,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        }
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+fn write_5_gib(writer: &mut WavWriter<&mut HeaderOnlyWriter>) -> Result<()> {
+    let block = vec![0u8; 64 << 20];
+    for _ in 0..80 {
+        writer.write_raw_data(&block)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn oversized_data_should_signal_error() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 48_000,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let mut out = HeaderOnlyWriter::new();
+    {
+        let mut writer = WavWriter::new(&mut out, spec).unwrap();
+        match write_5_gib(&mut writer) {
+            Err(Error::TooLarge) => {}
+            _ => panic!("TooLarge error should have been returned."),
+        }
+        // Samples that fit can still be written.
+        writer.write_sample(1).unwrap();
+        writer.write_sample(2).unwrap();
+        writer.finalize().unwrap();
+    }
+    // 63 blocks of 64 MiB were written, the 64th did not fit.
+    assert_eq!(&out.header[..4], b"RIFF");
+    assert_eq!(out.read_le_u32(64), (63 << 26) + 6);
+    assert_eq!(out.read_le_u32(4), (63 << 26) + 6 + 60);
+}
+
+#[test]
+fn oversized_data_is_capped_at_whole_frames() {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 48_000,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let mut out = HeaderOnlyWriter::new();
+    {
+        let mut writer = WavWriter::new(&mut out, spec).unwrap();
+        writer.set_size_limit(SizeLimit::Cap).unwrap();
+        write_5_gib(&mut writer).unwrap();
+        writer.write_sample(1).unwrap();
+        writer.finalize().unwrap();
+    }
+    let data_len = out.read_le_u32(64);
+    assert_eq!(data_len % 6, 0);
+    assert!(data_len > 0xffff_ffff - 60 - 6);
+    assert_eq!(out.read_le_u32(4), data_len + 60);
+}
+
+#[test]
+#[cfg(feature = "rf64")]
+fn oversized_data_switches_to_rf64() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 48_000,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut out = HeaderOnlyWriter::new();
+    {
+        let mut writer = WavWriter::new(&mut out, spec).unwrap();
+        writer.set_size_limit(SizeLimit::Rf64).unwrap();
+        write_5_gib(&mut writer).unwrap();
+        writer.finalize().unwrap();
+    }
+    let data_len = 80 << 26;
+    // The header has a ds64 chunk, fmt chunk, fact chunk, and data chunk.
+    let header_len = 12 + 36 + 48 + 12 + 8;
+    assert_eq!(&out.header[..4], b"RF64");
+    assert_eq!(out.read_le_u32(4), 0xffff_ffff);
+    assert_eq!(&out.header[12..16], b"ds64");
+    assert_eq!(out.read_le_u32(16), 28);
+    assert_eq!(out.read_le_u64(20), data_len + header_len - 8);
+    assert_eq!(out.read_le_u64(28), data_len);
+    assert_eq!(out.read_le_u64(36), data_len / 4);
+    assert_eq!(out.read_le_u32(44), 0);
+    assert_eq!(&out.header[48..52], b"fmt ");
+    assert_eq!(&out.header[96..100], b"fact");
+    assert_eq!(out.read_le_u32(104), 0xffff_ffff);
+    assert_eq!(&out.header[108..112], b"data");
+    assert_eq!(out.read_le_u32(112), 0xffff_ffff);
+
+    // The reader takes the sizes from the ds64 chunk.
+    let reader = crate::read::WavReader::new(&out.header[..header_len as usize]).unwrap();
+    assert_eq!(reader.spec(), spec);
+    assert_eq!(reader.num_samples(), data_len / 4);
+}
+
+#[test]
+#[cfg(feature = "rf64")]
+fn rf64_writer_with_small_data_writes_riff() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer, spec).unwrap();
+        writer.set_size_limit(SizeLimit::Rf64).unwrap();
+        writer.reserve_junk(10).unwrap();
+        writer.write_samples(&[1i16, -1, 2, -2]).unwrap();
+        writer.finalize().unwrap();
+    }
+
+    assert_eq!(&buffer.get_ref()[..4], b"RIFF");
+    buffer.set_position(0);
+    let mut reader = WavReader::new(&mut buffer).unwrap();
+    let ids: Vec<&[u8; 4]> = reader.file_map().iter().map(|c| &c.id).collect();
+    assert_eq!(&ids[..], &[b"JUNK", b"fmt ", b"JUNK", b"data"]);
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1, -1, 2, -2]);
+}
+
+#[test]
+fn set_channel_mask_overrides_the_default_mask() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    assert_eq!(writer.channel_mask(), None);
+    writer.set_channel_mask(ChannelMask(0)).unwrap();
+    assert_eq!(writer.channel_mask(), Some(ChannelMask(0)));
+    writer.write_samples(&[1i16, -1]).unwrap();
+    let bytes = writer.finalize().unwrap();
+    let mut reader = WavReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.channel_mask(), Some(ChannelMask(0)));
+    assert_eq!(reader.spec(), spec);
+    assert_eq!(reader.samples::<i16>().map(|s| s.unwrap()).collect::<Vec<_>>(), vec![1, -1]);
+
+    // Front left, front right, and low frequency.
+    let spec = WavSpec { channels: 3, ..spec };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    assert_eq!(writer.channel_mask(), Some(ChannelMask(0x7)));
+    writer.set_channel_mask(ChannelMask(0xb)).unwrap();
+    let bytes = writer.finalize().unwrap();
+    let reader = WavReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.channel_mask().unwrap().speaker_names(), ["FL", "FR", "LFE"]);
+
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    match writer.set_channel_mask(ChannelMask(0xf)) {
+        Err(Error::Unsupported) => {}
+        other => panic!("expected Unsupported, got {:?}", other),
+    }
+}
+
+#[test]
+fn roll_over_to_finalizes_and_keeps_settings() {
+    use std::io::Read;
+    use crate::read::WavReader;
+
+    let spec_a = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let spec_b = WavSpec {
+        channels: 1,
+        sample_rate: 48000,
+        bits_per_sample: 24,
+        sample_format: SampleFormat::Int,
+    };
+    let mut buffer_a = io::Cursor::new(Vec::new());
+    let mut buffer_b = io::Cursor::new(Vec::new());
+    let mut buffer_bad = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new(&mut buffer_a, spec_a).unwrap();
+        writer.set_clip_detection(ClipDetection::Count);
+        writer.reserve_junk(9).unwrap();
+        writer.write_chunk(b"iXML", b"<BWFXML/>").unwrap();
+        writer.write_samples(&[1i16, 32767]).unwrap();
+        assert_eq!(writer.clip_count(), 1);
+
+        // A spec that cannot be written leaves the writer as it was.
+        let bad_spec = WavSpec { channels: 0, ..spec_b };
+        assert!(writer.roll_over_to(&mut buffer_bad, bad_spec).is_err());
+        writer.write_samples(&[3i16, 4]).unwrap();
+
+        writer.roll_over_to(&mut buffer_b, spec_b).unwrap();
+        assert_eq!(writer.clip_count(), 0);
+        writer.write_samples(&[5i32, -8_388_608]).unwrap();
+        assert_eq!(writer.clip_count(), 1);
+        writer.finalize().unwrap();
+    }
+
+    buffer_a.set_position(0);
+    let mut reader = WavReader::new(&mut buffer_a).unwrap();
+    assert_eq!(reader.spec(), spec_a);
+    let samples: Vec<i16> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[1, 32767, 3, 4]);
+
+    buffer_b.set_position(0);
+    let mut reader = WavReader::new(&mut buffer_b).unwrap();
+    assert_eq!(reader.spec(), spec_b);
+    let junk = reader.file_map().iter().find(|c| &c.id == b"JUNK").cloned().unwrap();
+    assert_eq!(junk.len, 10);
+    let mut ixml = Vec::new();
+    reader.open_chunk(b"iXML").unwrap().unwrap().read_to_end(&mut ixml).unwrap();
+    assert_eq!(ixml, b"<BWFXML/>");
+    let samples: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+    assert_eq!(&samples[..], &[5, -8_388_608]);
+}
+
+#[test]
+fn write_read_20bit_in_wider_containers_is_lossless() {
+    use crate::read::WavReader;
+    use crate::WavSpecEx;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 96_000,
+        bits_per_sample: 20,
+        sample_format: SampleFormat::Int,
+    };
+    let samples = [-524_288, 524_287, -1, 1];
+
+    for &bytes_per_sample in &[3, 4] {
+        let spec_ex = WavSpecEx { spec, bytes_per_sample };
+        let mut buffer = io::Cursor::new(Vec::new());
+        {
+            let mut writer = WavWriter::new_with_spec_ex(&mut buffer, spec_ex).unwrap();
+            writer.write_samples(&samples).unwrap();
+            // Samples must fit in 20 bits, even though the container is wider.
+            match writer.write_sample(524_288) {
+                Err(Error::TooWide) => {}
+                _ => panic!("TooWide error should have been returned."),
+            }
+            writer.finalize().unwrap();
+        }
+
+        buffer.set_position(0);
+        let mut reader = WavReader::new(&mut buffer).unwrap();
+        assert_eq!(reader.spec_ex(), spec_ex);
+        {
+            let fmt = reader.fmt_chunk_bytes();
+            let mut cursor = io::Cursor::new(fmt);
+            assert_eq!(cursor.read_le_u16().unwrap(), 0xfffe);
+            cursor.set_position(14);
+            assert_eq!(cursor.read_le_u16().unwrap(), bytes_per_sample * 8);
+            cursor.set_position(18);
+            assert_eq!(cursor.read_le_u16().unwrap(), 20);
+        }
+        let read: Vec<i32> = reader.samples().map(|r| r.unwrap()).collect();
+        assert_eq!(&read[..], &samples[..]);
+    }
+
+    // The valid bits are the most significant bits, and padding is zero.
+    let spec_ex = WavSpecEx { spec, bytes_per_sample: 4 };
+    let mut buffer = io::Cursor::new(Vec::new());
+    {
+        let mut writer = WavWriter::new_with_spec_ex(&mut buffer, spec_ex).unwrap();
+        writer.write_samples(&[1i32, -1]).unwrap();
+        writer.finalize().unwrap();
+    }
+    let bytes = buffer.into_inner();
+    let data = &bytes[bytes.len() - 8..];
+    assert_eq!(data, &[0x00, 0x10, 0x00, 0x00, 0x00, 0xf0, 0xff, 0xff]);
+
+    // The container must hold the bits.
+    let spec_ex = WavSpecEx { spec, bytes_per_sample: 2 };
+    assert!(WavWriter::new_with_spec_ex(io::Cursor::new(Vec::new()), spec_ex).is_err());
+}
+
+#[test]
+fn write_samples_matches_write_sample_for_every_layout() {
+    for &bits in &[8, 12, 16, 20, 24, 32] {
+        for &endianness in &[Endianness::Little, Endianness::Big] {
+            let spec = WavSpec {
+                channels: 1,
+                sample_rate: 8000,
+                bits_per_sample: bits,
+                sample_format: SampleFormat::Int,
+            };
+            let max = ((1_i64 << (bits - 1)) - 1) as i32;
+            let samples = [0, 1, -1, max, -max - 1, max / 3];
+
+            let mut one_by_one = WavWriter::new_in_memory(spec).unwrap();
+            one_by_one.set_endianness(endianness).unwrap();
+            for &sample in &samples {
+                one_by_one.write_sample(sample).unwrap();
+            }
+            let mut sliced = WavWriter::new_in_memory(spec).unwrap();
+            sliced.set_endianness(endianness).unwrap();
+            sliced.write_samples(&samples).unwrap();
+
+            let one_by_one = one_by_one.finalize().unwrap();
+            assert_eq!(sliced.finalize().unwrap(), one_by_one);
+            let mut reader = crate::read::WavReader::new(&one_by_one[..]).unwrap();
+            let decoded: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+            assert_eq!(decoded, samples);
+        }
+    }
+}
+
+#[test]
+fn md5_chunk_covers_the_data_chunk() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 8000,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    writer.set_md5_chunk(true).unwrap();
+    writer.write_sample(1_i16).unwrap();
+    writer.write_raw_data(&[0xfe, 0xff]).unwrap();
+    {
+        let mut block = writer.get_i16_writer(1);
+        block.write_sample(3_i16);
+        block.flush().unwrap();
+    }
+    let mut bytes = writer.finalize().unwrap();
+
+    let md5 = [0x19, 0xa0, 0x41, 0xac, 0xe9, 0xb9, 0x37, 0x06, 0xc9, 0xce, 0x3a, 0x76, 0x5e, 0x62, 0x1e, 0x06];
+    assert_eq!(&bytes[50..58], b"MD5 \x10\x00\x00\x00");
+    assert_eq!(&bytes[58..], &md5[..]);
+    let mut reader = WavReader::new_seekable(io::Cursor::new(&bytes[..])).unwrap();
+    assert!(reader.consistency().unwrap().is_consistent());
+    assert_eq!(reader.verify_md5().unwrap(), Some(true));
+    assert_eq!(reader.samples::<i16>().map(|s| s.unwrap()).collect::<Vec<_>>(), vec![1, -2, 3]);
+
+    bytes[47] ^= 1;
+    let mut reader = WavReader::new_seekable(io::Cursor::new(&bytes[..])).unwrap();
+    reader.samples::<i16>().next().unwrap().unwrap();
+    assert_eq!(reader.verify_md5().unwrap(), Some(false));
+    assert_eq!(reader.samples::<i16>().next().unwrap().unwrap(), -2 ^ 0x100);
+
+    let plain = WavWriter::new_in_memory(spec).unwrap().finalize().unwrap();
+    let mut reader = WavReader::new_seekable(io::Cursor::new(&plain[..])).unwrap();
+    assert_eq!(reader.verify_md5().unwrap(), None);
+}
+
+#[test]
+fn md5_chunk_follows_padding_and_byte_order() {
+    use crate::read::WavReader;
+
+    for &endianness in &[Endianness::Little, Endianness::Big] {
+        for &bits in &[8, 24] {
+            let spec = WavSpec {
+                channels: 1,
+                sample_rate: 8000,
+                bits_per_sample: bits,
+                sample_format: SampleFormat::Int,
+            };
+            let mut writer = WavWriter::new_in_memory(spec).unwrap();
+            writer.set_endianness(endianness).unwrap();
+            writer.set_md5_chunk(true).unwrap();
+            writer.write_samples(&[-5, 7, 100]).unwrap();
+            let bytes = writer.finalize().unwrap();
+            // The odd-length data chunk is padded before the MD5 chunk.
+            let md5_offset = bytes.len() - 24;
+            assert_eq!(md5_offset % 2, 0);
+            assert_eq!(bytes[md5_offset - 1], 0);
+            assert_eq!(&bytes[md5_offset..md5_offset + 4], b"MD5 ");
+
+            let mut reader = WavReader::new_seekable(io::Cursor::new(&bytes[..])).unwrap();
+            assert!(reader.consistency().unwrap().is_consistent());
+            assert_eq!(reader.verify_md5().unwrap(), Some(true));
+            let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
+            assert_eq!(samples, vec![-5, 7, 100]);
+        }
+    }
+}
+
+#[test]
+fn flush_leaves_a_valid_file_for_the_samples_so_far() {
+    use std::env;
+    use crate::read::WavReader;
+
+    let path = env::temp_dir().join(format!("hound-flush-{}.wav", process::id()));
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 8000,
+        bits_per_sample: 8,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(&path, spec).unwrap();
+    writer.set_md5_chunk(true).unwrap();
+    writer.write_samples(&[1_i8, -2, 3]).unwrap();
+    writer.flush().unwrap();
+
+    // The odd data chunk is padded, and followed by the MD5 chunk so far.
+    let mut reader = WavReader::open(&path).unwrap();
+    assert!(reader.consistency().unwrap().is_consistent());
+    assert_eq!(reader.verify_md5().unwrap(), Some(true));
+    assert_eq!(reader.samples::<i8>().map(|s| s.unwrap()).collect::<Vec<_>>(), vec![1, -2, 3]);
+
+    writer.write_samples(&[-4_i8, 5]).unwrap();
+    writer.finalize().unwrap();
+    let mut reader = WavReader::open(&path).unwrap();
+    assert!(reader.consistency().unwrap().is_consistent());
+    assert_eq!(reader.verify_md5().unwrap(), Some(true));
+    assert_eq!(reader.samples::<i8>().map(|s| s.unwrap()).collect::<Vec<_>>(), vec![1, -2, 3, -4, 5]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn frames_are_counted_across_channels() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    writer.write_samples(&[1_i16, 2, 3]).unwrap();
+    assert_eq!(writer.frames_written(), 1);
+    writer.write_samples(&[4_i16, 5, 6]).unwrap();
+    assert_eq!(writer.frames_written(), 3);
+    let bytes = writer.finalize().unwrap();
+
+    let mut reader = WavReader::new(io::Cursor::new(&bytes[..])).unwrap();
+    assert_eq!(reader.num_frames(), 3);
+    assert_eq!(reader.num_samples(), 6);
+    assert_eq!(reader.frames_read(), 0);
+    reader.samples::<i16>().nth(2).unwrap().unwrap();
+    assert_eq!(reader.frames_read(), 1);
+    reader.seek_frame(2).unwrap();
+    assert_eq!(reader.frames_read(), 2);
+    assert_eq!(reader.samples::<i16>().next().unwrap().unwrap(), 5);
+}
+
+#[test]
+fn write_frame_stereo_interleaves_left_and_right() {
+    use crate::read::WavReader;
+
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    writer.write_frame_stereo(1_i16, -1).unwrap();
+    writer.write_frame_stereo(2_i16, -2).unwrap();
+    assert_eq!(writer.frames_written(), 2);
+    let bytes = writer.finalize().unwrap();
+
+    let mut reader = WavReader::new(io::Cursor::new(&bytes[..])).unwrap();
+    let samples: Vec<i16> = reader.samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, vec![1, -1, 2, -2]);
+
+    let mono = WavSpec { channels: 1, ..spec };
+    let mut writer = WavWriter::new_in_memory(mono).unwrap();
+    writer.write_frame_mono(7_i16).unwrap();
+    assert_eq!(writer.frames_written(), 1);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+fn write_frame_stereo_panics_for_a_mono_spec() {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::new_in_memory(spec).unwrap();
+    let _ = writer.write_frame_stereo(1_i16, 2);
+}
@@ -0,0 +1,105 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verifies that decoding does not allocate after the header has been read.
+//!
+//! The allocator is global, so this test lives in its own binary. Only the
+//! allocations of the thread that counts are counted, because the threads of
+//! the test harness may allocate at any time.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+thread_local! {
+    // A `Cell<bool>` needs no destructor, so accessing it does not allocate.
+    static COUNTING: Cell<bool> = Cell::new(false);
+}
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns whether the current thread counts its allocations.
+fn is_counting() -> bool {
+    COUNTING.try_with(|c| c.get()).unwrap_or(false)
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if is_counting() {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if is_counting() {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made by `f`.
+fn count_allocations<F: FnOnce()>(f: F) -> usize {
+    ALLOCATIONS.store(0, Ordering::SeqCst);
+    COUNTING.with(|c| c.set(true));
+    f();
+    COUNTING.with(|c| c.set(false));
+    ALLOCATIONS.load(Ordering::SeqCst)
+}
+
+#[test]
+fn decoding_does_not_allocate() {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 44100,
+        bits_per_sample: 24,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::new_in_memory(spec).unwrap();
+    for i in 0..4096 {
+        writer.write_sample(i * 1000).unwrap();
+    }
+    let bytes = writer.finalize().unwrap();
+
+    let mut reader = hound::WavReader::new(Cursor::new(&bytes[..])).unwrap();
+    let mut buffer = [0i32; 256];
+    let mut sum = 0i64;
+    let allocations = count_allocations(|| {
+        for sample in reader.samples::<i32>().take(512) {
+            sum += sample.unwrap() as i64;
+        }
+        for (_, _, sample) in reader.samples_enumerated::<i32>().take(512).map(|s| s.unwrap()) {
+            sum += sample as i64;
+        }
+        for sample in reader.channel_samples::<i32>(1).take(256) {
+            sum += sample.unwrap() as i64;
+        }
+        sum += reader.samples_unchecked::<i32>().take(512).map(|s| s as i64).sum::<i64>();
+        reader.read_samples(&mut buffer).unwrap();
+        reader.read_exact_frames(128, &mut buffer).unwrap();
+        reader.seek_frame(100).unwrap();
+        reader.skip_samples(3).unwrap();
+        sum += reader.samples::<i32>().map(|s| s.unwrap() as i64).sum::<i64>();
+    });
+    assert!(sum > 0);
+    assert_eq!(allocations, 0);
+}
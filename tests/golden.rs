@@ -0,0 +1,70 @@
+// Hound -- A wav encoding and decoding library in Rust
+// Copyright (C) 2015 Ruud van Asseldonk
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// A copy of the License has been included in the root of the repository.
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decodes every file in the testsamples corpus and compares the result
+//! with the golden file next to it.
+//!
+//! For `testsamples/x/name.wav`, the golden file is `testsamples/x/name.json`,
+//! as produced by `hound::dump_json()`. To add a case, add the wav file and
+//! run the tests with `HOUND_BLESS=1`, which writes missing and outdated
+//! golden files instead of failing. Review the diff before committing it.
+//!
+//! Float files decode differently without the `float` feature, so the
+//! golden files assume that it is enabled.
+
+#![cfg(feature = "float")]
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+fn collect_wav_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_wav_files(&path, files);
+        } else if path.extension().map_or(false, |ext| ext == "wav") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn decoding_matches_golden_files() {
+    let bless = env::var_os("HOUND_BLESS").is_some();
+    let mut files = Vec::new();
+    collect_wav_files(Path::new("testsamples"), &mut files);
+    files.sort();
+    assert!(!files.is_empty());
+
+    let mut failures = Vec::new();
+    for wav in &files {
+        let golden = wav.with_extension("json");
+        let actual = hound::dump_json(Cursor::new(fs::read(wav).unwrap()));
+        let expected = fs::read_to_string(&golden).ok();
+        if expected.as_ref() == Some(&actual) {
+            continue;
+        }
+        if bless {
+            fs::write(&golden, &actual).unwrap();
+        } else if expected.is_none() {
+            failures.push(format!("{}: no golden file", wav.display()));
+        } else {
+            failures.push(format!("{}: decoding differs from golden file, got:\n{}",
+                                  wav.display(), actual));
+        }
+    }
+    assert!(failures.is_empty(),
+            "{}\nRun with HOUND_BLESS=1 to update the golden files.",
+            failures.join("\n"));
+}